@@ -0,0 +1,33 @@
+//! Compare a Cylon with the NaiveMatcher for the same rules. Each line of
+//! the input is a rule, starting with `+` to allow or anything else to
+//! disallow, except the last line which is the path. Run with
+//! `cargo fuzz run differential` from the repository root.
+#![no_main]
+
+use cylon::CylonBuilder;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(data) = std::str::from_utf8(data) else {
+        return;
+    };
+    let mut lines: Vec<&str> = data.split('\n').collect();
+    let path = lines.pop().unwrap_or("");
+
+    let mut builder = CylonBuilder::new();
+    for line in lines {
+        builder = match line.strip_prefix('+') {
+            Some(pattern) => builder.allow(pattern),
+            None => builder.disallow(line.get(1..).unwrap_or("")),
+        };
+    }
+    let (Ok(cylon), Ok(naive)) = (builder.build(), builder.build_naive()) else {
+        return;
+    };
+
+    // Check every prefix too, since they exercise different states.
+    for (i, _) in path.char_indices().chain([(path.len(), ' ')]) {
+        let path = &path[..i];
+        assert_eq!(naive.allow(path), cylon.allow(path), "{:?}", path);
+    }
+});
@@ -0,0 +1,67 @@
+use std::io::{self, Read};
+use std::process::ExitCode;
+
+use cylon::{Compiler, Cylon};
+
+const USAGE: &str = "\
+Usage:
+    cylon dot <FILE> [USER_AGENT]
+        Print the compiled rules as a Graphviz graph.
+    cylon check <FILE> <USER_AGENT> <PATH>...
+        Print whether each path is allowed. Exits with 1 if any is not.
+
+FILE may be - to read standard input. USER_AGENT defaults to *.";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let result = match args[..] {
+        ["dot", file] => dot(file, "*"),
+        ["dot", file, user_agent] => dot(file, user_agent),
+        ["check", file, user_agent, ref paths @ ..] if !paths.is_empty() => {
+            check(file, user_agent, paths)
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    result.unwrap_or_else(|e| {
+        eprintln!("cylon: {}", e);
+        ExitCode::from(2)
+    })
+}
+
+fn dot(file: &str, user_agent: &str) -> io::Result<ExitCode> {
+    print!("{}", compile(file, user_agent)?.to_dot());
+    Ok(ExitCode::SUCCESS)
+}
+
+fn check(file: &str, user_agent: &str, paths: &[&str]) -> io::Result<ExitCode> {
+    let cylon = compile(file, user_agent)?;
+    let mut code = ExitCode::SUCCESS;
+    for path in paths {
+        if cylon.allow(path) {
+            println!("allow\t{}", path);
+        } else {
+            println!("disallow\t{}", path);
+            code = ExitCode::from(1);
+        }
+    }
+    Ok(code)
+}
+
+fn compile(file: &str, user_agent: &str) -> io::Result<Cylon> {
+    let input = if file == "-" {
+        let mut input = vec![];
+        io::stdin().read_to_end(&mut input)?;
+        input
+    } else {
+        std::fs::read(file)?
+    };
+    Ok(futures_executor::block_on(
+        Compiler::new(user_agent).compile(&input[..]),
+    )?)
+}
@@ -0,0 +1,360 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::Duration;
+
+const MAGIC: &[u8; 4] = b"CYLN";
+const HEADER_LEN: usize = 16;
+
+/// The version of the binary format written by this build. It is bumped
+/// whenever the layout changes, and other versions are rejected rather
+/// than guessed at.
+pub(crate) const VERSION: u16 = 1;
+
+/// Set when the data includes the optional crawl-delay fields.
+pub(crate) const FLAG_CRAWL_DELAY: u16 = 1;
+
+/// The feature flags this build can read.
+const SUPPORTED_FLAGS: u16 = if cfg!(feature = "crawl-delay") {
+    FLAG_CRAWL_DELAY
+} else {
+    0
+};
+
+/// Why bytes given to [`Cylon::from_bytes`](crate::Cylon::from_bytes)
+/// were rejected, as the cause of an [`Error::Decode`](crate::Error::Decode).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The data does not start with the Cylon magic bytes.
+    BadMagic,
+    /// The data was written in a format version this build cannot read.
+    UnsupportedVersion(u16),
+    /// The data uses features this build was compiled without, e.g.
+    /// crawl delays written by a build with the `crawl-delay` feature.
+    UnsupportedFeatures(u16),
+    /// The data is shorter than its header claims.
+    Truncated,
+    /// The checksum does not match the data.
+    ChecksumMismatch,
+    /// The data passed the checksum but is not a valid Cylon.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::BadMagic => write!(f, "not a compiled cylon"),
+            DecodeError::UnsupportedVersion(v) => {
+                write!(f, "unsupported format version {} (expected {})", v, VERSION)
+            }
+            DecodeError::UnsupportedFeatures(flags) => {
+                write!(f, "unsupported feature flags {:#06x}", flags)
+            }
+            DecodeError::Truncated => write!(f, "data is truncated"),
+            DecodeError::ChecksumMismatch => write!(f, "checksum mismatch"),
+            DecodeError::Invalid(reason) => write!(f, "invalid data: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// 32-bit FNV-1a, which is plenty to detect corruption and truncation.
+fn checksum(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c_9dc5, |hash, b| {
+        (hash ^ u32::from(*b)).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Writes the little-endian payload of the binary format.
+#[derive(Default)]
+pub(crate) struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub(crate) fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub(crate) fn u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    /// Write a length, count or state number. Panics if it doesn't fit
+    /// in 32 bits, rather than writing a corrupt value.
+    pub(crate) fn len(&mut self, v: usize) {
+        self.u32(to_u32(v));
+    }
+
+    pub(crate) fn bytes(&mut self, v: &[u8]) {
+        self.buf.extend_from_slice(v);
+    }
+
+    pub(crate) fn str(&mut self, v: &str) {
+        self.len(v.len());
+        self.bytes(v.as_bytes());
+    }
+
+    pub(crate) fn option<T>(&mut self, v: Option<T>, f: impl FnOnce(&mut Self, T)) {
+        match v {
+            Some(v) => {
+                self.u8(1);
+                f(self, v);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    pub(crate) fn duration(&mut self, v: Duration) {
+        self.u64(v.as_secs());
+        self.u32(v.subsec_nanos());
+    }
+
+    /// Prepend the header: magic, version, flags, payload length and
+    /// checksum.
+    pub(crate) fn finish(self, flags: u16) -> Vec<u8> {
+        self.finish_as(MAGIC, flags)
+    }
+
+    /// Like finish, but with the magic bytes of another file type that
+    /// shares the header layout.
+    pub(crate) fn finish_as(self, magic: &[u8; 4], flags: u16) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.buf.len());
+        out.extend_from_slice(magic);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&flags.to_le_bytes());
+        out.extend_from_slice(&to_u32(self.buf.len()).to_le_bytes());
+        out.extend_from_slice(&checksum(&self.buf).to_le_bytes());
+        out.extend_from_slice(&self.buf);
+        out
+    }
+}
+
+fn to_u32(v: usize) -> u32 {
+    u32::try_from(v).expect("value is too large for the binary format")
+}
+
+/// Reads the payload of the binary format written by a Writer.
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Read from part of a payload that was already checked by open.
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    /// Check the header and return a reader over the payload along with
+    /// the feature flags it was written with.
+    pub(crate) fn open(data: &'a [u8]) -> Result<(Self, u16), DecodeError> {
+        let (r, flags, rest) = Self::open_prefix(data, MAGIC, SUPPORTED_FLAGS)?;
+        if !rest.is_empty() {
+            return Err(DecodeError::Invalid("trailing bytes"));
+        }
+        Ok((r, flags))
+    }
+
+    /// Check a header with the given magic bytes and return a reader over
+    /// its payload, the flags and whatever follows the payload. Only the
+    /// payload is checksummed.
+    pub(crate) fn open_prefix(
+        data: &'a [u8],
+        magic: &[u8; 4],
+        supported_flags: u16,
+    ) -> Result<(Self, u16, &'a [u8]), DecodeError> {
+        if data.len() < magic.len() || &data[..magic.len()] != magic {
+            return Err(DecodeError::BadMagic);
+        }
+        if data.len() < HEADER_LEN {
+            return Err(DecodeError::Truncated);
+        }
+
+        let mut header = Reader::new(&data[MAGIC.len()..HEADER_LEN]);
+        let version = header.u16()?;
+        let flags = header.u16()?;
+        let len = header.u32()? as usize;
+        let sum = header.u32()?;
+
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        if flags & !supported_flags != 0 {
+            return Err(DecodeError::UnsupportedFeatures(flags));
+        }
+        let rest = &data[HEADER_LEN..];
+        if rest.len() < len {
+            return Err(DecodeError::Truncated);
+        }
+        let (payload, rest) = rest.split_at(len);
+        if checksum(payload) != sum {
+            return Err(DecodeError::ChecksumMismatch);
+        }
+        Ok((Reader::new(payload), flags, rest))
+    }
+
+    pub(crate) fn bytes(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if self.buf.len() < n {
+            return Err(DecodeError::Truncated);
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, DecodeError> {
+        let mut b = [0; 2];
+        b.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(b))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, DecodeError> {
+        let mut b = [0; 4];
+        b.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(b))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, DecodeError> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+
+    /// Read a count of items that each take at least `size` bytes, so a
+    /// corrupt count can't cause a huge allocation.
+    pub(crate) fn len(&mut self, size: usize) -> Result<usize, DecodeError> {
+        let len = self.u32()? as usize;
+        if len.saturating_mul(size) > self.buf.len() {
+            return Err(DecodeError::Truncated);
+        }
+        Ok(len)
+    }
+
+    pub(crate) fn str(&mut self) -> Result<&'a str, DecodeError> {
+        let len = self.len(1)?;
+        std::str::from_utf8(self.bytes(len)?)
+            .map_err(|_| DecodeError::Invalid("string is not utf-8"))
+    }
+
+    pub(crate) fn option<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, DecodeError>,
+    ) -> Result<Option<T>, DecodeError> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => f(self).map(Some),
+            _ => Err(DecodeError::Invalid("bad option tag")),
+        }
+    }
+
+    pub(crate) fn duration(&mut self) -> Result<Duration, DecodeError> {
+        let secs = self.u64()?;
+        let nanos = self.u32()?;
+        if nanos >= 1_000_000_000 {
+            return Err(DecodeError::Invalid("bad duration"));
+        }
+        Ok(Duration::new(secs, nanos))
+    }
+
+    /// Ensure the whole payload was consumed.
+    pub(crate) fn finish(self) -> Result<(), DecodeError> {
+        if self.buf.is_empty() {
+            Ok(())
+        } else {
+            Err(DecodeError::Invalid("trailing bytes"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut w = Writer::default();
+        w.u8(7);
+        w.u32(70_000);
+        w.str("ツ");
+        w.option(Some(3u8), |w, v| w.u8(v));
+        w.option(None::<u8>, |w, v| w.u8(v));
+        let data = w.finish(0);
+
+        let (mut r, flags) = Reader::open(&data).unwrap();
+        assert_eq!(0, flags);
+        assert_eq!(7, r.u8().unwrap());
+        assert_eq!(70_000, r.u32().unwrap());
+        assert_eq!("ツ", r.str().unwrap());
+        assert_eq!(Some(3), r.option(|r| r.u8()).unwrap());
+        assert_eq!(None, r.option(|r| r.u8()).unwrap());
+        r.finish().unwrap();
+    }
+
+    #[test]
+    fn test_open_rejects() {
+        let mut w = Writer::default();
+        w.str("hello");
+        let data = w.finish(0);
+
+        let mut corrupt = data.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        let mut version = data.clone();
+        version[4] = 99;
+        let mut flags = data.clone();
+        flags[7] = 0x80;
+        let mut trailing = data.clone();
+        trailing.push(0);
+
+        let test_cases = vec![
+            (&b"{\"states\":[]}"[..], DecodeError::BadMagic),
+            (&data[..10], DecodeError::Truncated),
+            (&data[..data.len() - 1], DecodeError::Truncated),
+            (&corrupt, DecodeError::ChecksumMismatch),
+            (&version, DecodeError::UnsupportedVersion(99)),
+            (&flags, DecodeError::UnsupportedFeatures(0x8000)),
+            (&trailing, DecodeError::Invalid("trailing bytes")),
+        ];
+
+        for (i, o) in test_cases {
+            assert_eq!(Some(o), Reader::open(i).err());
+        }
+    }
+
+    #[test]
+    fn test_unsupported_versions_rejected() {
+        let data = Writer::default().finish(0);
+        for version in [0, VERSION + 1] {
+            let mut old = data.clone();
+            old[4..6].copy_from_slice(&version.to_le_bytes());
+            assert_eq!(
+                Some(DecodeError::UnsupportedVersion(version)),
+                Reader::open(&old).err()
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    #[should_panic(expected = "too large for the binary format")]
+    fn test_len_overflow() {
+        Writer::default().len(u32::MAX as usize + 1);
+    }
+
+    #[test]
+    fn test_len_bounded() {
+        let mut w = Writer::default();
+        w.u32(u32::MAX);
+        let data = w.finish(0);
+
+        let (mut r, _) = Reader::open(&data).unwrap();
+        assert_eq!(Err(DecodeError::Truncated), r.len(1));
+    }
+}
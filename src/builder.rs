@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::error::Error;
+use crate::naive::NaiveMatcher;
+use crate::nfa::{Cylon, Rule};
+use crate::parse::escape_pattern;
+
+/// Why a pattern given to a CylonBuilder was rejected, as the cause of an
+/// [`Error::Pattern`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternError {
+    /// The pattern is empty.
+    Empty,
+    /// The pattern does not start with `/` or `*`.
+    MissingLeadingSlash(String),
+    /// The pattern contains whitespace, a control character or `#`, which
+    /// cannot be written in a robots.txt file.
+    InvalidCharacter(String, char),
+    /// The pattern contains a `$` anywhere but at the end.
+    MisplacedEndAnchor(String),
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatternError::Empty => write!(f, "pattern is empty"),
+            PatternError::MissingLeadingSlash(p) => {
+                write!(f, "pattern {:?} must start with '/' or '*'", p)
+            }
+            PatternError::InvalidCharacter(p, c) => {
+                write!(f, "pattern {:?} contains invalid character {:?}", p, c)
+            }
+            PatternError::MisplacedEndAnchor(p) => {
+                write!(f, "pattern {:?} may only contain '$' at the end", p)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+/// Build a Cylon from rules given in code rather than from a robots.txt
+/// file. Patterns use the same syntax as robots.txt rules, including the
+/// `*` and `$` special characters, and are percent-encoded the same way,
+/// but unlike the Compiler, malformed patterns are reported as errors
+/// instead of being tolerated.
+///
+/// ```
+/// use cylon::CylonBuilder;
+///
+/// let cylon = CylonBuilder::new()
+///     .allow("/a")
+///     .disallow("/b*")
+///     .build()
+///     .unwrap();
+///
+/// assert!(cylon.allow("/a"));
+/// assert!(!cylon.allow("/bc"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CylonBuilder {
+    rules: Vec<(bool, String)>,
+}
+
+impl CylonBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow paths matching the pattern.
+    pub fn allow(mut self, pattern: &str) -> Self {
+        self.rules.push((true, pattern.into()));
+        self
+    }
+
+    /// Disallow paths matching the pattern.
+    pub fn disallow(mut self, pattern: &str) -> Self {
+        self.rules.push((false, pattern.into()));
+        self
+    }
+
+    /// Validate every pattern and compile the rules. Rules are matched with
+    /// the same precedence as in a robots.txt file: the longest matching
+    /// pattern wins, and allow wins ties.
+    pub fn build(&self) -> Result<Cylon, Error> {
+        for (_, pattern) in &self.rules {
+            validate(pattern)?;
+        }
+
+        let rules = self.escaped_rules();
+        let mut dedupe = BTreeMap::new();
+        for (allow, pattern) in &rules {
+            let rule = if *allow {
+                Rule::Allow(pattern.as_bytes())
+            } else {
+                Rule::Disallow(pattern.as_bytes())
+            };
+            if *allow || !dedupe.contains_key(pattern) {
+                dedupe.insert(pattern, rule);
+            }
+        }
+        Ok(Cylon::compile(dedupe.into_values().collect()))
+    }
+
+    /// Validate every pattern and return a NaiveMatcher for the rules. It
+    /// makes the same decisions as the Cylon returned by `build`, only much
+    /// more slowly, and is meant for testing.
+    pub fn build_naive(&self) -> Result<NaiveMatcher, Error> {
+        for (_, pattern) in &self.rules {
+            validate(pattern)?;
+        }
+        Ok(NaiveMatcher::new(self.escaped_rules()))
+    }
+
+    /// The rules with their patterns percent-encoded like the Compiler
+    /// encodes the patterns in a robots.txt file.
+    fn escaped_rules(&self) -> Vec<(bool, String)> {
+        self.rules
+            .iter()
+            .map(|(allow, pattern)| (*allow, escape_pattern(pattern).into_owned()))
+            .collect()
+    }
+}
+
+fn validate(pattern: &str) -> Result<(), PatternError> {
+    if pattern.is_empty() {
+        return Err(PatternError::Empty);
+    }
+    if !pattern.starts_with('/') && !pattern.starts_with('*') {
+        return Err(PatternError::MissingLeadingSlash(pattern.into()));
+    }
+    if let Some(c) = pattern
+        .chars()
+        .find(|c| c.is_whitespace() || c.is_control() || *c == '#')
+    {
+        return Err(PatternError::InvalidCharacter(pattern.into(), c));
+    }
+    if pattern.trim_end_matches('$').contains('$') || pattern.ends_with("$$") {
+        return Err(PatternError::MisplacedEndAnchor(pattern.into()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Compiler;
+
+    #[test]
+    fn test_validate() {
+        let test_cases = vec![
+            ("/", Ok(())),
+            ("*.php", Ok(())),
+            ("/a/*/b$", Ok(())),
+            ("/ツ", Ok(())),
+            ("", Err(PatternError::Empty)),
+            ("a", Err(PatternError::MissingLeadingSlash("a".into()))),
+            (
+                "/a b",
+                Err(PatternError::InvalidCharacter("/a b".into(), ' ')),
+            ),
+            (
+                "/a#b",
+                Err(PatternError::InvalidCharacter("/a#b".into(), '#')),
+            ),
+            (
+                "/a\n",
+                Err(PatternError::InvalidCharacter("/a\n".into(), '\n')),
+            ),
+            ("/a$b", Err(PatternError::MisplacedEndAnchor("/a$b".into()))),
+            ("/a$$", Err(PatternError::MisplacedEndAnchor("/a$$".into()))),
+        ];
+
+        for (i, o) in test_cases {
+            assert_eq!(validate(i), o, "{}", i);
+        }
+    }
+
+    #[test]
+    fn test_build() {
+        let cylon = CylonBuilder::new()
+            .disallow("/")
+            .allow("/public")
+            .disallow("/public/*.tmp$")
+            .disallow("/shared")
+            .allow("/shared")
+            .build()
+            .unwrap();
+
+        assert!(!cylon.allow("/"));
+        assert!(!cylon.allow("/index.html"));
+        assert!(cylon.allow("/public/index.html"));
+        assert!(!cylon.allow("/public/a/b.tmp"));
+        assert!(cylon.allow("/public/a/b.tmp.html"));
+        assert!(cylon.allow("/shared/file"));
+    }
+
+    #[test]
+    fn test_build_like_compiler() {
+        let builder = CylonBuilder::new().disallow("/ツ").allow("/ツ/%e3%83%84$");
+        let file = "User-agent: *\nDisallow: /ツ\nAllow: /ツ/%e3%83%84$\n";
+        let compiler = Compiler::new("foobot");
+        let expect = tokio_test::block_on(compiler.compile(file.as_bytes())).unwrap();
+
+        let cylon = builder.build().unwrap();
+        let naive = builder.build_naive().unwrap();
+        let test_cases = vec![
+            "/%E3%83%84",
+            "/%E3%83%84/a",
+            "/%E3%83%84/%E3%83%84",
+            "/%e3%83%84",
+            "/a",
+        ];
+        for path in test_cases {
+            assert_eq!(expect.allow(path), cylon.allow(path), "{}", path);
+            assert_eq!(expect.allow(path), naive.allow(path), "{}", path);
+        }
+        assert!(!cylon.allow("/%E3%83%84"));
+        assert!(cylon.allow("/%E3%83%84/%E3%83%84"));
+    }
+
+    #[test]
+    fn test_build_invalid() {
+        let result = CylonBuilder::new().allow("/").disallow("private").build();
+        assert!(matches!(
+            result,
+            Err(Error::Pattern(PatternError::MissingLeadingSlash(p))) if p == "private"
+        ));
+    }
+}
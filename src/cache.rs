@@ -0,0 +1,255 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::error::Error;
+use crate::nfa::Cylon;
+use crate::parse::{self, Compiler};
+use crate::sniff::{self, ContentKind};
+
+type Key = (Compiler, ContentKind, u128);
+
+/// Interns compiled Cylons so that byte-identical robots.txt files, as
+/// served by many hosts running the same CMS, are compiled once and share
+/// memory.
+///
+/// Files are keyed on a hash of their significant lines, so files that
+/// differ only in comments, blank lines or whitespace are also shared,
+/// along with the Compiler configuration. The size limit and encoding are
+/// still checked against the whole file. The cache holds weak references:
+/// a Cylon is shared for as long as any handle to it is alive.
+///
+/// ```
+/// use std::sync::Arc;
+/// use cylon::{CompileCache, Compiler};
+///
+/// # tokio_test::block_on(async {
+/// let cache = CompileCache::new();
+/// let compiler = Compiler::new("googlebot");
+/// let a = cache.compile(&compiler, b"User-agent: *\nDisallow: /a").await.unwrap();
+/// let b = cache.compile(&compiler, b"User-agent: *\nDisallow: /a # cms").await.unwrap();
+/// assert!(Arc::ptr_eq(&a, &b));
+/// # });
+/// ```
+#[derive(Debug, Default)]
+pub struct CompileCache {
+    // Two independently seeded hashers give a 128-bit key that a site
+    // can't deliberately collide with another site's file.
+    hashers: (RandomState, RandomState),
+    entries: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    cylons: HashMap<Key, Weak<Cylon>>,
+    prune_at: usize,
+}
+
+impl CompileCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compile the file with the given compiler, or return the shared
+    /// Cylon if an equivalent file was already compiled with an equal
+    /// compiler and is still in use.
+    pub async fn compile(&self, compiler: &Compiler, file: &[u8]) -> Result<Arc<Cylon>, Error> {
+        self.compile_with_content_type(compiler, file, None).await
+    }
+
+    /// Like [`CompileCache::compile`], for a file served with the given
+    /// `Content-Type`. Files are only shared if they are the same kind of
+    /// content.
+    pub async fn compile_with_content_type(
+        &self,
+        compiler: &Compiler,
+        file: &[u8],
+        content_type: Option<&str>,
+    ) -> Result<Arc<Cylon>, Error> {
+        // The key ignores comments and blank lines, so a file over the
+        // size limit could otherwise be served the Cylon of a smaller one.
+        if let Some(limit) = compiler.max_size() {
+            if file.len() > limit {
+                return Err(Error::SizeLimitExceeded { limit });
+            }
+        }
+        if let Some(encoding) = sniff::unsupported_encoding(file) {
+            return Err(Error::Encoding(encoding));
+        }
+
+        let compile = || compiler.compile_with_content_type(file, content_type);
+        let key = match std::str::from_utf8(file) {
+            Ok(text) => {
+                let kind = ContentKind::sniff(file, content_type);
+                (compiler.clone(), kind, self.hash(text))
+            }
+            // The compiler percent-encodes invalid bytes, which the
+            // significant lines can't represent.
+            Err(_) => return compile().await.map(Arc::new),
+        };
+
+        if let Some(cylon) = self.lock().cylons.get(&key).and_then(Weak::upgrade) {
+            return Ok(cylon);
+        }
+        let cylon = Arc::new(compile().await?);
+        Ok(self.insert(key, cylon))
+    }
+
+    /// The number of distinct Cylons that are still in use.
+    pub fn len(&self) -> usize {
+        let entries = self.lock();
+        entries
+            .cylons
+            .values()
+            .filter(|c| c.strong_count() > 0)
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn hash(&self, file: &str) -> u128 {
+        let mut high = self.hashers.0.build_hasher();
+        let mut low = self.hashers.1.build_hasher();
+        for line in parse::significant_lines(file) {
+            line.hash(&mut high);
+            line.hash(&mut low);
+        }
+        (u128::from(high.finish()) << 64) | u128::from(low.finish())
+    }
+
+    fn insert(&self, key: Key, cylon: Arc<Cylon>) -> Arc<Cylon> {
+        let mut entries = self.lock();
+        // Another task may have compiled the same file in the meantime.
+        if let Some(existing) = entries.cylons.get(&key).and_then(Weak::upgrade) {
+            return existing;
+        }
+        entries.cylons.insert(key, Arc::downgrade(&cylon));
+
+        // Drop entries whose Cylons are no longer in use whenever the map
+        // doubles in size, so pruning is amortized over insertions.
+        if entries.cylons.len() >= entries.prune_at {
+            entries.cylons.retain(|_, c| c.strong_count() > 0);
+            entries.prune_at = 2 * entries.cylons.len().max(16);
+        }
+        cylon
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        // The entries are always left consistent, so a panic elsewhere
+        // while the lock was held doesn't matter.
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared() {
+        tokio_test::block_on(async {
+            let cache = CompileCache::new();
+            let compiler = Compiler::new("googlebot");
+            let file = b"User-agent: *\nDisallow: /a\n";
+
+            let a = cache.compile(&compiler, file).await.unwrap();
+            let test_cases: Vec<&[u8]> = vec![
+                b"User-agent: *\nDisallow: /a\n",
+                b"# Generated by a CMS\r\nUser-agent: *\r\n\r\n  Disallow: /a  \r\n",
+                b"User-agent: *\nDisallow: /a # private",
+            ];
+            for file in test_cases {
+                let b = cache.compile(&compiler, file).await.unwrap();
+                assert!(Arc::ptr_eq(&a, &b), "{:?}", std::str::from_utf8(file));
+            }
+            assert_eq!(1, cache.len());
+        });
+    }
+
+    #[test]
+    fn test_distinct() {
+        tokio_test::block_on(async {
+            let cache = CompileCache::new();
+            let googlebot = Compiler::new("googlebot");
+            let file = b"User-agent: *\nDisallow: /a\n";
+
+            let a = cache.compile(&googlebot, file).await.unwrap();
+            let b = cache
+                .compile(&Compiler::new("bingbot"), file)
+                .await
+                .unwrap();
+            let c = cache
+                .compile(&googlebot, b"User-agent: *\nDisallow: /b\n")
+                .await
+                .unwrap();
+            let d = cache
+                .compile(&googlebot, b"User-agent: *\nDisallow: /a\nDisallow: /b\n")
+                .await
+                .unwrap();
+            let e = cache
+                .compile_with_content_type(&googlebot, file, Some("image/png"))
+                .await
+                .unwrap();
+            assert!(!Arc::ptr_eq(&a, &b));
+            assert!(!Arc::ptr_eq(&a, &c));
+            assert!(!Arc::ptr_eq(&c, &d));
+            assert!(!Arc::ptr_eq(&a, &e));
+            assert_eq!(ContentKind::Binary, e.content_kind());
+            assert_eq!(5, cache.len());
+        });
+    }
+
+    #[test]
+    fn test_released() {
+        tokio_test::block_on(async {
+            let cache = CompileCache::new();
+            let compiler = Compiler::new("googlebot");
+
+            for i in 0..100 {
+                let file = format!("User-agent: *\nDisallow: /{}\n", i);
+                cache.compile(&compiler, file.as_bytes()).await.unwrap();
+            }
+            assert!(cache.is_empty());
+            assert!(cache.lock().cylons.len() < 100);
+
+            // Files that aren't valid UTF-8 are compiled but never shared.
+            let cylon = cache
+                .compile(&compiler, b"User-agent: *\nDisallow: /\xff")
+                .await
+                .unwrap();
+            assert!(!cylon.allow("/%FF"));
+            assert!(cache.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_limits() {
+        tokio_test::block_on(async {
+            let cache = CompileCache::new();
+            let compiler = Compiler::new("googlebot").with_max_size(32);
+            let file = b"User-agent: *\nDisallow: /a\n";
+            let _cylon = cache.compile(&compiler, file).await.unwrap();
+
+            // Only comments and blank lines take these over the limit, but
+            // they aren't served the Cylon of the smaller file.
+            let test_cases: Vec<&[u8]> = vec![
+                b"User-agent: *\nDisallow: /a # private\n",
+                b"User-agent: *\n\n\n\n\n\n\n\n\nDisallow: /a\n",
+            ];
+            for file in test_cases {
+                let result = cache.compile(&compiler, file).await;
+                assert!(
+                    matches!(result, Err(Error::SizeLimitExceeded { limit: 32 })),
+                    "{:?}",
+                    std::str::from_utf8(file)
+                );
+            }
+
+            let result = cache.compile(&compiler, b"\xfe\xff\0U").await;
+            assert!(matches!(result, Err(Error::Encoding("UTF-16BE"))));
+        });
+    }
+}
@@ -0,0 +1,158 @@
+use serde_derive::{Deserialize, Serialize};
+
+const WILDCARD: char = '*';
+
+/// A Yandex `Clean-param` directive, e.g. `Clean-param: ref&sid /forum/`.
+/// It declares that the listed query parameters do not affect the content
+/// of pages whose path starts with the given prefix, so URLs that differ
+/// only in those parameters are equivalent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CleanParam {
+    params: Vec<String>,
+    prefix: String,
+}
+
+impl CleanParam {
+    pub(crate) fn new(params: Vec<String>, prefix: String) -> Self {
+        Self { params, prefix }
+    }
+
+    /// The names of the query parameters that should be removed.
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
+
+    /// The path prefix this directive applies to. A `*` matches any
+    /// sequence of characters. Defaults to `/` (every path).
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// The bytes allocated on the heap for the params and prefix.
+    pub(crate) fn heap_size(&self) -> usize {
+        self.params.capacity() * std::mem::size_of::<String>()
+            + self.params.iter().map(String::capacity).sum::<usize>()
+            + self.prefix.capacity()
+    }
+
+    /// Whether this directive applies to the given path.
+    pub fn applies_to(&self, path: &str) -> bool {
+        prefix_matches(&self.prefix, path)
+    }
+
+    /// Parse a value like `ref&sid /forum/`. Returns None when no
+    /// parameters are listed.
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.split_whitespace();
+        let params: Vec<String> = parts
+            .next()?
+            .split('&')
+            .filter(|p| !p.is_empty())
+            .map(String::from)
+            .collect();
+        let prefix = parts.next().unwrap_or("/").into();
+        if params.is_empty() || parts.next().is_some() {
+            return None;
+        }
+        Some(Self { params, prefix })
+    }
+}
+
+/// Remove every query parameter that one of the clean params declares
+/// irrelevant for the path. The path and any fragment are kept as-is, and
+/// the remaining parameters keep their original order.
+pub(crate) fn canonicalize(clean_params: &[CleanParam], url: &str) -> String {
+    let (url, fragment) = match url.find('#') {
+        Some(i) => url.split_at(i),
+        None => (url, ""),
+    };
+    let (path, query) = match url.find('?') {
+        Some(i) => (&url[..i], &url[i + 1..]),
+        None => return [url, fragment].concat(),
+    };
+
+    let applicable: Vec<&CleanParam> = clean_params.iter().filter(|c| c.applies_to(path)).collect();
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| {
+            let key = pair.split('=').next().unwrap_or(pair);
+            !pair.is_empty() && !applicable.iter().any(|c| c.params.iter().any(|p| p == key))
+        })
+        .collect();
+
+    if kept.is_empty() {
+        [path, fragment].concat()
+    } else {
+        [path, "?", &kept.join("&"), fragment].concat()
+    }
+}
+
+fn prefix_matches(prefix: &str, path: &str) -> bool {
+    let mut pieces = prefix.split(WILDCARD);
+    let first = pieces.next().unwrap_or("");
+    if !path.starts_with(first) {
+        return false;
+    }
+
+    let mut rest = &path[first.len()..];
+    for piece in pieces {
+        match rest.find(piece) {
+            Some(i) => rest = &rest[i + piece.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_clean_param() {
+        let clean = CleanParam::parse("ref&sid /forum/").unwrap();
+        assert_eq!(&["ref", "sid"], clean.params());
+        assert_eq!("/forum/", clean.prefix());
+
+        let clean = CleanParam::parse("utm_source").unwrap();
+        assert_eq!(&["utm_source"], clean.params());
+        assert_eq!("/", clean.prefix());
+
+        assert_eq!(None, CleanParam::parse(""));
+        assert_eq!(None, CleanParam::parse("&"));
+        assert_eq!(None, CleanParam::parse("a /b /c"));
+    }
+
+    #[test]
+    fn test_prefix_matches() {
+        assert!(prefix_matches("/", "/index.php"));
+        assert!(prefix_matches("/forum/", "/forum/showthread.php"));
+        assert!(!prefix_matches("/forum/", "/blog/"));
+        assert!(prefix_matches("/*/show", "/forum/showthread.php"));
+        assert!(!prefix_matches("/*/show", "/forum/list.php"));
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let clean_params = vec![
+            CleanParam::parse("sid&ref /forum/").unwrap(),
+            CleanParam::parse("utm_source").unwrap(),
+        ];
+
+        let test_cases = vec![
+            ("/forum/t.php?id=1&sid=abc&ref=x", "/forum/t.php?id=1"),
+            ("/forum/t.php?sid=abc", "/forum/t.php"),
+            ("/blog/p.php?id=1&sid=abc", "/blog/p.php?id=1&sid=abc"),
+            ("/blog/p.php?utm_source=x&id=1", "/blog/p.php?id=1"),
+            ("/blog/p.php?utm_source=x#top", "/blog/p.php#top"),
+            ("/blog/p.php#a?utm_source=x", "/blog/p.php#a?utm_source=x"),
+            ("/blog/p.php", "/blog/p.php"),
+            ("/blog/p.php?", "/blog/p.php"),
+            ("/blog/p.php?sidebar=1", "/blog/p.php?sidebar=1"),
+        ];
+
+        for (i, o) in test_cases {
+            assert_eq!(canonicalize(&clean_params, i), o, "{}", i);
+        }
+    }
+}
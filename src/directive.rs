@@ -0,0 +1,84 @@
+use serde_derive::{Deserialize, Serialize};
+
+/// Where in the robots.txt file a directive appeared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    /// The directive appeared before the first group.
+    Global,
+    /// The directive appeared in the group that matched the user agent.
+    Group,
+}
+
+/// A `key: value` line that Cylon does not interpret itself, such as
+/// `Content-Signal`, `DisallowAITraining`, `Noindex` or a vendor extension.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnknownDirective {
+    key: String,
+    value: String,
+    scope: Scope,
+}
+
+impl UnknownDirective {
+    pub(crate) fn new(key: String, value: String, scope: Scope) -> Self {
+        Self { key, value, scope }
+    }
+
+    /// The directive name, lowercased.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// The directive value with surrounding whitespace and comments removed.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Whether the directive is global or belongs to the matching group.
+    pub fn scope(&self) -> Scope {
+        self.scope
+    }
+
+    /// The bytes allocated on the heap for the key and value.
+    pub(crate) fn heap_size(&self) -> usize {
+        self.key.capacity() + self.value.capacity()
+    }
+}
+
+/// An extension hook for typed parsing of directives that Cylon does not
+/// understand natively. Implement this for your own type and query it with
+/// [`Cylon::directives`](crate::Cylon::directives).
+///
+/// ```
+/// use cylon::Directive;
+///
+/// struct ContentSignal {
+///     ai_train: bool,
+/// }
+///
+/// impl Directive for ContentSignal {
+///     const NAME: &'static str = "content-signal";
+///
+///     fn parse(value: &str) -> Option<Self> {
+///         let ai_train = value
+///             .split(',')
+///             .find_map(|kv| kv.trim().strip_prefix("ai-train="))?;
+///         Some(Self { ai_train: ai_train == "yes" })
+///     }
+/// }
+/// ```
+pub trait Directive: Sized {
+    /// The directive name to match. Names are matched case-insensitively.
+    const NAME: &'static str;
+
+    /// Parse the directive's value, returning None if it is invalid.
+    fn parse(value: &str) -> Option<Self>;
+}
+
+/// Whether a directive name is a plausible token, so that arbitrary prose
+/// containing a colon isn't recorded as a directive.
+pub(crate) fn is_directive_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
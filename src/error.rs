@@ -0,0 +1,115 @@
+use std::fmt;
+use std::io;
+
+use crate::binary::DecodeError;
+use crate::builder::PatternError;
+
+/// Why a robots.txt file could not be compiled, or a Cylon could not be
+/// built or decoded.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading the file failed.
+    Io(io::Error),
+    /// Compiled data could not be decoded.
+    Decode(DecodeError),
+    /// A CylonBuilder rule is not a valid pattern.
+    Pattern(PatternError),
+    /// The file is in a text encoding that can't be read as UTF-8, such as
+    /// UTF-16.
+    Encoding(&'static str),
+    /// The file is larger than the Compiler's size limit, in bytes.
+    SizeLimitExceeded { limit: usize },
+    /// The compiled Cylon has more states than the Compiler's limit.
+    StateLimitExceeded { limit: usize, states: usize },
+    /// The Compiler's options contradict each other.
+    InvalidConfiguration(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "failed to read file: {}", e),
+            Error::Decode(e) => write!(f, "failed to decode cylon: {}", e),
+            Error::Pattern(e) => write!(f, "invalid pattern: {}", e),
+            Error::Encoding(encoding) => write!(f, "file is encoded as {}, not UTF-8", encoding),
+            Error::SizeLimitExceeded { limit } => {
+                write!(f, "file is larger than the limit of {} bytes", limit)
+            }
+            Error::StateLimitExceeded { limit, states } => write!(
+                f,
+                "compiled to {} states, more than the limit of {}",
+                states, limit
+            ),
+            Error::InvalidConfiguration(reason) => write!(f, "invalid configuration: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Decode(e) => Some(e),
+            Error::Pattern(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(e: DecodeError) -> Self {
+        Error::Decode(e)
+    }
+}
+
+impl From<PatternError> for Error {
+    fn from(e: PatternError) -> Self {
+        Error::Pattern(e)
+    }
+}
+
+/// I/O errors are passed through as they are, so that code returning
+/// `io::Result` can still use `?` on a compile.
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_source() {
+        let error = Error::from(io::Error::new(io::ErrorKind::UnexpectedEof, "eof"));
+        assert_eq!("eof", error.source().unwrap().to_string());
+        let io_error = io::Error::from(error);
+        assert_eq!(io::ErrorKind::UnexpectedEof, io_error.kind());
+
+        let error = Error::from(DecodeError::Truncated);
+        assert_eq!("data is truncated", error.source().unwrap().to_string());
+
+        let error = Error::from(PatternError::Empty);
+        assert_eq!("pattern is empty", error.source().unwrap().to_string());
+
+        let error = Error::SizeLimitExceeded { limit: 10 };
+        assert!(error.source().is_none());
+        let io_error = io::Error::from(error);
+        assert_eq!(io::ErrorKind::InvalidData, io_error.kind());
+        assert!(io_error
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<Error>())
+            .is_some());
+    }
+}
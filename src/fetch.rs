@@ -0,0 +1,379 @@
+use std::future::Future;
+use std::io;
+
+use futures_util::io::{AsyncRead, AsyncReadExt};
+
+use crate::error::Error;
+use crate::nfa::Cylon;
+use crate::parse::Compiler;
+use crate::robots_cache::{FetchResponse, Fetcher};
+
+/// RFC 9309 requires following at least five consecutive redirects.
+const MAX_REDIRECTS: usize = 5;
+/// RFC 9309 requires parsing at least the first 500 KiB of a file.
+const DEFAULT_MAX_SIZE: usize = 500 * 1024;
+
+/// A response to a single HTTP request.
+#[derive(Debug)]
+pub struct HttpResponse<B> {
+    /// The HTTP status code.
+    pub status: u16,
+    /// The value of the `Location` header, if any.
+    pub location: Option<String>,
+    /// The value of the `Cache-Control` header, if any.
+    pub cache_control: Option<String>,
+    /// The value of the `Content-Type` header, if any.
+    pub content_type: Option<String>,
+    /// The response body, which is only read up to the size limit.
+    pub body: B,
+}
+
+/// A minimal async HTTP client. Like the rest of this library it doesn't
+/// assume any async runtime, so it can be implemented for any client.
+pub trait HttpClient {
+    type Body: AsyncRead + Unpin + Send;
+
+    /// Make a GET request for the URL without following redirects. Return
+    /// an error if the server could not be reached at all.
+    fn get(&self, url: &str) -> impl Future<Output = io::Result<HttpResponse<Self::Body>>> + Send;
+}
+
+/// Downloads robots.txt files with an HttpClient, following redirects and
+/// limiting their size as RFC 9309 describes. It can be used directly, or
+/// as the Fetcher of a [`RobotsCache`](crate::RobotsCache).
+#[derive(Debug, Clone)]
+pub struct RobotsFetcher<C> {
+    client: C,
+    max_size: usize,
+}
+
+impl<C: HttpClient + Sync> RobotsFetcher<C> {
+    pub fn new(client: C) -> Self {
+        Self {
+            client,
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+
+    /// Ignore anything after the first `max_size` bytes of a file.
+    /// Defaults to 500 KiB.
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Fetch `/robots.txt` from the origin, e.g. `https://example.com`,
+    /// following up to five redirects. If there are more, or a redirect has
+    /// no usable `Location`, the redirect response itself is returned, which
+    /// means the file is unavailable. Fails with [`Error::Io`] if the server
+    /// could not be reached or the body could not be read.
+    pub async fn fetch(&self, origin: &str) -> Result<FetchResponse, Error> {
+        let mut url = format!("{}/robots.txt", origin.trim_end_matches('/'));
+        let mut redirects = 0;
+        loop {
+            let response = self.client.get(&url).await?;
+            let next = match response.location {
+                Some(ref location) if (300..400).contains(&response.status) => {
+                    resolve(&url, location)
+                }
+                _ => None,
+            };
+            match next {
+                Some(next) if redirects < MAX_REDIRECTS => {
+                    url = next;
+                    redirects += 1;
+                    continue;
+                }
+                Some(_) => return Ok(without_body(response)),
+                None if (300..400).contains(&response.status) => return Ok(without_body(response)),
+                None => {}
+            }
+
+            let mut body = vec![];
+            response
+                .body
+                .take(self.max_size as u64)
+                .read_to_end(&mut body)
+                .await?;
+            return Ok(FetchResponse {
+                status: response.status,
+                body,
+                cache_control: response.cache_control,
+                content_type: response.content_type,
+            });
+        }
+    }
+
+    /// Fetch and compile the origin's robots.txt file. As RFC 9309
+    /// requires, everything is allowed if the file is unavailable and
+    /// disallowed if the server can't be reached. Fails if the compiler is
+    /// misconfigured or the file can't be compiled, e.g. because it is
+    /// larger than the compiler's size limit.
+    pub async fn compile(&self, compiler: &Compiler, origin: &str) -> Result<Cylon, Error> {
+        compiler.validate()?;
+        match self.fetch(origin).await {
+            Ok(response) if response.is_success() => {
+                let content_type = response.content_type.as_deref();
+                compiler
+                    .compile_with_content_type(&response.body[..], content_type)
+                    .await
+            }
+            Ok(response) if !response.is_unreachable() => Ok(Cylon::allow_all()),
+            _ => Ok(Cylon::disallow_all()),
+        }
+    }
+}
+
+impl<C: HttpClient + Sync> Fetcher for RobotsFetcher<C> {
+    async fn fetch(&self, origin: &str) -> Result<FetchResponse, Error> {
+        RobotsFetcher::fetch(self, origin).await
+    }
+}
+
+fn without_body<B>(response: HttpResponse<B>) -> FetchResponse {
+    FetchResponse {
+        status: response.status,
+        body: vec![],
+        cache_control: response.cache_control,
+        content_type: response.content_type,
+    }
+}
+
+/// Resolve a `Location` header against the URL that returned it.
+fn resolve(base: &str, location: &str) -> Option<String> {
+    let scheme_end = base.find("://")? + 3;
+    let is_absolute = location.find("://").is_some_and(|i| {
+        location[..i]
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'-' || b == b'.')
+    });
+    if is_absolute {
+        return Some(location.into());
+    }
+    if let Some(rest) = location.strip_prefix("//") {
+        return Some(format!("{}{}", &base[..scheme_end], rest));
+    }
+
+    let path_start = base[scheme_end..]
+        .find('/')
+        .map_or(base.len(), |i| scheme_end + i);
+    if location.starts_with('/') {
+        return Some(format!("{}{}", &base[..path_start], location));
+    }
+    let path_end = base.find(['?', '#']).unwrap_or(base.len());
+    match base[path_start..path_end].rfind('/') {
+        Some(i) => Some(format!("{}{}", &base[..path_start + i + 1], location)),
+        None => Some(format!("{}/{}", &base[..path_start], location)),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use super::*;
+    use crate::sniff::{ContentKind, ContentPolicy};
+    use std::collections::HashMap;
+
+    /// Serves canned responses by URL.
+    #[derive(Default)]
+    struct FakeClient {
+        responses: HashMap<String, (u16, Option<&'static str>, &'static str)>,
+    }
+
+    impl FakeClient {
+        fn respond(mut self, url: &str, status: u16, body: &'static str) -> Self {
+            self.responses.insert(url.into(), (status, None, body));
+            self
+        }
+
+        fn redirect(mut self, url: &str, status: u16, location: &'static str) -> Self {
+            self.responses
+                .insert(url.into(), (status, Some(location), ""));
+            self
+        }
+    }
+
+    impl HttpClient for FakeClient {
+        type Body = &'static [u8];
+
+        async fn get(&self, url: &str) -> io::Result<HttpResponse<Self::Body>> {
+            let (status, location, body) = self
+                .responses
+                .get(url)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::ConnectionRefused, url))?;
+            Ok(HttpResponse {
+                status: *status,
+                location: location.map(String::from),
+                cache_control: Some("max-age=60".into()),
+                content_type: Some("text/plain".into()),
+                body: body.as_bytes(),
+            })
+        }
+    }
+
+    const ROBOTS: &str = "User-agent: *\nDisallow: /private\n";
+    const SPA_SHELL: &str = "<!DOCTYPE html>\n<pre>\nUser-agent: *\nDisallow: /\n</pre>\n";
+
+    #[test]
+    fn test_resolve() {
+        let base = "https://a.com/x/robots.txt?q=1";
+        let test_cases = vec![
+            ("https://b.com/robots.txt", "https://b.com/robots.txt"),
+            ("//b.com/robots.txt", "https://b.com/robots.txt"),
+            ("/robots.txt", "https://a.com/robots.txt"),
+            ("other.txt", "https://a.com/x/other.txt"),
+            ("/next?a=http://c.com", "https://a.com/next?a=http://c.com"),
+        ];
+
+        for (i, o) in test_cases {
+            assert_eq!(Some(o.into()), resolve(base, i), "{}", i);
+        }
+        assert_eq!(
+            Some("https://a.com/b.txt".into()),
+            resolve("https://a.com", "b.txt")
+        );
+    }
+
+    #[test]
+    fn test_fetch() {
+        tokio_test::block_on(async {
+            let client = FakeClient::default().respond("https://a.com/robots.txt", 200, ROBOTS);
+            let fetcher = RobotsFetcher::new(client);
+
+            let response = fetcher.fetch("https://a.com/").await.unwrap();
+            assert_eq!(200, response.status);
+            assert_eq!(ROBOTS.as_bytes(), &response.body[..]);
+            assert_eq!(Some("max-age=60"), response.cache_control.as_deref());
+            assert_eq!(Some("text/plain"), response.content_type.as_deref());
+
+            let error = fetcher.fetch("https://b.com").await.unwrap_err();
+            assert!(
+                matches!(error, Error::Io(ref e) if e.kind() == io::ErrorKind::ConnectionRefused)
+            );
+        });
+    }
+
+    #[test]
+    fn test_redirects() {
+        tokio_test::block_on(async {
+            let client = FakeClient::default()
+                .redirect(
+                    "https://a.com/robots.txt",
+                    301,
+                    "https://www.a.com/robots.txt",
+                )
+                .redirect("https://www.a.com/robots.txt", 302, "/1")
+                .redirect("https://www.a.com/1", 307, "2")
+                .redirect("https://www.a.com/2", 308, "3")
+                .redirect("https://www.a.com/3", 303, "4")
+                .respond("https://www.a.com/4", 200, ROBOTS)
+                .redirect("https://loop.com/robots.txt", 301, "/robots.txt");
+
+            let fetcher = RobotsFetcher::new(client);
+            let response = fetcher.fetch("https://a.com").await.unwrap();
+            assert_eq!(200, response.status);
+            assert_eq!(ROBOTS.as_bytes(), &response.body[..]);
+
+            let response = fetcher.fetch("https://www.a.com").await.unwrap();
+            assert_eq!(200, response.status);
+
+            let response = fetcher.fetch("https://loop.com").await.unwrap();
+            assert_eq!(301, response.status);
+            assert!(response.body.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_max_size() {
+        tokio_test::block_on(async {
+            let client = FakeClient::default().respond("https://a.com/robots.txt", 200, ROBOTS);
+            let fetcher = RobotsFetcher::new(client).with_max_size(16);
+
+            let response = fetcher.fetch("https://a.com").await.unwrap();
+            assert_eq!(b"User-agent: *\nDi", &response.body[..]);
+        });
+    }
+
+    #[test]
+    fn test_compile() {
+        tokio_test::block_on(async {
+            let client = FakeClient::default()
+                .respond("https://ok.com/robots.txt", 200, ROBOTS)
+                .respond("https://gone.com/robots.txt", 404, ROBOTS)
+                .respond("https://down.com/robots.txt", 503, ROBOTS)
+                .respond("https://spa.com/robots.txt", 200, SPA_SHELL)
+                .redirect("https://moved.com/robots.txt", 301, "/robots.txt");
+            let fetcher = RobotsFetcher::new(client);
+            let compiler = Compiler::new("foobot");
+
+            let cylon = fetcher.compile(&compiler, "https://ok.com").await.unwrap();
+            assert_eq!(true, cylon.allow("/"));
+            assert_eq!(false, cylon.allow("/private"));
+
+            let cylon = fetcher.compile(&compiler, "https://spa.com").await.unwrap();
+            assert_eq!(ContentKind::Html, cylon.content_kind());
+            assert_eq!(false, cylon.allow("/private"));
+            let compiler_lenient = compiler
+                .clone()
+                .with_content_policy(ContentKind::Html, ContentPolicy::AllowAll);
+            let cylon = fetcher
+                .compile(&compiler_lenient, "https://spa.com")
+                .await
+                .unwrap();
+            assert_eq!(true, cylon.allow("/private"));
+            let compiler_strict = compiler
+                .clone()
+                .with_content_policy(ContentKind::Html, ContentPolicy::DisallowAll);
+            let cylon = fetcher
+                .compile(&compiler_strict, "https://spa.com")
+                .await
+                .unwrap();
+            assert_eq!(false, cylon.allow("/private"));
+            assert_eq!(true, cylon.allow("/robots.txt"));
+
+            let cylon = fetcher
+                .compile(&compiler, "https://gone.com")
+                .await
+                .unwrap();
+            assert_eq!(true, cylon.allow("/private"));
+            let cylon = fetcher
+                .compile(&compiler, "https://moved.com")
+                .await
+                .unwrap();
+            assert_eq!(true, cylon.allow("/private"));
+
+            let cylon = fetcher
+                .compile(&compiler, "https://down.com")
+                .await
+                .unwrap();
+            assert_eq!(false, cylon.allow("/"));
+            let cylon = fetcher
+                .compile(&compiler, "https://offline.com")
+                .await
+                .unwrap();
+            assert_eq!(false, cylon.allow("/"));
+        });
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        tokio_test::block_on(async {
+            let client = FakeClient::default().respond("https://ok.com/robots.txt", 200, ROBOTS);
+            let fetcher = RobotsFetcher::new(client);
+
+            // A misconfigured compiler fails even if the server can't be
+            // reached, instead of allowing or disallowing everything.
+            for origin in ["https://ok.com", "https://offline.com"] {
+                let result = fetcher.compile(&Compiler::new(""), origin).await;
+                assert!(matches!(result, Err(Error::InvalidConfiguration(_))));
+            }
+
+            let compiler = Compiler::new("foobot").with_max_size(16);
+            let result = fetcher.compile(&compiler, "https://ok.com").await;
+            assert!(matches!(
+                result,
+                Err(Error::SizeLimitExceeded { limit: 16 })
+            ));
+        });
+    }
+}
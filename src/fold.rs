@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use serde_derive::{Deserialize, Serialize};
+
+/// The characters beyond which no script has case.
+const LAST_CASED: u32 = 0x1FFFF;
+
+/// Whether a Compiler matches paths case-insensitively. RFC 9309 requires
+/// case-sensitive matching, but many servers, e.g. on IIS, treat paths
+/// case-insensitively, and their robots.txt files are written to match.
+///
+/// Folding is compiled into the Cylon, so paths don't need to be changed
+/// before they are matched.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CaseFolding {
+    /// Paths are matched case-sensitively.
+    #[default]
+    None,
+    /// ASCII letters, including the hex digits of percent-encodings, match
+    /// in either case.
+    Ascii,
+    /// Like `Ascii`, but other letters also match any character with the
+    /// same Unicode simple case folding, whether they are percent-encoded
+    /// or not. For example, `%C3%84` (`Ä`) also matches `%C3%A4` (`ä`).
+    Unicode,
+}
+
+impl CaseFolding {
+    /// Split a pattern into the alternative byte strings that each of its
+    /// characters matches. ASCII letters are lowercased, so the NFA must
+    /// match them in either case.
+    pub(crate) fn units(self, pattern: &[u8]) -> Vec<Vec<Vec<u8>>> {
+        let byte = |b: u8| match self {
+            CaseFolding::None => vec![vec![b]],
+            _ => vec![vec![b.to_ascii_lowercase()]],
+        };
+        if self != CaseFolding::Unicode {
+            return pattern.iter().map(|b| byte(*b)).collect();
+        }
+
+        let mut units = vec![];
+        let mut i = 0;
+        while i < pattern.len() {
+            let (c, len, encoded) = match decode_char(&pattern[i..]) {
+                Some(decoded) => decoded,
+                None => {
+                    units.push(byte(pattern[i]));
+                    i += 1;
+                    continue;
+                }
+            };
+            let class = case_class(c);
+            if class.len() > 1 {
+                // Characters matched alongside an ASCII letter are always
+                // percent-encoded, like the paths they will be matched to.
+                let mut variants: Vec<Vec<u8>> = class
+                    .into_iter()
+                    .map(|c| encode_char(c, encoded || len == 1))
+                    .collect();
+                variants.sort_unstable();
+                variants.dedup();
+                units.push(variants);
+            } else {
+                units.extend(pattern[i..i + len].iter().map(|b| byte(*b)));
+            }
+            i += len;
+        }
+        units
+    }
+}
+
+/// Decode the character at the start of the pattern, which may be
+/// percent-encoded. Returns the character, the number of bytes it takes
+/// up in the pattern and whether it was percent-encoded.
+fn decode_char(pattern: &[u8]) -> Option<(char, usize, bool)> {
+    let hex = |at: usize| {
+        let digits = pattern.get(at + 1..at + 3)?;
+        if pattern[at] != b'%' || !digits.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+        u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+    };
+    let (first, encoded) = match hex(0) {
+        Some(first) => (first, true),
+        None => (*pattern.first()?, false),
+    };
+    let len = match first {
+        0x00..=0x7F => 1,
+        0xC2..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF4 => 4,
+        _ => return None,
+    };
+
+    let bytes = match encoded {
+        true => (0..len).map(|i| hex(3 * i)).collect::<Option<Vec<u8>>>()?,
+        false => pattern.get(..len)?.to_vec(),
+    };
+    let c = std::str::from_utf8(&bytes).ok()?.chars().next()?;
+    // Encoded ASCII characters are left alone, since they don't match
+    // their decoded form.
+    match (encoded, c.is_ascii()) {
+        (true, true) => None,
+        (true, false) => Some((c, 3 * len, true)),
+        (false, _) => Some((c, len, false)),
+    }
+}
+
+/// Encode a character as it is stored in the NFA: ASCII letters are
+/// lowercased, and percent-encodings use lowercase hex digits.
+fn encode_char(c: char, percent_encode: bool) -> Vec<u8> {
+    let mut buf = [0; 4];
+    let bytes = c.to_ascii_lowercase().encode_utf8(&mut buf).as_bytes();
+    if !percent_encode || c.is_ascii() {
+        return bytes.to_vec();
+    }
+    bytes
+        .iter()
+        .flat_map(|b| format!("%{:02x}", b).into_bytes())
+        .collect()
+}
+
+/// The characters with the same simple case folding as `c`, including
+/// `c` itself.
+fn case_class(c: char) -> Vec<char> {
+    static CLASSES: OnceLock<HashMap<char, Vec<char>>> = OnceLock::new();
+    let classes = CLASSES.get_or_init(|| {
+        let mut classes: HashMap<char, Vec<char>> = HashMap::new();
+        for c in (0..=LAST_CASED).filter_map(char::from_u32) {
+            let folded = fold(c);
+            if folded != c {
+                let class = classes.entry(folded).or_insert_with(|| vec![folded]);
+                class.push(c);
+            }
+        }
+        classes
+    });
+    classes.get(&fold(c)).cloned().unwrap_or_else(|| vec![c])
+}
+
+/// The simple case folding of a character, which is the lowercase form of
+/// its uppercase form where both are single characters.
+fn fold(c: char) -> char {
+    // Dotless i has no simple case folding, though its uppercase is I.
+    if c == 'ı' {
+        return c;
+    }
+    let upper = single(c.to_uppercase()).unwrap_or(c);
+    single(upper.to_lowercase()).unwrap_or(c)
+}
+
+fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+    let c = chars.next()?;
+    chars.next().is_none().then_some(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_case_class() {
+        let test_cases = vec![
+            ('a', vec!['A', 'a']),
+            ('k', vec!['K', 'k', '\u{212A}']),
+            ('ä', vec!['Ä', 'ä']),
+            ('ς', vec!['Σ', 'σ', 'ς']),
+            ('ß', vec!['ß', 'ẞ']),
+            ('ı', vec!['ı']),
+            ('1', vec!['1']),
+            ('א', vec!['א']),
+        ];
+
+        for (c, expected) in test_cases {
+            let mut expected = expected;
+            expected.sort_unstable();
+            let mut class = case_class(c);
+            class.sort_unstable();
+            assert_eq!(expected, class, "{}", c);
+        }
+    }
+
+    #[test]
+    fn test_units() {
+        let units = |folding: CaseFolding, pattern: &str| -> Vec<Vec<String>> {
+            folding
+                .units(pattern.as_bytes())
+                .into_iter()
+                .map(|variants| {
+                    variants
+                        .into_iter()
+                        .map(|v| String::from_utf8(v).unwrap())
+                        .collect()
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            vec![vec!["/"], vec!["A"], vec!["%"]],
+            units(CaseFolding::None, "/A%")
+        );
+        assert_eq!(
+            vec![vec!["/"], vec!["a"], vec!["%"], vec!["c"], vec!["3"]],
+            units(CaseFolding::Ascii, "/A%C3")
+        );
+
+        assert_eq!(
+            vec![vec!["/"], vec!["%c3%84", "%c3%a4"]],
+            units(CaseFolding::Unicode, "/%C3%84")
+        );
+        assert_eq!(
+            vec![vec!["/"], vec!["Ä", "ä"]],
+            units(CaseFolding::Unicode, "/ä")
+        );
+
+        assert_eq!(
+            vec![vec!["k", "\u{212A}"]],
+            units(CaseFolding::Unicode, "\u{212A}")
+        );
+        assert_eq!(
+            vec![vec!["%e2%84%aa", "k"]],
+            units(CaseFolding::Unicode, "K")
+        );
+
+        // Encoded ASCII, uncased letters and partial encodings stay bytes.
+        assert_eq!(
+            vec![vec!["%"], vec!["4"], vec!["1"]],
+            units(CaseFolding::Unicode, "%41")
+        );
+        assert_eq!(6, units(CaseFolding::Unicode, "%D7%90").len());
+        assert_eq!(3, units(CaseFolding::Unicode, "%C3").len());
+    }
+}
@@ -1,5 +1,50 @@
+mod binary;
+mod builder;
+mod cache;
+mod clean_param;
+mod directive;
+mod error;
+mod fetch;
+mod fold;
+mod model;
+mod naive;
 mod nfa;
+mod normalize;
+mod pack;
 mod parse;
+mod push;
+mod rate;
+mod regex_rule;
+#[cfg(feature = "reqwest")]
+mod reqwest_client;
+mod robots_cache;
+mod semantics;
+mod sniff;
+mod syntax;
+mod url;
+mod view;
 
-pub use nfa::Cylon;
+pub use binary::DecodeError;
+pub use builder::{CylonBuilder, PatternError};
+pub use cache::CompileCache;
+pub use clean_param::CleanParam;
+pub use directive::{Directive, Scope, UnknownDirective};
+pub use error::Error;
+pub use fetch::{HttpClient, HttpResponse, RobotsFetcher};
+pub use fold::CaseFolding;
+pub use model::{Entry, Group, GroupRule, RobotsTxt};
+pub use naive::NaiveMatcher;
+pub use nfa::{Cylon, CylonStats};
+pub use normalize::PathNormalizer;
+pub use pack::CylonPack;
 pub use parse::Compiler;
+pub use push::PushParser;
+pub use rate::{RequestRate, VisitTime};
+pub use regex_rule::RegexRule;
+#[cfg(feature = "reqwest")]
+pub use reqwest_client::ReqwestClient;
+pub use robots_cache::{CachedRobots, FetchResponse, Fetcher, RobotsCache};
+pub use semantics::MatchSemantics;
+pub use sniff::{ContentKind, ContentPolicy};
+pub use syntax::{LineKind, SyntaxTree};
+pub use view::CylonView;
@@ -0,0 +1,433 @@
+use std::fmt;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::directive;
+use crate::parse::{self, Key};
+
+/// A `key: value` line. Known directive names are spelled canonically and
+/// unknown ones as they were written.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub key: String,
+    pub value: String,
+}
+
+impl Entry {
+    pub fn new(key: &str, value: &str) -> Self {
+        Self {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+}
+
+/// A rule inside a group.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GroupRule {
+    Allow(String),
+    Disallow(String),
+    /// Any other directive scoped to the group, e.g. `Crawl-delay`.
+    Other(Entry),
+}
+
+impl GroupRule {
+    /// Build a rule from a directive name and value, spelling known names
+    /// canonically.
+    pub(crate) fn from_key_value(key: &str, value: &str) -> Self {
+        match Key::from_name(key) {
+            Key::Allow => GroupRule::Allow(value.into()),
+            Key::Disallow => GroupRule::Disallow(value.into()),
+            kind => GroupRule::Other(Entry::new(kind.name().unwrap_or(key), value)),
+        }
+    }
+
+    fn is_path_rule(&self) -> bool {
+        !matches!(self, GroupRule::Other(..))
+    }
+}
+
+/// A group of rules that apply to one or more user agents. The first group
+/// of a file may have no user agents if rules appear before any
+/// `User-agent` line.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Group {
+    /// Comments written before or inside the group, without the `#`.
+    pub comments: Vec<String>,
+    pub user_agents: Vec<String>,
+    pub rules: Vec<GroupRule>,
+}
+
+impl Group {
+    /// Create an empty group for the given user agents.
+    pub fn new<I, S>(user_agents: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            comments: vec![],
+            user_agents: user_agents.into_iter().map(Into::into).collect(),
+            rules: vec![],
+        }
+    }
+
+    pub fn comment(mut self, comment: &str) -> Self {
+        self.comments.push(comment.into());
+        self
+    }
+
+    pub fn allow(mut self, path: &str) -> Self {
+        self.rules.push(GroupRule::Allow(path.into()));
+        self
+    }
+
+    pub fn disallow(mut self, path: &str) -> Self {
+        self.rules.push(GroupRule::Disallow(path.into()));
+        self
+    }
+
+    pub fn directive(mut self, key: &str, value: &str) -> Self {
+        self.rules.push(GroupRule::Other(Entry::new(key, value)));
+        self
+    }
+}
+
+/// The parsed contents of a robots.txt file. Unlike a compiled Cylon, this
+/// keeps every group, so it can be inspected, edited, or built by hand and
+/// written back out as text with `to_string()`.
+///
+/// ```
+/// use cylon::{Group, RobotsTxt};
+///
+/// let robots = RobotsTxt::new()
+///     .group(Group::new(vec!["*"]).disallow("/private"))
+///     .sitemap("https://example.com/sitemap.xml");
+///
+/// assert_eq!(
+///     "User-agent: *\nDisallow: /private\n\nSitemap: https://example.com/sitemap.xml\n",
+///     robots.to_string()
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RobotsTxt {
+    /// Comments at the top of the file that are not attached to a group.
+    pub comments: Vec<String>,
+    /// Directives that apply to the whole file, such as `Host` and
+    /// `Clean-param`, and unknown directives before the first group.
+    pub globals: Vec<Entry>,
+    pub groups: Vec<Group>,
+    pub sitemaps: Vec<String>,
+}
+
+impl RobotsTxt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn comment(mut self, comment: &str) -> Self {
+        self.comments.push(comment.into());
+        self
+    }
+
+    pub fn global(mut self, key: &str, value: &str) -> Self {
+        self.globals.push(Entry::new(key, value));
+        self
+    }
+
+    pub fn group(mut self, group: Group) -> Self {
+        self.groups.push(group);
+        self
+    }
+
+    pub fn sitemap(mut self, url: &str) -> Self {
+        self.sitemaps.push(url.into());
+        self
+    }
+
+    /// Parse a robots.txt file. Groups are delimited the same way the
+    /// Compiler delimits them. Comments are attached to the group that
+    /// follows them, or to the file when they are separated from the first
+    /// group by a blank line. Lines that are not directives and a leading
+    /// byte order mark are dropped.
+    pub fn parse(input: &str) -> Self {
+        let mut robots = Self::new();
+        let mut pending: Vec<String> = vec![];
+        let mut parsing_agents = false;
+
+        for (line, _) in parse::lines(input.trim_start_matches('\u{feff}')) {
+            let comment = line.find('#').map(|i| line[i + 1..].trim().to_string());
+            let key_value = parse::split_line(line);
+
+            match key_value {
+                None if comment.is_none() && robots.groups.is_empty() => {
+                    // A blank line detaches leading comments from the first group.
+                    robots.comments.append(&mut pending);
+                }
+                None => (),
+                Some((key, value)) => {
+                    let kind = Key::from_name(key);
+                    let key = kind.name().unwrap_or(key);
+                    if kind == Key::Unknown && !directive::is_directive_key(key) {
+                        // Not a directive; only its comment is kept.
+                    } else if kind == Key::Sitemap {
+                        robots.sitemaps.push(value.into());
+                    } else if kind.is_global() {
+                        robots.globals.push(Entry::new(key, value));
+                    } else if kind == Key::UserAgent {
+                        match robots.groups.last_mut() {
+                            Some(group) if parsing_agents => group.user_agents.push(value.into()),
+                            _ => robots.groups.push(Group {
+                                comments: std::mem::take(&mut pending),
+                                user_agents: vec![value.into()],
+                                rules: vec![],
+                            }),
+                        }
+                        parsing_agents = true;
+                    } else if kind == Key::Unknown && robots.groups.is_empty() {
+                        robots.globals.push(Entry::new(key, value));
+                    } else {
+                        let rule = GroupRule::from_key_value(key, value);
+                        if robots.groups.is_empty() {
+                            robots.groups.push(Group::default());
+                        }
+                        let group = robots.groups.last_mut().unwrap();
+                        group.comments.append(&mut pending);
+                        // Unknown lines don't end a group's header.
+                        parsing_agents &= kind == Key::Unknown;
+                        group.rules.push(rule);
+                    }
+                }
+            }
+
+            pending.extend(comment);
+        }
+
+        match robots.groups.last_mut() {
+            Some(group) => group.comments.append(&mut pending),
+            None => robots.comments.append(&mut pending),
+        }
+        robots
+    }
+
+    /// Put the file into a canonical order: `Host`, then `Clean-param`, then
+    /// other global directives at the top, and in every group the
+    /// `Allow`/`Disallow` rules before other directives. The relative order
+    /// of rules of the same kind is kept, since some crawlers give it
+    /// meaning. Together with the writer, which normalizes directive casing
+    /// and spacing, this makes formatting idempotent.
+    pub fn normalize(&mut self) {
+        self.globals.sort_by_key(|e| match Key::from_name(&e.key) {
+            Key::Host => 0,
+            Key::CleanParam => 1,
+            _ => 2,
+        });
+        for group in self.groups.iter_mut() {
+            group.rules.sort_by_key(|r| !r.is_path_rule());
+        }
+    }
+
+    /// Format a robots.txt file canonically. Formatting the output again
+    /// yields the same text, and parsing it yields the same groups.
+    pub fn format(input: &str) -> String {
+        let mut robots = Self::parse(input);
+        robots.normalize();
+        robots.to_string()
+    }
+}
+
+impl fmt::Display for RobotsTxt {
+    /// Write the file as robots.txt text. Groups are separated by a blank
+    /// line and sitemaps are written at the end.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sections = 0;
+        let mut section = |f: &mut fmt::Formatter<'_>| {
+            sections += 1;
+            if sections > 1 {
+                writeln!(f)
+            } else {
+                Ok(())
+            }
+        };
+
+        if !self.comments.is_empty() {
+            section(f)?;
+            for comment in &self.comments {
+                write_comment(f, comment)?;
+            }
+        }
+
+        if !self.globals.is_empty() {
+            section(f)?;
+            for entry in &self.globals {
+                write_directive(f, &entry.key, &entry.value)?;
+            }
+        }
+
+        for group in &self.groups {
+            section(f)?;
+            for comment in &group.comments {
+                write_comment(f, comment)?;
+            }
+            for agent in &group.user_agents {
+                write_directive(f, "user-agent", agent)?;
+            }
+            for rule in &group.rules {
+                match rule {
+                    GroupRule::Allow(path) => write_directive(f, "allow", path)?,
+                    GroupRule::Disallow(path) => write_directive(f, "disallow", path)?,
+                    GroupRule::Other(entry) => write_directive(f, &entry.key, &entry.value)?,
+                }
+            }
+        }
+
+        if !self.sitemaps.is_empty() {
+            section(f)?;
+            for url in &self.sitemaps {
+                write_directive(f, "sitemap", url)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_comment(f: &mut fmt::Formatter<'_>, comment: &str) -> fmt::Result {
+    let comment = comment.replace(['\r', '\n'], " ");
+    if comment.is_empty() {
+        writeln!(f, "#")
+    } else {
+        writeln!(f, "# {}", comment)
+    }
+}
+
+/// Write a directive with its canonical name. A `#` in the value would start
+/// a comment, so it is percent-encoded, and line breaks are dropped.
+fn write_directive(f: &mut fmt::Formatter<'_>, key: &str, value: &str) -> fmt::Result {
+    let key = Key::from_name(key).name().unwrap_or(key);
+    let value = value.replace('#', "%23").replace(['\r', '\n'], "");
+    if value.is_empty() {
+        writeln!(f, "{}:", key)
+    } else {
+        writeln!(f, "{}: {}", key, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = r#"# robots.txt for example.com
+
+host: example.com
+Content-Usage: train-ai=n
+
+# Keep the bad bot out
+user-agent: BadBot
+USER-AGENT: EvilBot
+crawl-delay:   10   # slow down
+disallow:/
+
+User-agent: *
+Allow: /public
+Noindex: /drafts
+Disallow: /private
+sitemap: https://example.com/sitemap.xml
+Clean-param: sid /forum/
+"#;
+
+    #[test]
+    fn test_parse() {
+        let robots = RobotsTxt::parse(EXAMPLE);
+        let expected = RobotsTxt::new()
+            .comment("robots.txt for example.com")
+            .global("Host", "example.com")
+            .global("Content-Usage", "train-ai=n")
+            .global("Clean-param", "sid /forum/")
+            .group(
+                Group::new(vec!["BadBot", "EvilBot"])
+                    .comment("Keep the bad bot out")
+                    .comment("slow down")
+                    .directive("Crawl-delay", "10")
+                    .disallow("/"),
+            )
+            .group(
+                Group::new(vec!["*"])
+                    .allow("/public")
+                    .directive("Noindex", "/drafts")
+                    .disallow("/private"),
+            )
+            .sitemap("https://example.com/sitemap.xml");
+
+        assert_eq!(expected, robots);
+    }
+
+    #[test]
+    fn test_parse_bare_cr() {
+        let robots = RobotsTxt::parse("User-agent: a\rDisallow: /a\rUser-agent: b\rDisallow: /b\r");
+        let expected = RobotsTxt::new()
+            .group(Group::new(vec!["a"]).disallow("/a"))
+            .group(Group::new(vec!["b"]).disallow("/b"));
+        assert_eq!(expected, robots);
+    }
+
+    #[test]
+    fn test_format() {
+        let expected = r#"# robots.txt for example.com
+
+Host: example.com
+Clean-param: sid /forum/
+Content-Usage: train-ai=n
+
+# Keep the bad bot out
+# slow down
+User-agent: BadBot
+User-agent: EvilBot
+Disallow: /
+Crawl-delay: 10
+
+User-agent: *
+Allow: /public
+Disallow: /private
+Noindex: /drafts
+
+Sitemap: https://example.com/sitemap.xml
+"#;
+
+        assert_eq!(expected, RobotsTxt::format(EXAMPLE));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let test_cases = vec![
+            EXAMPLE,
+            "",
+            "# only a comment",
+            "Allow: /\n\nUser-agent: foo\nDisallow: /",
+            "User-agent: a\nX-Unknown: 1\nUser-agent: b\nDisallow: /\n# trailing",
+            "User-agent: a\nDisallow: /a#b\nDisallow:\n",
+        ];
+
+        for i in test_cases {
+            let mut parsed = RobotsTxt::parse(i);
+            parsed.normalize();
+            let formatted = RobotsTxt::format(i);
+            assert_eq!(parsed, RobotsTxt::parse(&formatted), "{}", i);
+            assert_eq!(formatted, RobotsTxt::format(&formatted), "{}", i);
+        }
+    }
+
+    #[test]
+    fn test_write_escapes() {
+        let robots = RobotsTxt::new().group(
+            Group::new(vec!["*"])
+                .comment("multi\nline")
+                .disallow("/a#b\n"),
+        );
+
+        assert_eq!(
+            "# multi line\nUser-agent: *\nDisallow: /a%23b\n",
+            robots.to_string()
+        );
+    }
+}
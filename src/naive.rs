@@ -0,0 +1,129 @@
+use crate::semantics;
+
+const EOW_BYTE: u8 = b'$';
+const WILDCARD_BYTE: u8 = b'*';
+
+/// A reference matcher that checks a path against every rule in turn,
+/// without compiling them. It is much slower than a Cylon, but simple
+/// enough to be obviously correct, so it is used to verify the NFA. Build
+/// one from the same rules as a Cylon with
+/// [`CylonBuilder::build_naive`](crate::CylonBuilder::build_naive).
+///
+/// ```
+/// use cylon::CylonBuilder;
+///
+/// let builder = CylonBuilder::new().disallow("/").allow("/*.html$");
+/// let naive = builder.build_naive().unwrap();
+/// let cylon = builder.build().unwrap();
+///
+/// for path in ["/", "/index.html", "/index.html?q"] {
+///     assert_eq!(naive.allow(path), cylon.allow(path));
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NaiveMatcher {
+    rules: Vec<(bool, String)>,
+}
+
+impl NaiveMatcher {
+    /// Rules are given as `(allow, pattern)` pairs, which are assumed to
+    /// have been validated already.
+    pub(crate) fn new(rules: Vec<(bool, String)>) -> Self {
+        Self { rules }
+    }
+
+    /// Whether the path is allowed. The longest pattern that matches the
+    /// path decides, counting a trailing run of `*` as one character, and
+    /// allow wins if an allow and a disallow pattern of the same length
+    /// both match. Paths that match nothing are allowed.
+    pub fn allow<T: AsRef<[u8]>>(&self, path: T) -> bool {
+        let path = path.as_ref();
+        let path = if path.is_empty() { b"/" } else { path };
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| matches(pattern.as_bytes(), path))
+            .map(|(allow, pattern)| (semantics::length(pattern.as_bytes()), *allow))
+            .max()
+            .is_none_or(|(_, allow)| allow)
+    }
+}
+
+/// Whether the pattern matches a prefix of the path, or the whole path if
+/// the pattern ends with `$`. A `*` matches any sequence of bytes.
+fn matches(pattern: &[u8], path: &[u8]) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix(&[EOW_BYTE]) {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+
+    // Every position in the path that the pattern so far can end at.
+    let mut positions = vec![0];
+    for byte in pattern {
+        positions = match *byte {
+            WILDCARD_BYTE => (positions[0]..=path.len()).collect(),
+            byte => positions
+                .into_iter()
+                .filter(|i| path.get(*i) == Some(&byte))
+                .map(|i| i + 1)
+                .collect(),
+        };
+        if positions.is_empty() {
+            return false;
+        }
+    }
+    !anchored || positions.last() == Some(&path.len())
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        let test_cases = vec![
+            ("/", "/", true),
+            ("/", "/a", true),
+            ("/a", "/", false),
+            ("/fish", "/fish.html", true),
+            ("/fish", "/Fish", false),
+            ("/fish*", "/fish", true),
+            ("/*.php", "/a/b.php?q", true),
+            ("/*.php$", "/a/b.php?q", false),
+            ("/*.php$", "/a/b.php", true),
+            ("/a*b*c", "/abbbc", true),
+            ("/a*b*c", "/acb", false),
+            ("/a*b$", "/abab", true),
+            ("/$", "/", true),
+            ("/$", "/a", false),
+            ("*", "/", true),
+        ];
+
+        for (pattern, path, expected) in test_cases {
+            assert_eq!(
+                expected,
+                matches(pattern.as_bytes(), path.as_bytes()),
+                "{} {}",
+                pattern,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_allow() {
+        let naive = NaiveMatcher::new(vec![
+            (false, "/".into()),
+            (true, "/a".into()),
+            (false, "/a*".into()),
+            (true, "/b".into()),
+            (false, "/b".into()),
+        ]);
+
+        assert_eq!(false, naive.allow(""));
+        assert_eq!(false, naive.allow("/c"));
+        assert_eq!(false, naive.allow("/a"));
+        assert_eq!(true, naive.allow("/b"));
+        assert_eq!(true, NaiveMatcher::new(vec![]).allow("/"));
+    }
+}
@@ -1,19 +1,35 @@
-#[cfg(feature = "crawl-delay")]
-use std::cmp::Ordering;
-
 use std::collections::{BTreeSet, VecDeque};
+use std::fmt::Write;
+use std::mem::size_of;
+#[cfg(feature = "crawl-delay")]
+use std::time::Duration;
+use std::time::SystemTime;
 
 use serde_derive::{Deserialize, Serialize};
 
+#[cfg(feature = "crawl-delay")]
+use crate::binary::FLAG_CRAWL_DELAY;
+use crate::binary::{DecodeError, Reader, Writer};
+use crate::clean_param::{self, CleanParam};
+use crate::directive::{Directive, Scope, UnknownDirective};
+use crate::error::Error;
+use crate::fold::CaseFolding;
+use crate::normalize::PathNormalizer;
+use crate::rate::{RequestRate, VisitTime};
+use crate::semantics::MatchSemantics;
+use crate::sniff::ContentKind;
+use crate::url;
+
 const EOW_BYTE: u8 = 36; // '$'
 const WILDCARD_BYTE: u8 = 42; // '*'
 
+/// The path of the robots.txt file, which RFC 9309 implicitly allows.
+pub(crate) const ROBOTS_TXT: &[u8] = b"/robots.txt";
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Rule<'a> {
     Allow(&'a [u8]),
     Disallow(&'a [u8]),
-    #[cfg(feature = "crawl-delay")]
-    Delay(&'a [u8]),
 }
 
 impl<'a> Rule<'a> {
@@ -21,18 +37,68 @@ impl<'a> Rule<'a> {
         match self {
             Rule::Allow(inner) => inner,
             Rule::Disallow(inner) => inner,
-            #[cfg(feature = "crawl-delay")]
-            Rule::Delay(inner) => inner,
         }
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
-enum Accept {
+pub(crate) enum Accept {
     Allow,
     Disallow,
     #[cfg(feature = "crawl-delay")]
     Delay,
+    /// No rule has matched yet. Behaves like Allow, but lets a combined
+    /// Cylon tell an explicit decision apart from the default.
+    Unmatched,
+}
+
+impl Accept {
+    /// The decision of the rule this state matched, or None if the
+    /// path has not matched any rule.
+    fn decision(self) -> Option<bool> {
+        match self {
+            Accept::Unmatched => None,
+            Accept::Allow => Some(true),
+            Accept::Disallow => Some(false),
+            #[cfg(feature = "crawl-delay")]
+            Accept::Delay => Some(true),
+        }
+    }
+
+    /// Re-map a state's weight based on the accept state. This
+    /// makes it easier to guarantee Allow states always break
+    /// ties against Disallow states, and that any rule breaks
+    /// ties against no rule at all.
+    fn normalized_weight(self, weight: usize) -> usize {
+        match self {
+            Accept::Unmatched => 3 * weight,
+            Accept::Disallow => 1 + 3 * weight,
+            Accept::Allow => 2 + 3 * weight,
+            #[cfg(feature = "crawl-delay")]
+            Accept::Delay => 2 + 3 * weight,
+        }
+    }
+
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            Accept::Allow => 0,
+            Accept::Disallow => 1,
+            Accept::Unmatched => 2,
+            #[cfg(feature = "crawl-delay")]
+            Accept::Delay => 3,
+        }
+    }
+
+    pub(crate) fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Accept::Allow),
+            1 => Some(Accept::Disallow),
+            2 => Some(Accept::Unmatched),
+            #[cfg(feature = "crawl-delay")]
+            3 => Some(Accept::Delay),
+            _ => None,
+        }
+    }
 }
 
 /// A Node represents a state in the NFA. All nodes are
@@ -84,49 +150,270 @@ impl Node {
         out
     }
 
-    fn allow(&self) -> bool {
-        match self.accept {
-            Accept::Allow => true,
-            Accept::Disallow => false,
-            #[cfg(feature = "crawl-delay")]
-            Accept::Delay => true,
+    fn offset(&self, offset: usize) -> Self {
+        Self {
+            accept: self.accept,
+            edges: self.edges.iter().map(|(e, s)| (*e, s + offset)).collect(),
+            weight: self.weight,
+            wildcards: self.wildcards.iter().map(|s| s + offset).collect(),
         }
     }
+}
 
-    /// Re-map the node's weight based on the accept state. This
-    /// makes it easier to guarantee Allow states always break
-    /// ties against Disallow states.
-    fn normalized_weight(&self) -> usize {
-        match self.accept {
-            Accept::Allow => 1 + 2 * self.weight,
-            Accept::Disallow => 2 * self.weight,
-            #[cfg(feature = "crawl-delay")]
-            Accept::Delay => 1 + 2 * self.weight,
+/// A step of the postfix program that combines the decisions of the
+/// rule sets making up a Cylon.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub(crate) enum Instruction {
+    /// Push the decision of the rule set starting at this state.
+    Match(usize),
+    /// Pop two decisions, allow only if both allow.
+    Intersect,
+    /// Pop two decisions, allow if either allows.
+    Union,
+    /// Pop two decisions, the second wins wherever one of its rules matched.
+    Override,
+}
+
+impl Instruction {
+    /// Combine two decisions, where None means no rule matched and
+    /// the path is allowed by default.
+    fn apply(self, first: Option<bool>, second: Option<bool>) -> Option<bool> {
+        match self {
+            Instruction::Override => second.or(first),
+            _ if first.is_none() && second.is_none() => None,
+            Instruction::Intersect => Some(first != Some(false) && second != Some(false)),
+            Instruction::Union => Some(first != Some(false) || second != Some(false)),
+            Instruction::Match(..) => first,
+        }
+    }
+
+    fn offset(self, offset: usize) -> Self {
+        match self {
+            Instruction::Match(start) => Instruction::Match(start + offset),
+            op => op,
+        }
+    }
+
+    fn write(self, w: &mut Writer) {
+        let (tag, start) = match self {
+            Instruction::Match(start) => (0, start),
+            Instruction::Intersect => (1, 0),
+            Instruction::Union => (2, 0),
+            Instruction::Override => (3, 0),
+        };
+        w.u8(tag);
+        w.len(start);
+    }
+
+    pub(crate) fn read(r: &mut Reader) -> Result<Self, DecodeError> {
+        let tag = r.u8()?;
+        let start = r.u32()? as usize;
+        match tag {
+            0 => Ok(Instruction::Match(start)),
+            1 => Ok(Instruction::Intersect),
+            2 => Ok(Instruction::Union),
+            3 => Ok(Instruction::Override),
+            _ => Err(DecodeError::Invalid("bad instruction")),
+        }
+    }
+}
+
+/// Check that a program can be evaluated over the given number of states:
+/// rule sets start at state 0 in increasing order, every operator has two
+/// operands and a single decision is left at the end.
+pub(crate) fn valid_program(program: &[Instruction], states: usize) -> bool {
+    let mut depth = 0;
+    let mut last_start = None;
+    for op in program {
+        match *op {
+            Instruction::Match(start) => {
+                let ordered = match last_start {
+                    None => start == 0,
+                    Some(last) => start > last,
+                };
+                if !ordered || start >= states {
+                    return false;
+                }
+                last_start = Some(start);
+                depth += 1;
+            }
+            _ if depth < 2 => return false,
+            _ => depth -= 1,
+        }
+    }
+    depth == 1
+}
+
+const SINGLE_PROGRAM: &[Instruction] = &[Instruction::Match(0)];
+
+/// A symbol of a pattern, which the NFA has a state for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Symbol {
+    Byte(u8),
+    Wildcard,
+    /// A trailing `$`, which matches the end of the path.
+    EndOfWord,
+    /// A character that matches any of several byte strings when case is
+    /// folded, by its index in the list of classes.
+    Class(usize),
+}
+
+impl Symbol {
+    /// Symbols sort like the bytes they are written as, so that patterns
+    /// sort as their bytes would, and after the bytes if they are classes.
+    fn sort_key(self) -> (usize, bool) {
+        match self {
+            Symbol::Byte(byte) => (byte as usize, true),
+            Symbol::Wildcard => (WILDCARD_BYTE as usize, false),
+            Symbol::EndOfWord => (EOW_BYTE as usize, false),
+            Symbol::Class(class) => (256 + class, true),
         }
     }
 }
 
+/// A rule to compile, split into symbols and with its weight.
+#[derive(Debug)]
+struct Pattern {
+    symbols: Vec<Symbol>,
+    accept: Accept,
+    weight: usize,
+}
+
+/// Split a pattern into symbols, adding the variants of each folded
+/// character to the classes. Without special characters, `*` and `$`
+/// are matched like any other byte.
+fn symbols(
+    pattern: &[u8],
+    special_characters: bool,
+    folding: CaseFolding,
+    classes: &mut Vec<Vec<Vec<u8>>>,
+) -> Vec<Symbol> {
+    let (pattern, anchored) = match pattern.strip_suffix(&[EOW_BYTE]) {
+        Some(pattern) if special_characters => (pattern, true),
+        _ => (pattern, false),
+    };
+    let mut symbols: Vec<Symbol> = folding
+        .units(pattern)
+        .into_iter()
+        .map(|variants| match variants.as_slice() {
+            [variant] if variant == &[WILDCARD_BYTE] && special_characters => Symbol::Wildcard,
+            [variant] if variant.len() == 1 => Symbol::Byte(variant[0]),
+            _ => match classes.iter().position(|c| *c == variants) {
+                Some(class) => Symbol::Class(class),
+                None => {
+                    classes.push(variants);
+                    Symbol::Class(classes.len() - 1)
+                }
+            },
+        })
+        .collect();
+    if anchored {
+        symbols.push(Symbol::EndOfWord);
+    }
+    symbols
+}
+
+/// The rule that should win when both match.
+fn best(a: (Accept, usize), b: (Accept, usize)) -> (Accept, usize) {
+    let weight = |(accept, weight): (Accept, usize)| accept.normalized_weight(weight);
+    if weight(b) > weight(a) {
+        b
+    } else {
+        a
+    }
+}
+
+/// Make the node accept the rule if it should win over the node's own.
+fn keep_best(node: &mut Node, rule: (Accept, usize)) {
+    let (accept, weight) = best((node.accept, node.weight), rule);
+    node.accept = accept;
+    node.weight = weight;
+}
+
 #[derive(Debug, Clone, Copy)]
 struct QueueItem<'a> {
-    parent_prefix: &'a [u8],
+    parent_prefix: &'a [Symbol],
     parent_state: usize,
     epsilon_state: Option<usize>,
+    /// The best rule matched by any path that reaches the parent, which
+    /// its children inherit.
+    inherited: (Accept, usize),
 }
 
 impl<'a> QueueItem<'a> {
-    fn new(parent_prefix: &'a [u8], parent_state: usize) -> Self {
+    fn new(parent_prefix: &'a [Symbol], parent_state: usize, inherited: (Accept, usize)) -> Self {
         Self {
             parent_prefix,
             parent_state,
             epsilon_state: None,
+            inherited,
         }
     }
 }
 
 impl<'a> Default for QueueItem<'a> {
     fn default() -> Self {
-        Self::new(b"", 0)
+        Self::new(&[], 0, (Accept::Unmatched, 0))
+    }
+}
+
+/// The bytes that an edge for the byte matches. Folded letters are stored
+/// in lowercase and match either case.
+fn cases(byte: u8, folding: CaseFolding) -> impl Iterator<Item = u8> {
+    let upper = (folding != CaseFolding::None && byte.is_ascii_lowercase())
+        .then(|| byte.to_ascii_uppercase());
+    std::iter::once(byte).chain(upper)
+}
+
+/// Add a chain of states that matches the bytes of one variant of a folded
+/// character, leading from `from` to `to`, and return the edges out of
+/// `from`. A path that stops partway through the character still matches
+/// the inherited rule, and falls back to the wildcards of `from` when the
+/// next byte doesn't match.
+fn add_chain(
+    states: &mut Vec<Node>,
+    from: usize,
+    to: usize,
+    variant: &[u8],
+    folding: CaseFolding,
+    inherited: (Accept, usize),
+) -> Vec<(u8, usize)> {
+    let wildcards = states[from].wildcards.clone();
+    let mut entries = vec![];
+    let mut current = from;
+    for (i, byte) in variant.iter().enumerate() {
+        let next = match i + 1 == variant.len() {
+            true => to,
+            false => {
+                let mut node = Node::new(inherited.0, inherited.1);
+                node.wildcards = wildcards.clone();
+                states.push(node);
+                states.len() - 1
+            }
+        };
+        for edge in cases(*byte, folding) {
+            states[current].add_edge(edge, next);
+            if current == from {
+                entries.push((edge, next));
+            }
+        }
+        current = next;
     }
+    entries
+}
+
+/// Statistics about the NFA of a compiled Cylon, e.g. to flag
+/// pathological robots.txt files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CylonStats {
+    /// The number of states.
+    pub states: usize,
+    /// The number of transitions on a specific byte.
+    pub edges: usize,
+    /// The number of wildcard transitions, which match any byte.
+    pub wildcards: usize,
+    /// The largest number of transitions out of a single state.
+    pub max_fan_out: usize,
 }
 
 /// A Cylon is an NFA that recognizes rules from a compiled robots.txt
@@ -154,99 +441,734 @@ impl<'a> Default for QueueItem<'a> {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Cylon {
     states: Vec<Node>,
+    #[serde(default)]
+    program: Vec<Instruction>,
+    #[serde(default)]
+    sitemaps: Vec<String>,
+    #[serde(default)]
+    host: Option<String>,
+    #[serde(default)]
+    clean_params: Vec<CleanParam>,
+    #[serde(default)]
+    unknown_directives: Vec<UnknownDirective>,
+    #[serde(default)]
+    content_kind: ContentKind,
     #[cfg(feature = "crawl-delay")]
-    delay: Option<u64>,
+    #[serde(default, with = "serde_delay")]
+    delay: Option<Duration>,
+    #[cfg(feature = "crawl-delay")]
+    #[serde(default)]
+    invalid_delay: Option<String>,
+    #[serde(default)]
+    request_rates: Vec<RequestRate>,
+    #[serde(default)]
+    visit_times: Vec<VisitTime>,
+}
+
+/// Crawl delays were serialized as whole seconds before fractional delays
+/// were supported. In human-readable formats such as JSON, whole delays are
+/// still written that way so that older builds can read them, and either
+/// form is read. Compact formats such as bincode can't tell the forms
+/// apart, so they always use a `Duration`.
+#[cfg(feature = "crawl-delay")]
+mod serde_delay {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum Delay {
+        Seconds(u64),
+        Duration(Duration),
+    }
+
+    pub(super) fn serialize<S: Serializer>(
+        delay: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if !serializer.is_human_readable() {
+            return delay.serialize(serializer);
+        }
+        let delay = delay.map(|delay| match delay.subsec_nanos() {
+            0 => Delay::Seconds(delay.as_secs()),
+            _ => Delay::Duration(delay),
+        });
+        delay.serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        if !deserializer.is_human_readable() {
+            return Option::<Duration>::deserialize(deserializer);
+        }
+        let delay = Option::<Delay>::deserialize(deserializer)?;
+        Ok(delay.map(|delay| match delay {
+            Delay::Seconds(seconds) => Duration::from_secs(seconds),
+            Delay::Duration(delay) => delay,
+        }))
+    }
 }
 
 impl Cylon {
+    /// An estimate of the memory this Cylon owns on the heap, in bytes,
+    /// not counting the Cylon itself.
+    pub fn heap_size(&self) -> usize {
+        let strings =
+            |strings: &[String]| -> usize { strings.iter().map(String::capacity).sum::<usize>() };
+
+        let states = self.states.capacity() * size_of::<Node>()
+            + self
+                .states
+                .iter()
+                .map(|n| {
+                    n.edges.capacity() * size_of::<(u8, usize)>()
+                        + n.wildcards.capacity() * size_of::<usize>()
+                })
+                .sum::<usize>();
+        let program = self.program.capacity() * size_of::<Instruction>();
+        let sitemaps = self.sitemaps.capacity() * size_of::<String>() + strings(&self.sitemaps);
+        let host = self.host.as_ref().map_or(0, String::capacity);
+        let clean_params = self.clean_params.capacity() * size_of::<CleanParam>()
+            + self
+                .clean_params
+                .iter()
+                .map(CleanParam::heap_size)
+                .sum::<usize>();
+        let unknown_directives = self.unknown_directives.capacity() * size_of::<UnknownDirective>()
+            + self
+                .unknown_directives
+                .iter()
+                .map(UnknownDirective::heap_size)
+                .sum::<usize>();
+        let rates = self.request_rates.capacity() * size_of::<RequestRate>()
+            + self.visit_times.capacity() * size_of::<VisitTime>();
+        #[cfg(feature = "crawl-delay")]
+        let rates = rates + self.invalid_delay.as_ref().map_or(0, String::capacity);
+
+        states + program + sitemaps + host + clean_params + unknown_directives + rates
+    }
+
+    /// Statistics about the compiled NFA.
+    pub fn stats(&self) -> CylonStats {
+        let mut stats = CylonStats {
+            states: self.states.len(),
+            ..CylonStats::default()
+        };
+        for node in &self.states {
+            stats.edges += node.edges.len();
+            stats.wildcards += node.wildcards.len();
+            stats.max_fan_out = stats
+                .max_fan_out
+                .max(node.edges.len() + node.wildcards.len());
+        }
+        stats
+    }
+
+    /// The number of states in the compiled NFA.
+    pub fn state_count(&self) -> usize {
+        self.states.len()
+    }
+
+    /// The number of transitions in the compiled NFA, including wildcards.
+    pub fn edge_count(&self) -> usize {
+        let stats = self.stats();
+        stats.edges + stats.wildcards
+    }
+
+    /// Render the compiled NFA in the Graphviz DOT language, e.g. to see
+    /// what a robots.txt file compiles into with `dot -Tsvg`. States are
+    /// labelled with their accept kind and weight, byte transitions with
+    /// their byte, and wildcard transitions are dashed.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cylon {\n    rankdir=LR;\n");
+        for (i, op) in self.program().iter().enumerate() {
+            match op {
+                Instruction::Match(start) => {
+                    let _ = writeln!(dot, "    start{} [shape=point];", i);
+                    let _ = writeln!(dot, "    start{} -> {};", i, start);
+                }
+                op => {
+                    let _ = writeln!(dot, "    // {:?}", op);
+                }
+            }
+        }
+        for (i, node) in self.states.iter().enumerate() {
+            let (accept, color) = match node.accept {
+                Accept::Allow => ("allow", "darkgreen"),
+                Accept::Disallow => ("disallow", "red"),
+                Accept::Unmatched => ("unmatched", "gray"),
+                #[cfg(feature = "crawl-delay")]
+                Accept::Delay => ("delay", "blue"),
+            };
+            let _ = writeln!(
+                dot,
+                "    {} [label=\"{}\\n{} {}\", color={}];",
+                i, i, accept, node.weight, color
+            );
+        }
+        for (i, node) in self.states.iter().enumerate() {
+            for (edge, state) in &node.edges {
+                let label = match *edge {
+                    b'"' => "\\\"".to_string(),
+                    b'\\' => "\\\\".to_string(),
+                    b if b.is_ascii_graphic() => (b as char).to_string(),
+                    b => format!("\\\\x{:02x}", b),
+                };
+                let _ = writeln!(dot, "    {} -> {} [label=\"{}\"];", i, state, label);
+            }
+            for state in &node.wildcards {
+                let _ = writeln!(dot, "    {} -> {} [label=\"*\", style=dashed];", i, state);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The sitemap URLs listed anywhere in the file.
+    pub fn sitemaps(&self) -> &[String] {
+        &self.sitemaps
+    }
+
+    pub(crate) fn set_sitemaps(&mut self, sitemaps: Vec<String>) {
+        self.sitemaps = sitemaps;
+    }
+
+    /// The preferred host (mirror) of the site declared by a Yandex `Host`
+    /// directive, if any. Only the first directive in the file is used.
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    /// The Yandex `Clean-param` directives declared in the file.
+    pub fn clean_params(&self) -> &[CleanParam] {
+        &self.clean_params
+    }
+
+    /// Strip the query parameters that the file's `Clean-param` directives
+    /// declare irrelevant for the given path, so that equivalent URLs
+    /// canonicalize to the same string. The input is a path with an
+    /// optional query string, e.g. `/forum/t.php?id=1&sid=abc`.
+    pub fn canonicalize(&self, path: &str) -> String {
+        clean_param::canonicalize(&self.clean_params, path)
+    }
+
+    /// Directives that Cylon does not interpret, from before the first group
+    /// and from the group matching the user agent, in file order.
+    pub fn unknown_directives(&self) -> &[UnknownDirective] {
+        &self.unknown_directives
+    }
+
+    /// The values of every unknown directive with the given name, which is
+    /// matched case-insensitively.
+    pub fn unknown_values<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.unknown_directives
+            .iter()
+            .filter(move |d| d.key().eq_ignore_ascii_case(key))
+            .map(|d| d.value())
+    }
+
+    /// Parse every unknown directive named `D::NAME` with a custom parser,
+    /// skipping values the parser rejects.
+    pub fn directives<D: Directive>(&self) -> Vec<D> {
+        self.unknown_values(D::NAME).filter_map(D::parse).collect()
+    }
+
+    /// What the compiled file looked like. Unless it was
+    /// [`ContentKind::RobotsTxt`], the Cylon may not hold its rules but
+    /// the result of the Compiler's [`ContentPolicy`](crate::ContentPolicy)
+    /// for that kind of content.
+    pub fn content_kind(&self) -> ContentKind {
+        self.content_kind
+    }
+
+    pub(crate) fn set_content_kind(&mut self, content_kind: ContentKind) {
+        self.content_kind = content_kind;
+    }
+
+    pub(crate) fn set_unknown_directives(&mut self, unknown_directives: Vec<UnknownDirective>) {
+        self.unknown_directives = unknown_directives;
+    }
+
+    pub(crate) fn set_yandex(&mut self, host: Option<String>, clean_params: Vec<CleanParam>) {
+        self.host = host;
+        self.clean_params = clean_params;
+    }
+
+    /// The crawl delay requested by the matching group, if any. Fractional
+    /// delays such as `Crawl-delay: 0.5` are supported.
     #[cfg(feature = "crawl-delay")]
-    pub fn delay(&self) -> Option<u64> {
+    pub fn delay(&self) -> Option<Duration> {
         self.delay
     }
 
+    /// The raw value of a `Crawl-delay` directive in the matching group that
+    /// could not be parsed as a number of seconds, if any.
+    #[cfg(feature = "crawl-delay")]
+    pub fn invalid_delay(&self) -> Option<&str> {
+        self.invalid_delay.as_deref()
+    }
+
+    /// The request rates declared by the matching group, in file order.
+    pub fn request_rates(&self) -> &[RequestRate] {
+        &self.request_rates
+    }
+
+    /// The most restrictive request rate that applies at the given
+    /// timestamp, if any.
+    pub fn request_rate_at(&self, time: SystemTime) -> Option<&RequestRate> {
+        self.request_rates
+            .iter()
+            .filter(|r| r.applies_at(time))
+            .max_by_key(|r| r.interval())
+    }
+
+    /// The windows of the day (in UTC) during which the matching group
+    /// permits visits. An empty list means visits are permitted at any time.
+    pub fn visit_times(&self) -> &[VisitTime] {
+        &self.visit_times
+    }
+
+    /// Whether the matching group permits crawling at the given timestamp,
+    /// according to its `Visit-time` directives.
+    pub fn visit_allowed_at(&self, time: SystemTime) -> bool {
+        self.visit_times.is_empty() || self.visit_times.iter().any(|v| v.contains(time))
+    }
+
+    pub(crate) fn set_rates(
+        &mut self,
+        request_rates: Vec<RequestRate>,
+        visit_times: Vec<VisitTime>,
+    ) {
+        self.request_rates = request_rates;
+        self.visit_times = visit_times;
+    }
+
+    #[cfg(feature = "crawl-delay")]
+    pub(crate) fn set_delay(&mut self, delay: Option<Duration>, invalid_delay: Option<String>) {
+        self.delay = delay;
+        self.invalid_delay = invalid_delay;
+    }
+
     /// Match whether the rules allow or disallow the target path.
     pub fn allow<T: AsRef<[u8]>>(&self, path: T) -> bool {
-        let path = path.as_ref();
-        let path = if path.is_empty() { b"/" } else { path };
-        let mut current_states = BTreeSet::new();
-        current_states.insert(0);
+        matches(self, path.as_ref())
+    }
 
-        for edge in path {
-            let mut next_states = BTreeSet::new();
+    /// Match whether the rules allow or disallow the target path after
+    /// normalizing it, so that e.g. `/a/./b` gets the same decision as
+    /// `/a/b`.
+    pub fn allow_normalized(&self, path: &str, normalizer: &PathNormalizer) -> bool {
+        self.allow(normalizer.normalize(path).as_bytes())
+    }
 
-            for s in current_states {
-                if let Some(state) = self.states.get(s) {
-                    next_states.extend(state.follow_edges(*edge, s));
-                }
+    /// Match whether the rules allow or disallow a full URL, such as
+    /// `https://example.com/a?b#c`. Only its path and query are matched,
+    /// extracted the same way as Google's parser does.
+    pub fn allow_url(&self, url: &str) -> bool {
+        self.allow(url::path_params_query(url).as_bytes())
+    }
+
+    /// Combine with another Cylon so that a path is allowed only if both
+    /// allow it. Useful to layer your own policy on top of a site's rules:
+    ///
+    /// ```
+    /// use cylon::CylonBuilder;
+    ///
+    /// let robots = CylonBuilder::new().disallow("/private").build().unwrap();
+    /// let policy = CylonBuilder::new().disallow("/logout").build().unwrap();
+    ///
+    /// let cylon = robots.intersect(&policy);
+    /// assert!(cylon.allow("/index.html"));
+    /// assert!(!cylon.allow("/private"));
+    /// assert!(!cylon.allow("/logout"));
+    /// ```
+    ///
+    /// The result still matches a path in a single pass. Metadata such as
+    /// sitemaps and crawl delays is kept from `self`.
+    pub fn intersect(&self, other: &Cylon) -> Cylon {
+        self.combine(other, Instruction::Intersect)
+    }
+
+    /// Combine with another Cylon so that a path is allowed if either
+    /// allows it. Metadata is kept from `self`.
+    pub fn union(&self, other: &Cylon) -> Cylon {
+        self.combine(other, Instruction::Union)
+    }
+
+    /// Combine with another Cylon whose decision takes priority wherever
+    /// one of its rules matches the path, falling back to `self`
+    /// elsewhere. Metadata is kept from `self`.
+    pub fn override_with(&self, other: &Cylon) -> Cylon {
+        self.combine(other, Instruction::Override)
+    }
+
+    fn combine(&self, other: &Cylon, op: Instruction) -> Cylon {
+        let offset = self.states.len();
+        let mut combined = self.clone();
+        combined
+            .states
+            .extend(other.states.iter().map(|n| n.offset(offset)));
+        combined.program = self.program().to_vec();
+        combined
+            .program
+            .extend(other.program().iter().map(|i| i.offset(offset)));
+        combined.program.push(op);
+        combined
+    }
+
+    fn program(&self) -> &[Instruction] {
+        if self.program.is_empty() {
+            SINGLE_PROGRAM
+        } else {
+            &self.program
+        }
+    }
+
+    /// Encode the compiled rules and metadata in a compact binary format.
+    /// Unlike the serde representation, the format is explicitly
+    /// versioned and checksummed, so it can be shared between builds of
+    /// different versions or features: [`Cylon::from_bytes`] either
+    /// decodes it faithfully or rejects it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the Cylon has more than `u32::MAX` states, or a list or
+    /// string longer than that, which the format can't represent.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+
+        // States are written as a fixed-width table of nodes followed by
+        // the edges and wildcards of every node. Each node records where
+        // its edges and wildcards end; they start where the previous
+        // node's ended.
+        w.len(self.states.len());
+        let (mut edges, mut wildcards) = (0, 0);
+        for node in &self.states {
+            edges += node.edges.len();
+            wildcards += node.wildcards.len();
+            w.u8(node.accept.to_byte());
+            w.len(node.weight);
+            w.len(edges);
+            w.len(wildcards);
+        }
+        for (edge, state) in self.states.iter().flat_map(|n| &n.edges) {
+            w.u8(*edge);
+            w.len(*state);
+        }
+        for state in self.states.iter().flat_map(|n| &n.wildcards) {
+            w.len(*state);
+        }
+
+        w.len(self.program().len());
+        for op in self.program() {
+            op.write(&mut w);
+        }
+
+        w.len(self.sitemaps.len());
+        for sitemap in &self.sitemaps {
+            w.str(sitemap);
+        }
+        w.option(self.host.as_deref(), Writer::str);
+        w.len(self.clean_params.len());
+        for clean_param in &self.clean_params {
+            w.len(clean_param.params().len());
+            for param in clean_param.params() {
+                w.str(param);
             }
+            w.str(clean_param.prefix());
+        }
+        w.len(self.unknown_directives.len());
+        for directive in &self.unknown_directives {
+            w.str(directive.key());
+            w.str(directive.value());
+            w.u8(match directive.scope() {
+                Scope::Global => 0,
+                Scope::Group => 1,
+            });
+        }
+        w.u8(self.content_kind.to_byte());
+        w.len(self.request_rates.len());
+        for rate in &self.request_rates {
+            w.u32(rate.requests());
+            w.duration(rate.period());
+            w.option(rate.window(), write_visit_time);
+        }
+        w.len(self.visit_times.len());
+        for visit_time in &self.visit_times {
+            write_visit_time(&mut w, *visit_time);
+        }
+
+        #[cfg(feature = "crawl-delay")]
+        {
+            w.option(self.delay, Writer::duration);
+            w.option(self.invalid_delay.as_deref(), Writer::str);
+            w.finish(FLAG_CRAWL_DELAY)
+        }
+        #[cfg(not(feature = "crawl-delay"))]
+        w.finish(0)
+    }
 
-            current_states = next_states;
+    /// Decode a Cylon written by [`Cylon::to_bytes`]. Data that was
+    /// written by an incompatible version, uses features this build was
+    /// compiled without, or is corrupt is rejected.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        let (mut r, flags) = Reader::open(data)?;
+
+        let len = r.len(13)?;
+        let mut states = Vec::with_capacity(len);
+        let mut ends = Vec::with_capacity(len);
+        for _ in 0..len {
+            let accept =
+                Accept::from_byte(r.u8()?).ok_or(DecodeError::Invalid("bad accept state"))?;
+            let weight = r.u32()? as usize;
+            states.push(Node::new(accept, weight));
+            ends.push((r.u32()? as usize, r.u32()? as usize));
+        }
+        let state = |r: &mut Reader| match r.u32()? as usize {
+            s if s < len => Ok(s),
+            _ => Err(DecodeError::Invalid("state out of range")),
+        };
+        let mut start = 0;
+        for (node, (end, _)) in states.iter_mut().zip(&ends) {
+            let count = end
+                .checked_sub(start)
+                .ok_or(DecodeError::Invalid("bad edge table"))?;
+            for _ in 0..count {
+                let edge = r.u8()?;
+                node.add_edge(edge, state(&mut r)?);
+            }
+            start = *end;
+        }
+        let mut start = 0;
+        for (node, (_, end)) in states.iter_mut().zip(&ends) {
+            let count = end
+                .checked_sub(start)
+                .ok_or(DecodeError::Invalid("bad wildcard table"))?;
+            for _ in 0..count {
+                node.add_wildcard(state(&mut r)?);
+            }
+            start = *end;
         }
 
-        let best_match = current_states
-            .into_iter()
-            .flat_map(|s| self.states.get(s))
-            .max_by_key(|n| n.normalized_weight());
+        let len = r.len(5)?;
+        let program = (0..len)
+            .map(|_| Instruction::read(&mut r))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !valid_program(&program, states.len()) {
+            return Err(DecodeError::Invalid("bad program").into());
+        }
 
-        match best_match {
-            Some(state) => state.allow(),
-            None => true,
+        let len = r.len(4)?;
+        let sitemaps = (0..len)
+            .map(|_| r.str().map(String::from))
+            .collect::<Result<_, _>>()?;
+        let host = r.option(|r| r.str().map(String::from))?;
+        let len = r.len(8)?;
+        let mut clean_params = Vec::with_capacity(len);
+        for _ in 0..len {
+            let count = r.len(4)?;
+            let params = (0..count)
+                .map(|_| r.str().map(String::from))
+                .collect::<Result<_, _>>()?;
+            clean_params.push(CleanParam::new(params, r.str()?.into()));
+        }
+        let len = r.len(9)?;
+        let mut unknown_directives = Vec::with_capacity(len);
+        for _ in 0..len {
+            let key = r.str()?.into();
+            let value = r.str()?.into();
+            let scope = match r.u8()? {
+                0 => Scope::Global,
+                1 => Scope::Group,
+                _ => return Err(DecodeError::Invalid("bad scope").into()),
+            };
+            unknown_directives.push(UnknownDirective::new(key, value, scope));
         }
+        let content_kind =
+            ContentKind::from_byte(r.u8()?).ok_or(DecodeError::Invalid("bad content kind"))?;
+        let len = r.len(13)?;
+        let mut request_rates = Vec::with_capacity(len);
+        for _ in 0..len {
+            let requests = r.u32()?;
+            let period = r.duration()?;
+            let window = r.option(read_visit_time)?;
+            let rate = RequestRate::new(requests, period, window)
+                .ok_or(DecodeError::Invalid("bad request rate"))?;
+            request_rates.push(rate);
+        }
+        let len = r.len(4)?;
+        let visit_times = (0..len)
+            .map(|_| read_visit_time(&mut r))
+            .collect::<Result<_, _>>()?;
+
+        let cylon = Self {
+            states,
+            program,
+            sitemaps,
+            host,
+            clean_params,
+            unknown_directives,
+            content_kind,
+            #[cfg(feature = "crawl-delay")]
+            delay: None,
+            #[cfg(feature = "crawl-delay")]
+            invalid_delay: None,
+            request_rates,
+            visit_times,
+        };
+
+        #[cfg(feature = "crawl-delay")]
+        let cylon = match flags & FLAG_CRAWL_DELAY {
+            0 => cylon,
+            _ => cylon.read_crawl_delay(&mut r)?,
+        };
+        #[cfg(not(feature = "crawl-delay"))]
+        let _ = flags;
+
+        r.finish()?;
+        Ok(cylon)
+    }
+
+    #[cfg(feature = "crawl-delay")]
+    fn read_crawl_delay(mut self, r: &mut Reader) -> Result<Self, DecodeError> {
+        self.delay = r.option(Reader::duration)?;
+        self.invalid_delay = r.option(|r| r.str().map(String::from))?;
+        Ok(self)
     }
 
-    pub fn compile(mut rules: Vec<Rule>) -> Self {
-        let mut first = Node::new(Accept::Allow, 0);
-        let second = Node::new(Accept::Allow, 0);
+    /// A Cylon that allows every path, for when there is no robots.txt.
+    pub(crate) fn allow_all() -> Self {
+        Self::compile(vec![])
+    }
+
+    /// A Cylon that disallows every path, for when the robots.txt file is
+    /// unreachable. The robots.txt file itself stays allowed, so that it
+    /// can be fetched again.
+    pub(crate) fn disallow_all() -> Self {
+        let rules = vec![Rule::Disallow(b"/")];
+        Self::compile_with(
+            rules,
+            MatchSemantics::default(),
+            CaseFolding::default(),
+            true,
+        )
+    }
+
+    pub fn compile(rules: Vec<Rule>) -> Self {
+        let (semantics, folding) = (MatchSemantics::default(), CaseFolding::default());
+        Self::compile_with(rules, semantics, folding, false)
+    }
+
+    /// Compile rules with the precedence and pattern syntax of the given
+    /// semantics, folding case as requested. For
+    /// [`MatchSemantics::Original`] the rules must be in the order they
+    /// appear in the file, duplicates included.
+    ///
+    /// With `allow_robots_txt`, `/robots.txt` is allowed whatever the rules
+    /// say, as RFC 9309 requires.
+    pub(crate) fn compile_with(
+        rules: Vec<Rule>,
+        semantics: MatchSemantics,
+        folding: CaseFolding,
+        allow_robots_txt: bool,
+    ) -> Self {
+        let special_characters = semantics.special_characters();
+        let count = rules.len();
+        let mut classes = vec![];
+        let mut patterns: Vec<Pattern> = rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| !rule.inner().is_empty())
+            .map(|(i, rule)| {
+                let (accept, allow) = match rule {
+                    Rule::Allow(..) => (Accept::Allow, true),
+                    Rule::Disallow(..) => (Accept::Disallow, false),
+                };
+                Pattern {
+                    symbols: symbols(rule.inner(), special_characters, folding, &mut classes),
+                    accept,
+                    weight: semantics.weight(rule.inner(), allow, i, count),
+                }
+            })
+            .collect();
+        if allow_robots_txt && patterns.iter().any(|p| p.accept == Accept::Disallow) {
+            let weight = patterns.iter().map(|p| p.weight + 1).max().unwrap_or(0);
+            let mut robots_txt = symbols(ROBOTS_TXT, false, folding, &mut classes);
+            robots_txt.push(Symbol::EndOfWord);
+            patterns.push(Pattern {
+                symbols: robots_txt,
+                accept: Accept::Allow,
+                weight,
+            });
+        }
+        // The sort is stable, so the rules of each pattern stay in order.
+        patterns.sort_by(|a, b| {
+            let key = |p: &Pattern| p.symbols.iter().map(|s| s.sort_key()).collect::<Vec<_>>();
+            key(a).cmp(&key(b))
+        });
+
+        let mut first = Node::new(Accept::Unmatched, 0);
+        let second = Node::new(Accept::Unmatched, 0);
         first.add_wildcard(1);
 
         let mut states: Vec<Node> = vec![first, second];
         let mut queue = VecDeque::new();
         queue.push_back(QueueItem::default());
-        rules.sort_by(|a, b| Ord::cmp(a.inner(), b.inner()));
 
         while let Some(QueueItem {
             parent_prefix,
             parent_state,
             epsilon_state,
+            mut inherited,
         }) = queue.pop_front()
         {
-            let mut last_prefix: &[u8] = b"";
-            for rule in rules.iter() {
-                let prefix = match rule.inner().get(..=parent_prefix.len()) {
+            let mut last_prefix: &[Symbol] = &[];
+            for (i, pattern) in patterns.iter().enumerate() {
+                let prefix = match pattern.symbols.get(..=parent_prefix.len()) {
                     None => continue,
                     Some(prefix) if !prefix.starts_with(parent_prefix) => continue,
                     Some(prefix) if last_prefix == prefix => continue,
                     Some(prefix) => prefix,
                 };
 
-                let is_terminal = prefix == rule.inner();
-                let accept_state = match rule {
-                    Rule::Allow(..) if is_terminal => Accept::Allow,
-                    Rule::Disallow(..) if is_terminal => Accept::Disallow,
-                    #[cfg(feature = "crawl-delay")]
-                    Rule::Delay(..) if is_terminal => Accept::Allow,
-                    _ => states.get(parent_state).unwrap().accept,
+                // Patterns that end here sort before the ones they are a
+                // prefix of, and the best of them applies.
+                let is_terminal = prefix == pattern.symbols.as_slice();
+                let accept_state = match is_terminal {
+                    true => patterns[i..]
+                        .iter()
+                        .take_while(|p| p.symbols == prefix)
+                        .map(|p| (p.accept, p.weight))
+                        .fold(inherited, best),
+                    false => inherited,
                 };
 
                 let state = states.len();
-                let edge = *prefix.last().unwrap();
-                let parent_edge = parent_prefix.last();
+                let symbol = *prefix.last().unwrap();
+                let parent_symbol = parent_prefix.last();
                 let parent_node = states.get_mut(parent_state).unwrap();
-                let mut child_node = Node::new(accept_state, prefix.len());
+                let mut child_node = Node::new(accept_state.0, accept_state.1);
                 let mut wildcard_node = None;
-                let mut queue_item = QueueItem::new(prefix, state);
+                let mut queue_item = QueueItem::new(prefix, state, accept_state);
+                // The edges into the child, which an epsilon transition
+                // copies to the grandparent.
+                let mut entries = vec![];
 
-                match edge {
-                    WILDCARD_BYTE if parent_edge != Some(&WILDCARD_BYTE) => {
+                match symbol {
+                    Symbol::Wildcard if parent_symbol != Some(&Symbol::Wildcard) => {
                         child_node.add_wildcard(state);
                         parent_node.add_wildcard(state);
 
                         if is_terminal {
-                            // If this is a terminal node with a different accept
-                            // state than its parent, then the parent should
-                            // inherit this accept state since the wildcard also
-                            // matches its parent.
-                            parent_node.accept = accept_state
+                            // If this is a terminal node, then the parent
+                            // matches its rule too, since the wildcard can
+                            // match nothing, and so do the parent's other
+                            // children.
+                            keep_best(parent_node, accept_state);
+                            inherited = best(inherited, accept_state);
                         }
 
                         for (_, e) in parent_node.edges.clone() {
@@ -260,26 +1182,43 @@ impl Cylon {
                         // node's children.
                         queue_item.epsilon_state = Some(parent_state);
                     }
-                    WILDCARD_BYTE => {
+                    Symbol::Wildcard => {
                         // Avoid the extremely inefficient degenerate case of multiple
                         // repeated wildcard characters by simply ignoring them.
+                        if is_terminal {
+                            keep_best(parent_node, accept_state);
+                            if let Some(grandparent_state) = epsilon_state {
+                                keep_best(&mut states[grandparent_state], accept_state);
+                            }
+                            inherited = best(inherited, accept_state);
+                        }
                         last_prefix = prefix;
                         queue_item.parent_state = parent_state;
+                        queue_item.epsilon_state = epsilon_state;
+                        queue_item.inherited = inherited;
                         queue.push_back(queue_item);
                         continue;
                     }
-                    EOW_BYTE => {
+                    Symbol::EndOfWord => {
                         parent_node.add_wildcard(state);
-                        // Swap the accept states of the parent node, which was
-                        // not treated as a terminal state and inherited its parent's.
-                        // If we match beyond the EOW we should use the grandparent
-                        // accept state instead since that was technically the last match.
-                        child_node.accept = parent_node.accept;
-                        parent_node.accept = accept_state;
-                        parent_node.weight = child_node.weight;
+                        // The parent now matches the anchored rule. If we
+                        // match beyond the EOW only the rules the parent
+                        // inherited apply, since they were technically the
+                        // last match.
+                        child_node.accept = inherited.0;
+                        child_node.weight = inherited.1;
+                        keep_best(parent_node, accept_state);
+                        if let Some(grandparent_state) = epsilon_state {
+                            keep_best(&mut states[grandparent_state], accept_state);
+                        }
                     }
-                    edge => {
-                        parent_node.add_edge(edge, state);
+                    Symbol::Byte(..) | Symbol::Class(..) => {
+                        if let Symbol::Byte(byte) = symbol {
+                            for edge in cases(byte, folding) {
+                                parent_node.add_edge(edge, state);
+                                entries.push((edge, state));
+                            }
+                        }
                         for e in parent_node.wildcards.iter() {
                             // Inherit the parent's wildcard transitions. If
                             // we cannot match more characters we should jump
@@ -292,17 +1231,12 @@ impl Cylon {
                         if is_terminal {
                             // Any characters after a terminal node should jump to a state
                             // that has no transitions except for a wildcard self-loop.
-                            wildcard_node = Some(Node::new(accept_state, prefix.len()));
+                            wildcard_node = Some(Node::new(accept_state.0, accept_state.1));
                             child_node.add_wildcard(state + 1);
                         }
                     }
                 }
 
-                if let Some(grandparent_state) = epsilon_state {
-                    let grandparent_node = states.get_mut(grandparent_state).unwrap();
-                    grandparent_node.add_edge(edge, state);
-                }
-
                 last_prefix = prefix;
                 states.push(child_node);
                 queue.push_back(queue_item);
@@ -310,43 +1244,178 @@ impl Cylon {
                 if let Some(wildcard_node) = wildcard_node {
                     states.push(wildcard_node);
                 }
+                if let Symbol::Class(class) = symbol {
+                    for variant in &classes[class] {
+                        let chain = add_chain(
+                            &mut states,
+                            parent_state,
+                            state,
+                            variant,
+                            folding,
+                            inherited,
+                        );
+                        entries.extend(chain);
+                    }
+                }
+
+                if let Some(grandparent_state) = epsilon_state {
+                    let grandparent_node = states.get_mut(grandparent_state).unwrap();
+                    for (edge, state) in entries {
+                        grandparent_node.add_edge(edge, state);
+                    }
+                }
             }
         }
 
-        #[cfg(feature = "crawl-delay")]
-        {
-            let mut delays: Vec<Option<u64>> = rules
-                .iter()
-                .filter(|rule| match rule {
-                    Rule::Delay(_) => true,
-                    _ => false,
-                })
-                .map(|r| r.inner())
-                .flat_map(|r| std::str::from_utf8(r).ok())
-                .map(|r| r.parse::<u64>().ok())
-                .collect();
-            delays.sort_unstable_by(|a, b| match (a, b) {
-                (None, Some(_)) => Ordering::Greater,
-                (Some(_), None) => Ordering::Less,
-                (None, None) => Ordering::Equal,
-                (Some(aa), Some(bb)) => aa.cmp(bb),
-            });
-            Self {
-                delay: *delays.get(0).unwrap_or(&None),
-                states,
-            }
+        Self {
+            states,
+            program: vec![],
+            sitemaps: vec![],
+            host: None,
+            clean_params: vec![],
+            unknown_directives: vec![],
+            content_kind: ContentKind::default(),
+            #[cfg(feature = "crawl-delay")]
+            delay: None,
+            #[cfg(feature = "crawl-delay")]
+            invalid_delay: None,
+            request_rates: vec![],
+            visit_times: vec![],
         }
+    }
+}
 
-        #[cfg(not(feature = "crawl-delay"))]
-        Self { states }
+/// The states and program of a compiled Cylon, whether they are owned or
+/// read in place from encoded bytes.
+pub(crate) trait Automaton {
+    /// The accept state and weight of a state.
+    fn node(&self, state: usize) -> Option<(Accept, usize)>;
+
+    /// Add the states reached from a state by consuming the given byte.
+    fn follow_edges(&self, state: usize, edge: u8, next: &mut BTreeSet<usize>);
+
+    /// The instruction at the given position of the program.
+    fn instruction(&self, i: usize) -> Option<Instruction>;
+}
+
+impl Automaton for Cylon {
+    fn node(&self, state: usize) -> Option<(Accept, usize)> {
+        self.states.get(state).map(|n| (n.accept, n.weight))
+    }
+
+    fn follow_edges(&self, state: usize, edge: u8, next: &mut BTreeSet<usize>) {
+        if let Some(node) = self.states.get(state) {
+            next.extend(node.follow_edges(edge, state));
+        }
+    }
+
+    fn instruction(&self, i: usize) -> Option<Instruction> {
+        self.program().get(i).copied()
     }
 }
 
+/// Run the automaton over the path, matching every rule set in a single
+/// pass, and combine their decisions with the program.
+pub(crate) fn matches<A: Automaton>(automaton: &A, path: &[u8]) -> bool {
+    let path = if path.is_empty() { b"/" } else { path };
+    let program = (0..).map_while(|i| automaton.instruction(i));
+    let starts: Vec<usize> = program
+        .clone()
+        .filter_map(|op| match op {
+            Instruction::Match(start) => Some(start),
+            _ => None,
+        })
+        .collect();
+    let mut current_states: BTreeSet<usize> = starts.iter().copied().collect();
+
+    for edge in path {
+        let mut next_states = BTreeSet::new();
+
+        for s in current_states {
+            automaton.follow_edges(s, *edge, &mut next_states);
+        }
+
+        current_states = next_states;
+    }
+
+    // The rule sets occupy consecutive ranges of states, so each
+    // state belongs to the last rule set starting at or before it.
+    let mut best_matches: Vec<Option<(usize, Accept)>> = vec![None; starts.len()];
+    for s in current_states {
+        let Some((accept, weight)) = automaton.node(s) else {
+            continue;
+        };
+        let Some(layer) = starts.partition_point(|start| *start <= s).checked_sub(1) else {
+            continue;
+        };
+        let weight = accept.normalized_weight(weight);
+        let best = &mut best_matches[layer];
+        if best.is_none_or(|(w, _)| weight >= w) {
+            *best = Some((weight, accept));
+        }
+    }
+
+    let mut decisions = best_matches
+        .into_iter()
+        .map(|n| n.and_then(|(_, accept)| accept.decision()));
+    let mut stack: Vec<Option<bool>> = vec![];
+    for op in program {
+        if let Instruction::Match(..) = op {
+            stack.push(decisions.next().flatten());
+            continue;
+        }
+        let (Some(second), Some(first)) = (stack.pop(), stack.pop()) else {
+            break;
+        };
+        stack.push(op.apply(first, second));
+    }
+
+    stack.pop().flatten().unwrap_or(true)
+}
+
+fn write_visit_time(w: &mut Writer, visit_time: VisitTime) {
+    let (start, end) = (visit_time.start(), visit_time.end());
+    for v in [start.0, start.1, end.0, end.1] {
+        w.u8(v);
+    }
+}
+
+fn read_visit_time(r: &mut Reader) -> Result<VisitTime, DecodeError> {
+    let start = (r.u8()?, r.u8()?);
+    let end = (r.u8()?, r.u8()?);
+    VisitTime::new(start, end).ok_or(DecodeError::Invalid("bad visit time"))
+}
+
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
     macro_rules! n {
+        ('u' $x:literal $e:expr) => {
+            Node {
+                accept: Accept::Unmatched,
+                edges: $e,
+                weight: $x,
+                wildcards: vec![],
+            }
+        };
+        ('u' $x:literal $w:literal, $e:expr) => {
+            Node {
+                accept: Accept::Unmatched,
+                edges: $e,
+                weight: $x,
+                wildcards: vec![$w],
+            }
+        };
+        ('u' $x:literal $w:expr, $e:expr) => {
+            Node {
+                accept: Accept::Unmatched,
+                edges: $e,
+                weight: $x,
+                wildcards: $w,
+            }
+        };
         ('a' $x:literal $e:expr) => {
             Node {
                 accept: Accept::Allow,
@@ -449,13 +1518,13 @@ mod tests {
         ];
 
         let expect_nodes = vec![
-            n!('a' 0 1, vec![(b!('/'), 2)]),                        // ''
-            n!('a' 0 vec![]),                                       // '' wildcard
-            n!('a' 1 1, vec![(b!('a'), 3)]),                        // '/'
+            n!('u' 0 1, vec![(b!('/'), 2)]),                        // ''
+            n!('u' 0 vec![]),                                       // '' wildcard
+            n!('u' 0 1, vec![(b!('a'), 3)]),                        // '/'
             n!('a' 2 vec![4, 5], vec![(b!('b'), 6), (b!('c'), 7)]), // '/a'
             n!('a' 2 vec![]),                                       // '/a' wildcard
-            n!('a' 3 5, vec![(b!('c'), 7)]),                        // '/a*'
-            n!('a' 3 vec![4, 5], vec![(b!('c'), 9)]),               // '/ab'
+            n!('a' 2 5, vec![(b!('c'), 7)]),                        // '/a*'
+            n!('a' 2 vec![4, 5], vec![(b!('c'), 9)]),               // '/ab'
             n!('a' 4 8, vec![]),                                    // '/a*c'
             n!('a' 4 vec![]),                                       // '/a*c' wildcard
             n!('d' 4 10, vec![]),                                   // '/abc'
@@ -479,12 +1548,12 @@ mod tests {
         ];
 
         let expect_nodes = vec![
-            n!('a' 0 1, vec![(b!('/'), 2)]),          // ''
-            n!('a' 0 vec![]),                         // '' wildcard
-            n!('a' 1 1, vec![(b!('a'), 3)]),          // '/'
+            n!('u' 0 1, vec![(b!('/'), 2)]),          // ''
+            n!('u' 0 vec![]),                         // '' wildcard
+            n!('u' 0 1, vec![(b!('a'), 3)]),          // '/'
             n!('d' 3 vec![4, 5], vec![(b!('b'), 6)]), // '/a$'
             n!('a' 2 vec![]),                         // '/a' wildcard
-            n!('a' 3 vec![]),                         // '/a$' wildcard
+            n!('a' 2 vec![]),                         // '/a$' wildcard
             n!('d' 3 7, vec![]),                      // '/ab'
             n!('d' 3 vec![]),                         // '/ab' wildcard
         ];
@@ -498,9 +1567,9 @@ mod tests {
         let rules = vec![Rule::Allow(b"/****************************")];
 
         let expect_nodes = vec![
-            n!('a' 0 1, vec![(b!('/'), 2)]), // ''
-            n!('a' 0 vec![]),                // '' wildcard
-            n!('a' 1 vec![1, 3], vec![]),    // '/'
+            n!('u' 0 1, vec![(b!('/'), 2)]), // ''
+            n!('u' 0 vec![]),                // '' wildcard
+            n!('a' 2 vec![1, 3], vec![]),    // '/'
             n!('a' 2 3, vec![]),             // '/*'
         ];
 
@@ -558,6 +1627,117 @@ mod tests {
         assert_eq!(true, machine.allow("/abd"));
     }
 
+    #[test]
+    fn test_repeated_wildcard() {
+        // The rule ends in the second `*`, which is merged into the first.
+        let rules = vec![Rule::Disallow(b"/"), Rule::Allow(b"/a**")];
+
+        let machine = Cylon::compile(rules);
+        assert_eq!(true, machine.allow("/a"));
+        assert_eq!(true, machine.allow("/ab"));
+        assert_eq!(false, machine.allow("/b"));
+    }
+
+    #[test]
+    fn test_unmatched_weight() {
+        // A path that only partly matches a rule doesn't outweigh another
+        // rule that it matches.
+        let rules = vec![Rule::Disallow(b"/*c"), Rule::Allow(b"/abcd")];
+
+        let machine = Cylon::compile(rules);
+        assert_eq!(false, machine.allow("/abc"));
+        assert_eq!(true, machine.allow("/abcd"));
+    }
+
+    #[test]
+    fn test_inherited_weight() {
+        // Past the end of `/a` its weight stays 2, so `/*b` wins on `/ab`.
+        let rules = vec![
+            Rule::Allow(b"/a"),
+            Rule::Disallow(b"/*b"),
+            Rule::Disallow(b"/abcd"),
+        ];
+
+        let machine = Cylon::compile(rules);
+        assert_eq!(true, machine.allow("/a"));
+        assert_eq!(false, machine.allow("/ab"));
+        assert_eq!(true, machine.allow("/ac"));
+    }
+
+    #[test]
+    fn test_eow_not_inherited() {
+        // Only the path `/a` matches `/a$`, not the paths beyond it.
+        let rules = vec![
+            Rule::Allow(b"/a"),
+            Rule::Disallow(b"/a$"),
+            Rule::Disallow(b"/abcd"),
+        ];
+
+        let machine = Cylon::compile(rules);
+        assert_eq!(false, machine.allow("/a"));
+        assert_eq!(true, machine.allow("/ab"));
+        assert_eq!(true, machine.allow("/abc"));
+        assert_eq!(false, machine.allow("/abcd"));
+    }
+
+    #[test]
+    fn test_eow_weight() {
+        // Beyond `/a$`, only `/a` with its own weight matches.
+        let rules = vec![
+            Rule::Allow(b"/a"),
+            Rule::Disallow(b"/a$"),
+            Rule::Disallow(b"/*x"),
+        ];
+
+        let machine = Cylon::compile(rules);
+        assert_eq!(false, machine.allow("/a"));
+        assert_eq!(true, machine.allow("/ab"));
+        assert_eq!(false, machine.allow("/ax"));
+    }
+
+    #[test]
+    fn test_wildcard_weight() {
+        // The path `/a` matches `/a*` with its full weight.
+        let rules = vec![Rule::Allow(b"/a*"), Rule::Disallow(b"/*a")];
+
+        let machine = Cylon::compile(rules);
+        assert_eq!(true, machine.allow("/a"));
+        assert_eq!(false, machine.allow("/ba"));
+    }
+
+    #[test]
+    fn test_repeated_wildcard_matches_nothing() {
+        let rules = vec![Rule::Disallow(b"/**"), Rule::Allow(b"/a**b")];
+
+        let machine = Cylon::compile(rules);
+        assert_eq!(false, machine.allow("/"));
+        assert_eq!(false, machine.allow("/a"));
+        assert_eq!(true, machine.allow("/ab"));
+        assert_eq!(true, machine.allow("/axb"));
+    }
+
+    #[test]
+    fn test_wildcard_eow_matches_nothing() {
+        let rules = vec![Rule::Disallow(b"/a*$")];
+
+        let machine = Cylon::compile(rules);
+        assert_eq!(false, machine.allow("/a"));
+        assert_eq!(false, machine.allow("/a$"));
+        assert_eq!(false, machine.allow("/ab"));
+        assert_eq!(true, machine.allow("/b"));
+    }
+
+    #[test]
+    fn test_inner_eow() {
+        // Only a trailing `$` matches the end of the path.
+        let rules = vec![Rule::Disallow(b"/a$b")];
+
+        let machine = Cylon::compile(rules);
+        assert_eq!(true, machine.allow("/a"));
+        assert_eq!(false, machine.allow("/a$b"));
+        assert_eq!(false, machine.allow("/a$bc"));
+    }
+
     #[test]
     fn test_allow_match_any() {
         let rules = vec![
@@ -678,4 +1858,317 @@ mod tests {
         assert_eq!(true, machine.allow("/fishheads/catfish.php?parameters"));
         assert_eq!(false, machine.allow("/Fish.PHP"));
     }
+
+    #[test]
+    fn test_combine() {
+        let robots = Cylon::compile(vec![
+            Rule::Disallow(b"/private"),
+            Rule::Allow(b"/private/verified"),
+        ]);
+        let policy = Cylon::compile(vec![
+            Rule::Disallow(b"/logout"),
+            Rule::Allow(b"/private/ok"),
+        ]);
+
+        let test_cases = vec![
+            // path, intersect, union, override
+            ("/index.html", true, true, true),
+            ("/logout", false, true, false),
+            ("/private/a", false, true, false),
+            ("/private/ok", false, true, true),
+            ("/private/verified", true, true, true),
+        ];
+
+        let intersect = robots.intersect(&policy);
+        let union = robots.union(&policy);
+        let override_with = robots.override_with(&policy);
+        for (path, i, u, o) in test_cases {
+            assert_eq!(i, intersect.allow(path), "intersect {}", path);
+            assert_eq!(u, union.allow(path), "union {}", path);
+            assert_eq!(o, override_with.allow(path), "override {}", path);
+        }
+    }
+
+    #[test]
+    fn test_combine_nested() {
+        let robots = Cylon::compile(vec![Rule::Disallow(b"/")]);
+        let never = Cylon::compile(vec![Rule::Disallow(b"/logout")]);
+        let always = Cylon::compile(vec![Rule::Allow(b"/robots-verified/")]);
+
+        let cylon = robots.override_with(&always).intersect(&never);
+        assert_eq!(false, cylon.allow("/"));
+        assert_eq!(false, cylon.allow("/logout"));
+        assert_eq!(true, cylon.allow("/robots-verified/page"));
+
+        let cylon = robots.override_with(&never.override_with(&always));
+        assert_eq!(false, cylon.allow("/index.html"));
+        assert_eq!(true, cylon.allow("/robots-verified/page"));
+
+        // An override that matches nothing leaves the decision unchanged.
+        let cylon = always.override_with(&never.intersect(&never));
+        assert_eq!(true, cylon.allow("/robots-verified/"));
+        assert_eq!(true, cylon.allow("/index.html"));
+        assert_eq!(false, cylon.allow("/logout"));
+    }
+
+    #[test]
+    fn test_override_wildcard() {
+        let robots = Cylon::compile(vec![Rule::Allow(b"/")]);
+        let policy = Cylon::compile(vec![Rule::Disallow(b"*.pdf$")]);
+
+        let cylon = robots.override_with(&policy);
+        assert_eq!(true, cylon.allow("/index.html"));
+        assert_eq!(false, cylon.allow("/docs/a.pdf"));
+    }
+
+    #[test]
+    #[cfg(feature = "crawl-delay")]
+    fn test_serde_delay() {
+        // A Cylon serialized before fractional delays were supported.
+        let old = r#"{"states":[{"accept":"Allow","edges":[[47,2]],"weight":0,"wildcards":[1]},{"accept":"Allow","edges":[],"weight":0,"wildcards":[]},{"accept":"Allow","edges":[[97,3]],"weight":1,"wildcards":[1]},{"accept":"Disallow","edges":[],"weight":2,"wildcards":[4]},{"accept":"Disallow","edges":[],"weight":2,"wildcards":[]}],"delay":3}"#;
+        let cylon: Cylon = serde_json::from_str(old).unwrap();
+        assert_eq!(Some(Duration::from_secs(3)), cylon.delay());
+        assert_eq!(false, cylon.allow("/a"));
+        assert_eq!(true, cylon.allow("/b"));
+
+        let value = serde_json::to_value(&cylon).unwrap();
+        assert_eq!(serde_json::json!(3), value["delay"]);
+
+        let test_cases = vec![
+            None,
+            Some(Duration::from_secs(3)),
+            Some(Duration::from_millis(500)),
+        ];
+        for delay in test_cases {
+            let mut cylon = Cylon::compile(vec![]);
+            cylon.set_delay(delay, None);
+            let json = serde_json::to_string(&cylon).unwrap();
+            let cylon: Cylon = serde_json::from_str(&json).unwrap();
+            assert_eq!(delay, cylon.delay(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut cylon = Cylon::compile(vec![
+            Rule::Disallow(b"/"),
+            Rule::Allow(b"/fish*.php"),
+            Rule::Disallow(b"/fish$"),
+        ])
+        .override_with(&Cylon::compile(vec![Rule::Allow(b"/fish")]));
+        cylon.set_sitemaps(vec!["https://example.com/sitemap.xml".into()]);
+        cylon.set_yandex(
+            Some("example.com".into()),
+            vec![CleanParam::parse("ref&sid /forum/").unwrap()],
+        );
+        cylon.set_unknown_directives(vec![UnknownDirective::new(
+            "noindex".into(),
+            "/tmp".into(),
+            Scope::Group,
+        )]);
+        cylon.set_rates(
+            vec![RequestRate::parse("1/10s 0100-0600").unwrap()],
+            vec![VisitTime::parse("2200-0500").unwrap()],
+        );
+        #[cfg(feature = "crawl-delay")]
+        cylon.set_delay(Some(Duration::from_millis(1500)), Some("soon".into()));
+
+        let decoded = Cylon::from_bytes(&cylon.to_bytes()).unwrap();
+        assert_eq!(cylon.states, decoded.states);
+        assert_eq!(cylon.program(), decoded.program());
+        assert_eq!(cylon.sitemaps(), decoded.sitemaps());
+        assert_eq!(cylon.host(), decoded.host());
+        assert_eq!(cylon.clean_params(), decoded.clean_params());
+        assert_eq!(cylon.unknown_directives(), decoded.unknown_directives());
+        assert_eq!(cylon.request_rates(), decoded.request_rates());
+        assert_eq!(cylon.visit_times(), decoded.visit_times());
+        #[cfg(feature = "crawl-delay")]
+        {
+            assert_eq!(cylon.delay(), decoded.delay());
+            assert_eq!(cylon.invalid_delay(), decoded.invalid_delay());
+        }
+        assert_eq!(true, decoded.allow("/fish"));
+        assert_eq!(false, decoded.allow("/cat"));
+    }
+
+    #[test]
+    fn test_from_bytes_invalid() {
+        let cylon = Cylon::compile(vec![Rule::Disallow(b"/a")]);
+        let data = cylon.to_bytes();
+
+        assert!(matches!(
+            Cylon::from_bytes(b""),
+            Err(Error::Decode(DecodeError::BadMagic))
+        ));
+        assert!(matches!(
+            Cylon::from_bytes(&data[..data.len() - 4]),
+            Err(Error::Decode(DecodeError::Truncated))
+        ));
+
+        // A state pointing past the end of the table is rejected even with
+        // a valid checksum.
+        let mut w = Writer::default();
+        w.len(1);
+        w.u8(0);
+        w.len(0);
+        w.len(1);
+        w.len(0);
+        w.u8(b'/');
+        w.len(5);
+        assert!(matches!(
+            Cylon::from_bytes(&w.finish(0)),
+            Err(Error::Decode(DecodeError::Invalid("state out of range")))
+        ));
+    }
+
+    #[test]
+    fn test_from_bytes_bad_rate() {
+        // Rewrite part of the payload and checksum it again.
+        let patch = |data: &[u8], find: &[u8]| {
+            let flags = u16::from_le_bytes([data[6], data[7]]);
+            let mut payload = data[16..].to_vec();
+            let at = payload.windows(find.len()).position(|w| w == find);
+            let at = at.unwrap();
+            payload[at..at + find.len()].fill(0);
+            let mut w = Writer::default();
+            payload.into_iter().for_each(|b| w.u8(b));
+            w.finish(flags)
+        };
+
+        let mut cylon = Cylon::compile(vec![]);
+        let rate = RequestRate::parse("3735928559/4328719365s").unwrap();
+        cylon.set_rates(vec![rate], vec![]);
+        let data = cylon.to_bytes();
+        assert!(Cylon::from_bytes(&data).is_ok());
+
+        let no_requests = patch(&data, &0xDEAD_BEEF_u32.to_le_bytes());
+        let no_period = patch(&data, &0x01_0203_0405_u64.to_le_bytes());
+        for data in [no_requests, no_period] {
+            assert!(matches!(
+                Cylon::from_bytes(&data),
+                Err(Error::Decode(DecodeError::Invalid("bad request rate")))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_valid_program() {
+        use Instruction::*;
+
+        let test_cases = vec![
+            (vec![], false),
+            (vec![Match(0)], true),
+            (vec![Match(0), Match(2), Union], true),
+            (vec![Match(0), Match(2), Match(3), Union, Override], true),
+            (vec![Match(1)], false),
+            (vec![Match(0), Match(2)], false),
+            (vec![Match(0), Union], false),
+            (vec![Match(0), Match(0), Union], false),
+            (vec![Match(0), Match(4), Union], false),
+        ];
+
+        for (i, o) in test_cases {
+            assert_eq!(o, valid_program(&i, 4), "{:?}", i);
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let cylon = Cylon::compile(vec![
+            Rule::Allow(b"/a"),
+            Rule::Disallow(b"/abc"),
+            Rule::Allow(b"/a*c"),
+        ]);
+
+        let expect = CylonStats {
+            states: 11,
+            edges: 6,
+            wildcards: 9,
+            max_fan_out: 4,
+        };
+        assert_eq!(expect, cylon.stats());
+        assert_eq!(11, cylon.state_count());
+        assert_eq!(15, cylon.edge_count());
+    }
+
+    #[test]
+    fn test_heap_size() {
+        let small = Cylon::compile(vec![Rule::Disallow(b"/a")]);
+        let mut large = Cylon::compile(vec![
+            Rule::Disallow(b"/a"),
+            Rule::Allow(b"/a/b/c/d/e/f/g"),
+            Rule::Disallow(b"/*.php$"),
+        ]);
+        assert!(small.heap_size() >= small.state_count() * size_of::<Node>());
+        assert!(large.heap_size() > small.heap_size());
+
+        let before = large.heap_size();
+        large.set_sitemaps(vec!["https://example.com/sitemap.xml".into()]);
+        assert!(large.heap_size() >= before + 31);
+    }
+
+    #[test]
+    fn test_heap_size_capacity() {
+        let mut cylon = Cylon::compile(vec![Rule::Disallow(b"/a")]);
+        let before = cylon.heap_size();
+
+        let mut params = Vec::with_capacity(4);
+        let mut param = String::with_capacity(8);
+        param.push_str("ref");
+        params.push(param);
+        let mut prefix = String::with_capacity(16);
+        prefix.push('/');
+        let mut clean_params = Vec::with_capacity(2);
+        clean_params.push(CleanParam::new(params, prefix));
+        cylon.set_yandex(None, clean_params);
+
+        let clean_params = 2 * size_of::<CleanParam>() + 4 * size_of::<String>() + 8 + 16;
+        assert_eq!(before + clean_params, cylon.heap_size());
+
+        let mut key = String::with_capacity(32);
+        key.push_str("noindex");
+        let mut directives = Vec::with_capacity(3);
+        directives.push(UnknownDirective::new(
+            key,
+            String::with_capacity(64),
+            Scope::Global,
+        ));
+        cylon.set_unknown_directives(directives);
+
+        let directives = 3 * size_of::<UnknownDirective>() + 32 + 64;
+        assert_eq!(before + clean_params + directives, cylon.heap_size());
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let cylon = Cylon::compile(vec![Rule::Disallow(b"/a$")]);
+        let expect = r#"digraph cylon {
+    rankdir=LR;
+    start0 [shape=point];
+    start0 -> 0;
+    0 [label="0\nunmatched 0", color=gray];
+    1 [label="1\nunmatched 0", color=gray];
+    2 [label="2\nunmatched 0", color=gray];
+    3 [label="3\ndisallow 3", color=red];
+    4 [label="4\nunmatched 0", color=gray];
+    0 -> 2 [label="/"];
+    0 -> 1 [label="*", style=dashed];
+    2 -> 3 [label="a"];
+    2 -> 1 [label="*", style=dashed];
+    3 -> 1 [label="*", style=dashed];
+    3 -> 4 [label="*", style=dashed];
+}
+"#;
+        assert_eq!(expect, cylon.to_dot());
+
+        let cylon = Cylon::compile(vec![Rule::Allow(b"/\"\\\x01")]);
+        let dot = cylon.to_dot();
+        assert!(dot.contains(r#"[label="\""]"#), "{}", dot);
+        assert!(dot.contains(r#"[label="\\"]"#), "{}", dot);
+        assert!(dot.contains(r#"[label="\\x01"]"#), "{}", dot);
+
+        let dot = cylon.union(&cylon).to_dot();
+        assert!(dot.contains("start1 -> 7;"), "{}", dot);
+        assert!(dot.contains("// Union"), "{}", dot);
+    }
 }
@@ -0,0 +1,200 @@
+use std::borrow::Cow;
+
+/// Normalizes paths before they are matched, so that paths that resolve to
+/// the same resource get the same decision. Dot segments are removed as
+/// RFC 3986 describes, so `/a/./b` and `/a/c/../b` both become `/a/b`, and
+/// runs of slashes can optionally be collapsed.
+///
+/// ```
+/// use cylon::{CylonBuilder, PathNormalizer};
+///
+/// let cylon = CylonBuilder::new().disallow("/a/b").build().unwrap();
+/// let normalizer = PathNormalizer::new().with_collapse_slashes(true);
+///
+/// assert!(cylon.allow("/a/c/../b"));
+/// assert!(!cylon.allow_normalized("/a/c/../b", &normalizer));
+/// assert!(!cylon.allow_normalized("/a//b", &normalizer));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct PathNormalizer {
+    collapse_slashes: bool,
+}
+
+impl PathNormalizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also collapse runs of slashes into one, e.g. `/a//b` into `/a/b`.
+    /// Most servers treat them the same, but RFC 3986 doesn't, so this is
+    /// off by default.
+    pub fn with_collapse_slashes(mut self, collapse_slashes: bool) -> Self {
+        self.collapse_slashes = collapse_slashes;
+        self
+    }
+
+    /// Normalize a path, which may be followed by a query string. The
+    /// query string is left as it is.
+    pub fn normalize<'a>(&self, target: &'a str) -> Cow<'a, str> {
+        let (path, query) = match target.find('?') {
+            Some(i) => target.split_at(i),
+            None => (target, ""),
+        };
+        let has_dots = path.starts_with('.') || path.contains("/.") || path.contains("%2");
+        let has_slashes = self.collapse_slashes && path.contains("//");
+        if !has_dots && !has_slashes {
+            return Cow::Borrowed(target);
+        }
+
+        let mut path = decode_dot_segments(path);
+        if self.collapse_slashes {
+            while path.contains("//") {
+                path = path.replace("//", "/");
+            }
+        }
+        let mut normalized = remove_dot_segments(&path);
+        normalized.push_str(query);
+        Cow::Owned(normalized)
+    }
+}
+
+/// Decode `%2E`, an encoded `.` that RFC 3986 treats the same, in segments
+/// that are then `.` or `..`. Elsewhere it is left encoded, since `/a%2Eb`
+/// is only equivalent to `/a.b` if the server decodes it.
+fn decode_dot_segments(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            let decoded = segment.replace("%2E", ".").replace("%2e", ".");
+            if decoded == "." || decoded == ".." {
+                Cow::Owned(decoded)
+            } else {
+                Cow::Borrowed(segment)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The remove_dot_segments algorithm of RFC 3986, section 5.2.4.
+fn remove_dot_segments(mut input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    while !input.is_empty() {
+        if let Some(rest) = input
+            .strip_prefix("../")
+            .or_else(|| input.strip_prefix("./"))
+        {
+            input = rest;
+        } else if input.starts_with("/./") || input == "/." {
+            input = &input[2..];
+            if input.is_empty() {
+                input = "/";
+            }
+        } else if input.starts_with("/../") || input == "/.." {
+            input = &input[3..];
+            if input.is_empty() {
+                input = "/";
+            }
+            output.truncate(output.rfind('/').unwrap_or(0));
+        } else if input == "." || input == ".." {
+            input = "";
+        } else {
+            let first = input.chars().next().map_or(0, char::len_utf8);
+            let end = input[first..].find('/').map_or(input.len(), |i| i + first);
+            output.push_str(&input[..end]);
+            input = &input[end..];
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_dot_segments() {
+        let test_cases = vec![
+            // From RFC 3986, section 5.2.4.
+            ("/a/b/c/./../../g", "/a/g"),
+            ("mid/content=5/../6", "mid/6"),
+            ("/a/./b", "/a/b"),
+            ("/a/c/../b", "/a/b"),
+            ("/a/b/..", "/a/"),
+            ("/a/b/.", "/a/b/"),
+            ("/..", "/"),
+            ("/../../a", "/a"),
+            ("/a/..b/.c", "/a/..b/.c"),
+            ("/", "/"),
+            ("", ""),
+        ];
+
+        for (i, o) in test_cases {
+            assert_eq!(o, remove_dot_segments(i), "{}", i);
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        let normalizer = PathNormalizer::new();
+        let collapse = PathNormalizer::new().with_collapse_slashes(true);
+        let test_cases = vec![
+            ("/a/b", "/a/b", "/a/b"),
+            ("/a/./b", "/a/b", "/a/b"),
+            ("/a/%2E/b", "/a/b", "/a/b"),
+            ("/a/c/%2e%2E/b", "/a/b", "/a/b"),
+            ("/a/c/.%2e/b", "/a/b", "/a/b"),
+            ("/a%2Eb/%2e", "/a%2Eb/", "/a%2Eb/"),
+            ("/a/%2E%2E%2E/b", "/a/%2E%2E%2E/b", "/a/%2E%2E%2E/b"),
+            ("/a//b", "/a//b", "/a/b"),
+            ("//a///b/../c", "//a///c", "/a/c"),
+            ("/ä/./ö", "/ä/ö", "/ä/ö"),
+            ("/a/../b?c=/../d", "/b?c=/../d", "/b?c=/../d"),
+            ("/a?b//c", "/a?b//c", "/a?b//c"),
+        ];
+
+        for (i, o, collapsed) in test_cases {
+            assert_eq!(o, normalizer.normalize(i), "{}", i);
+            assert_eq!(collapsed, collapse.normalize(i), "{}", i);
+        }
+        assert!(matches!(normalizer.normalize("/a/b?c"), Cow::Borrowed(_)));
+        assert!(matches!(collapse.normalize("/a//b"), Cow::Owned(_)));
+    }
+
+    #[test]
+    fn test_equivalent_paths() {
+        let cylon = crate::CylonBuilder::new()
+            .disallow("/a/b")
+            .allow("/a/b/public")
+            .disallow("/*.php$")
+            .disallow("/a%2Eb")
+            .build()
+            .unwrap();
+        let normalizer = PathNormalizer::new().with_collapse_slashes(true);
+        let test_cases = vec![
+            (
+                "/a/b",
+                vec!["/a/./b", "/a//b", "/a/c/../b", "//a/b", "/x/../a/b/."],
+            ),
+            (
+                "/a/b/public",
+                vec!["/a/b/./public", "/a/b/private/../public", "/a///b//public"],
+            ),
+            ("/x.php", vec!["/y/../x.php", "/./x.php", "/x/..//x.php"]),
+            ("/c", vec!["/a/b/../../c", "/a/../c", "//c"]),
+            // An encoded `.` is only decoded in a dot segment.
+            ("/a%2Eb", vec!["/a%2Eb", "/x/%2E%2E/a%2Eb", "/%2e/a%2Eb"]),
+        ];
+
+        for (canonical, paths) in test_cases {
+            for path in paths {
+                assert_eq!(
+                    cylon.allow(canonical),
+                    cylon.allow_normalized(path, &normalizer),
+                    "{}",
+                    path
+                );
+            }
+        }
+        assert!(!cylon.allow_normalized("/a%2Eb", &normalizer));
+    }
+}
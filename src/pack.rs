@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::ops::Range;
+
+use crate::binary::{DecodeError, Reader, Writer};
+use crate::error::Error;
+use crate::nfa::Cylon;
+use crate::view::CylonView;
+
+const MAGIC: &[u8; 4] = b"CYLP";
+const ENTRY_LEN: usize = 24;
+
+/// Many compiled Cylons in one buffer, each stored under a key such as the
+/// host the robots.txt file was fetched from. This is meant for large sets
+/// of precompiled files, e.g. one file for the top million hosts that is
+/// memory-mapped by every crawler process.
+///
+/// The buffer starts with a checksummed index of the keys and the offset of
+/// each entry, followed by the entries in the format written by
+/// [`Cylon::to_bytes`](crate::Cylon::to_bytes). Opening a pack only
+/// validates the index. Each entry is validated when it is looked up, so a
+/// lookup costs the size of that entry rather than of the whole buffer, and
+/// a corrupt entry doesn't affect the others.
+///
+/// ```
+/// use cylon::{CylonBuilder, CylonPack};
+///
+/// let a = CylonBuilder::new().disallow("/a").build().unwrap();
+/// let b = CylonBuilder::new().disallow("/b").build().unwrap();
+/// let bytes = CylonPack::encode(vec![("a.example", &a), ("b.example", &b)]);
+///
+/// let pack = CylonPack::new(&bytes).unwrap();
+/// let view = pack.get("b.example").unwrap().unwrap();
+/// assert!(view.allow("/a"));
+/// assert!(!view.allow("/b"));
+/// assert!(pack.get("c.example").unwrap().is_none());
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct CylonPack<'a> {
+    index: &'a [u8],
+    keys: &'a [u8],
+    entries: &'a [u8],
+}
+
+impl<'a> CylonPack<'a> {
+    /// Encode the Cylons under their keys. If a key is given more than
+    /// once, the last Cylon for it is kept.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are more than `u32::MAX` keys, or the keys together
+    /// are longer than that, which the format can't represent.
+    pub fn encode<'c, K: AsRef<str>>(cylons: impl IntoIterator<Item = (K, &'c Cylon)>) -> Vec<u8> {
+        let cylons = cylons
+            .into_iter()
+            .map(|(key, cylon)| (key.as_ref().to_owned(), cylon.to_bytes()))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut w = Writer::default();
+        w.len(cylons.len());
+        let (mut key_offset, mut entry_offset) = (0, 0);
+        for (key, bytes) in &cylons {
+            w.len(key_offset);
+            w.len(key.len());
+            w.u64(entry_offset as u64);
+            w.u64(bytes.len() as u64);
+            key_offset += key.len();
+            entry_offset += bytes.len();
+        }
+        w.len(key_offset);
+        for key in cylons.keys() {
+            w.bytes(key.as_bytes());
+        }
+
+        let mut out = w.finish_as(MAGIC, 0);
+        out.reserve(entry_offset);
+        for bytes in cylons.values() {
+            out.extend_from_slice(bytes);
+        }
+        out
+    }
+
+    /// Validate the index and open the pack. The entries are only
+    /// validated when they are looked up.
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        let (mut r, _, entries) = Reader::open_prefix(data, MAGIC, 0)?;
+
+        let count = r.len(ENTRY_LEN)?;
+        let index = r.bytes(count * ENTRY_LEN)?;
+        let keys_len = r.len(1)?;
+        let keys = r.bytes(keys_len)?;
+        r.finish()?;
+
+        let pack = Self {
+            index,
+            keys,
+            entries,
+        };
+        let mut previous: Option<&[u8]> = None;
+        for i in 0..count {
+            let (key, entry) = pack.ranges(i).ok_or(DecodeError::Truncated)?;
+            let key = &keys[key];
+            if std::str::from_utf8(key).is_err() {
+                return Err(DecodeError::Invalid("key is not utf-8").into());
+            }
+            if previous.is_some_and(|previous| previous >= key) {
+                return Err(DecodeError::Invalid("keys out of order").into());
+            }
+            if entry.end > entries.len() {
+                return Err(DecodeError::Truncated.into());
+            }
+            previous = Some(key);
+        }
+        Ok(pack)
+    }
+
+    /// The number of Cylons in the pack.
+    pub fn len(&self) -> usize {
+        self.index.len() / ENTRY_LEN
+    }
+
+    /// Whether the pack has no Cylons.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// The keys of the pack, in ascending order.
+    pub fn keys(&self) -> impl Iterator<Item = &'a str> + '_ {
+        (0..self.len()).map(move |i| self.key(i))
+    }
+
+    /// Look up a key and validate its entry, returning `Ok(None)` if the
+    /// pack has no such key.
+    pub fn get(&self, key: &str) -> Result<Option<CylonView<'a>>, Error> {
+        self.get_bytes(key).map(CylonView::new).transpose()
+    }
+
+    /// Look up the bytes of the entry for a key, e.g. to decode it with
+    /// [`Cylon::from_bytes`](crate::Cylon::from_bytes).
+    pub fn get_bytes(&self, key: &str) -> Option<&'a [u8]> {
+        let mut range = 0..self.len();
+        while !range.is_empty() {
+            let mid = range.start + range.len() / 2;
+            match self.key(mid).cmp(key) {
+                std::cmp::Ordering::Less => range.start = mid + 1,
+                std::cmp::Ordering::Greater => range.end = mid,
+                std::cmp::Ordering::Equal => {
+                    let (_, entry) = self.ranges(mid)?;
+                    return self.entries.get(entry);
+                }
+            }
+        }
+        None
+    }
+
+    fn key(&self, i: usize) -> &'a str {
+        // Checked to be in range and utf-8 when the pack was opened.
+        self.ranges(i)
+            .and_then(|(key, _)| std::str::from_utf8(&self.keys[key]).ok())
+            .unwrap_or_default()
+    }
+
+    /// The range of the key in the keys and of the entry in the entries.
+    fn ranges(&self, i: usize) -> Option<(Range<usize>, Range<usize>)> {
+        let mut r = Reader::new(self.index.get(i * ENTRY_LEN..(i + 1) * ENTRY_LEN)?);
+        let key_start = r.u32().ok()? as usize;
+        let key_len = r.u32().ok()? as usize;
+        let entry_start = usize::try_from(r.u64().ok()?).ok()?;
+        let entry_len = usize::try_from(r.u64().ok()?).ok()?;
+
+        let key = key_start..key_start.checked_add(key_len)?;
+        let entry = entry_start..entry_start.checked_add(entry_len)?;
+        if key.end > self.keys.len() {
+            return None;
+        }
+        Some((key, entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CylonBuilder;
+
+    fn cylon(path: &str) -> Cylon {
+        CylonBuilder::new().disallow(path).build().unwrap()
+    }
+
+    #[test]
+    fn test_pack() {
+        let (a, b, c) = (cylon("/a"), cylon("/b"), cylon("/c"));
+        let data = CylonPack::encode(vec![
+            ("c.example", &c),
+            ("a.example", &b),
+            ("a.example", &a),
+            ("b.example", &b),
+        ]);
+        let pack = CylonPack::new(&data).unwrap();
+
+        assert_eq!(3, pack.len());
+        assert_eq!(
+            vec!["a.example", "b.example", "c.example"],
+            pack.keys().collect::<Vec<_>>()
+        );
+
+        let test_cases = vec![
+            ("a.example", "/a", false),
+            ("a.example", "/b", true),
+            ("b.example", "/b", false),
+            ("c.example", "/c", false),
+            ("c.example", "/a", true),
+        ];
+        for (key, path, allow) in test_cases {
+            let view = pack.get(key).unwrap().unwrap();
+            assert_eq!(allow, view.allow(path), "{} {}", key, path);
+        }
+
+        for key in &["", "a", "b.example.com", "d.example"] {
+            assert!(pack.get(key).unwrap().is_none());
+        }
+        assert_eq!(a.to_bytes(), pack.get_bytes("a.example").unwrap(),);
+    }
+
+    #[test]
+    fn test_empty_pack() {
+        let data = CylonPack::encode(Vec::<(&str, &Cylon)>::new());
+        let pack = CylonPack::new(&data).unwrap();
+        assert!(pack.is_empty());
+        assert!(pack.get("a.example").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_corrupt_entry() {
+        let (a, b) = (cylon("/a"), cylon("/b"));
+        let mut data = CylonPack::encode(vec![("a.example", &a), ("b.example", &b)]);
+        *data.last_mut().unwrap() ^= 1;
+
+        // Only the corrupt entry is rejected, when it is looked up.
+        let pack = CylonPack::new(&data).unwrap();
+        assert!(pack.get("a.example").unwrap().is_some());
+        assert!(matches!(
+            pack.get("b.example"),
+            Err(Error::Decode(DecodeError::ChecksumMismatch))
+        ));
+    }
+
+    #[test]
+    fn test_new_rejects() {
+        let a = cylon("/a");
+        let data = CylonPack::encode(vec![("a.example", &a)]);
+
+        let mut index = data.clone();
+        index[20] ^= 1;
+
+        let test_cases = vec![
+            (a.to_bytes(), DecodeError::BadMagic),
+            (data[..10].to_vec(), DecodeError::Truncated),
+            (data[..data.len() - 1].to_vec(), DecodeError::Truncated),
+            (index, DecodeError::ChecksumMismatch),
+        ];
+        for (i, o) in test_cases {
+            match CylonPack::new(&i) {
+                Err(Error::Decode(e)) => assert_eq!(o, e),
+                result => panic!("expected {:?}, got {:?}", o, result.err()),
+            }
+        }
+    }
+}
@@ -1,14 +1,83 @@
+use std::borrow::Cow;
 use std::collections::BTreeMap;
+#[cfg(feature = "crawl-delay")]
+use std::time::Duration;
 
+use super::clean_param::CleanParam;
+use super::directive::{self, Scope, UnknownDirective};
+use super::error::Error;
+use super::fold::CaseFolding;
 use super::nfa::{Cylon, Rule};
-use futures_util::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, BufReader, Result};
+use super::rate::{RequestRate, VisitTime};
+use super::regex_rule::{self, RegexRule};
+use super::semantics::MatchSemantics;
+use super::sniff::{self, ContentKind, ContentPolicies, ContentPolicy, SNIFF_LEN};
+use futures_util::future::Either;
+use futures_util::io::{self, AsyncRead, AsyncReadExt, Chain, Cursor};
 use serde_derive::{Deserialize, Serialize};
 
-const UA_PREFIX: &str = "user-agent:";
-#[cfg(feature = "crawl-delay")]
-const DELAY_PREFIX: &str = "crawl-delay:";
-const ALLOW_PREFIX: &str = "allow:";
-const DISALLOW_PREFIX: &str = "disallow:";
+/// Like Google's parser, ignore anything after the first 16663 bytes of a
+/// line, which is eight times the longest URL some browsers support.
+const MAX_LINE_LEN: usize = 2083 * 8 - 1;
+/// A UTF-8 byte order mark, which is skipped at the start of a file even
+/// if it is incomplete.
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+/// How many bytes of a file are read at a time.
+const READ_LEN: usize = 8 * 1024;
+
+/// The directives recognized in a robots.txt file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Key {
+    UserAgent,
+    Allow,
+    Disallow,
+    CrawlDelay,
+    RequestRate,
+    VisitTime,
+    Sitemap,
+    Host,
+    CleanParam,
+    Unknown,
+}
+
+impl Key {
+    /// Look up a directive by name, ignoring case.
+    pub(crate) fn from_name(name: &str) -> Self {
+        match &name.to_ascii_lowercase()[..] {
+            "user-agent" => Key::UserAgent,
+            "allow" => Key::Allow,
+            "disallow" => Key::Disallow,
+            "crawl-delay" => Key::CrawlDelay,
+            "request-rate" => Key::RequestRate,
+            "visit-time" => Key::VisitTime,
+            "sitemap" => Key::Sitemap,
+            "host" => Key::Host,
+            "clean-param" => Key::CleanParam,
+            _ => Key::Unknown,
+        }
+    }
+
+    /// The canonical spelling of the directive name.
+    pub(crate) fn name(&self) -> Option<&'static str> {
+        match self {
+            Key::UserAgent => Some("User-agent"),
+            Key::Allow => Some("Allow"),
+            Key::Disallow => Some("Disallow"),
+            Key::CrawlDelay => Some("Crawl-delay"),
+            Key::RequestRate => Some("Request-rate"),
+            Key::VisitTime => Some("Visit-time"),
+            Key::Sitemap => Some("Sitemap"),
+            Key::Host => Some("Host"),
+            Key::CleanParam => Some("Clean-param"),
+            Key::Unknown => None,
+        }
+    }
+
+    /// Whether the directive applies to the whole file rather than a group.
+    pub(crate) fn is_global(&self) -> bool {
+        matches!(self, Key::Sitemap | Key::Host | Key::CleanParam)
+    }
+}
 
 #[derive(Debug, PartialEq, Clone)]
 enum ParsedRule {
@@ -16,67 +85,464 @@ enum ParsedRule {
     Disallow(String),
     #[cfg(feature = "crawl-delay")]
     Delay(String),
+    RequestRate(String),
+    VisitTime(String),
+    Unknown(String, String),
 }
 
-impl<'a> From<&'a ParsedRule> for Rule<'a> {
-    fn from(rule: &ParsedRule) -> Rule<'_> {
-        match rule {
-            ParsedRule::Allow(path) => Rule::Allow(path.as_bytes()),
-            ParsedRule::Disallow(path) => Rule::Disallow(path.as_bytes()),
-            #[cfg(feature = "crawl-delay")]
-            ParsedRule::Delay(delay) => Rule::Delay(delay.as_bytes()),
-        }
-    }
+/// Directives that apply to the whole file rather than to a group, so they
+/// may appear anywhere without ending the current group.
+#[derive(Debug, PartialEq, Clone)]
+enum ParsedGlobal {
+    Sitemap(String),
+    Host(String),
+    CleanParam(String),
 }
 
 #[derive(Debug, PartialEq)]
 enum ParsedLine {
     UserAgent(String),
     Rule(ParsedRule),
+    Global(ParsedGlobal),
+    Unknown(String, String),
     Nothing,
 }
 
 /// A compiler takes an input robots.txt file and outputs a compiled Cylon,
 /// which can be used to efficiently match a large number of paths against
 /// the robots.txt file.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Compiler {
     user_agent: String,
+    #[serde(default)]
+    semantics: MatchSemantics,
+    #[serde(default)]
+    case_folding: CaseFolding,
+    #[serde(default = "default_allow_robots_txt")]
+    allow_robots_txt: bool,
+    #[serde(default)]
+    content_policies: ContentPolicies,
+    #[serde(default)]
+    max_size: Option<usize>,
+    #[serde(default)]
+    max_states: Option<usize>,
+    #[cfg(feature = "crawl-delay")]
+    #[serde(default)]
+    min_delay: Option<Duration>,
+    #[cfg(feature = "crawl-delay")]
+    #[serde(default)]
+    max_delay: Option<Duration>,
 }
 
 impl Compiler {
     /// Build a new compiler that parses rules for the given user agent from
-    /// a robots.txt file.
+    /// a robots.txt file. Only the product token at the start of the user
+    /// agent is matched against groups, like Google's parser does, so
+    /// `Googlebot/2.1 (+http://www.google.com/bot.html)` follows the
+    /// `googlebot` groups. The user agent may also be `*` to only follow
+    /// the groups for every crawler. Compiling fails with
+    /// [`Error::InvalidConfiguration`] if the user agent doesn't start with
+    /// a product token, since it could never match a group of its own.
     pub fn new(user_agent: &str) -> Self {
+        let user_agent = user_agent.trim();
+        let user_agent = match user_agent {
+            "*" => user_agent,
+            _ => product_token(user_agent),
+        };
         Self {
             user_agent: user_agent.to_lowercase(),
+            semantics: MatchSemantics::default(),
+            case_folding: CaseFolding::default(),
+            allow_robots_txt: true,
+            content_policies: ContentPolicies::default(),
+            max_size: None,
+            max_states: None,
+            #[cfg(feature = "crawl-delay")]
+            min_delay: None,
+            #[cfg(feature = "crawl-delay")]
+            max_delay: None,
         }
     }
 
+    /// Match rules with the given semantics instead of RFC 9309's, e.g. to
+    /// see how an older crawler will read a file.
+    pub fn with_semantics(mut self, semantics: MatchSemantics) -> Self {
+        self.semantics = semantics;
+        self
+    }
+
+    /// Match paths case-insensitively, for sites whose server ignores the
+    /// case of paths. Patterns are folded when the file is compiled, so
+    /// paths can be matched as they are.
+    pub fn with_case_folding(mut self, case_folding: CaseFolding) -> Self {
+        self.case_folding = case_folding;
+        self
+    }
+
+    /// Whether `/robots.txt` is allowed even if the rules disallow it, as
+    /// RFC 9309 requires so that the file can always be fetched again.
+    /// Defaults to true; disable it to match paths exactly as the rules say.
+    pub fn with_robots_txt_allowed(mut self, allow_robots_txt: bool) -> Self {
+        self.allow_robots_txt = allow_robots_txt;
+        self
+    }
+
+    /// Choose what to do with files that look like the given kind of
+    /// content. By default every file is parsed as robots.txt, as Google's
+    /// parser does, whatever kind it looks like.
+    pub fn with_content_policy(mut self, kind: ContentKind, policy: ContentPolicy) -> Self {
+        self.content_policies.set(kind, policy);
+        self
+    }
+
+    /// Fail with [`Error::SizeLimitExceeded`] instead of compiling a file
+    /// larger than this many bytes. There is no limit by default.
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Fail with [`Error::StateLimitExceeded`] if a file compiles to a
+    /// Cylon with more than this many states, e.g. to bound the memory
+    /// used by cached files from untrusted sites. There is no limit by
+    /// default.
+    ///
+    /// The limit is checked once the Cylon is built, so it only caps the
+    /// size of the result, not the memory used while compiling. That grows
+    /// with the size of the file, which [`Compiler::with_max_size`] limits.
+    pub fn with_max_states(mut self, max_states: usize) -> Self {
+        self.max_states = Some(max_states);
+        self
+    }
+
+    /// Raise any crawl delay found in the robots.txt file to at least
+    /// the given duration.
+    #[cfg(feature = "crawl-delay")]
+    pub fn with_min_delay(mut self, min_delay: Duration) -> Self {
+        self.min_delay = Some(min_delay);
+        self
+    }
+
+    /// Lower any crawl delay found in the robots.txt file to at most
+    /// the given duration.
+    #[cfg(feature = "crawl-delay")]
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = Some(max_delay);
+        self
+    }
+
+    /// Whether the user agent is a valid product token that can be matched
+    /// against a robots.txt file, i.e. it only contains letters, `-` and `_`.
+    pub fn is_valid_user_agent(user_agent: &str) -> bool {
+        !user_agent.is_empty() && product_token(user_agent) == user_agent
+    }
+
     /// Parse an input robots.txt file into a Cylon that can recognize
     /// whether or not a path matches the rules for the Parser's user agent.
-    pub async fn compile<R: AsyncRead + Unpin>(&self, file: R) -> Result<Cylon> {
-        let reader = BufReader::new(file);
-        let mut agent = String::new();
-        let mut rules: Vec<ParsedRule> = vec![];
-        let mut group_reader = GroupReader::new(reader);
-
-        // find the most specific matching group in the robots file
-        while let Some(agents) = group_reader.next_header().await? {
-            let matching_agent = agents.iter().find(|a| {
-                let matches = &a[..] == "*" || self.user_agent.contains(*a);
-                let more_specific = a.len() > agent.len();
-                matches && more_specific
-            });
-
-            if let Some(matching_agent) = matching_agent {
-                agent = matching_agent.clone();
-                rules = group_reader.next_rules().await?;
+    /// Files that don't look like robots.txt are handled by the Compiler's
+    /// [`ContentPolicy`] for their [`ContentKind`].
+    pub async fn compile<R: AsyncRead + Unpin>(&self, file: R) -> Result<Cylon, Error> {
+        self.compile_with_content_type(file, None).await
+    }
+
+    /// Like [`Compiler::compile`], also using the `Content-Type` the file
+    /// was served with to tell what kind of content it is.
+    ///
+    /// ```
+    /// use cylon::{Compiler, ContentKind};
+    ///
+    /// # tokio_test::block_on(async {
+    /// let page = "<!DOCTYPE html>\n<p>User-agent: *</p>\n<p>Disallow: /</p>";
+    /// let compiler = Compiler::new("foobot");
+    /// let cylon = compiler
+    ///     .compile_with_content_type(page.as_bytes(), Some("text/html"))
+    ///     .await
+    ///     .unwrap();
+    /// assert_eq!(ContentKind::Html, cylon.content_kind());
+    /// assert!(cylon.allow("/"));
+    /// # });
+    /// ```
+    pub async fn compile_with_content_type<R: AsyncRead + Unpin>(
+        &self,
+        file: R,
+        content_type: Option<&str>,
+    ) -> Result<Cylon, Error> {
+        self.validate()?;
+        let file = self.limit_size(file).await?;
+        let (kind, file) = sniff(file, content_type).await?;
+        let groups = match self.content_policies.get(kind) {
+            ContentPolicy::Parse => Some(self.read_groups(file).await?),
+            _ => None,
+        };
+        self.build(kind, groups)
+    }
+
+    /// Build the Cylon for a file of the given kind, from its groups if
+    /// the kind's policy is to parse it.
+    pub(crate) fn build(&self, kind: ContentKind, groups: Option<Groups>) -> Result<Cylon, Error> {
+        let mut cylon = match groups {
+            Some(groups) => self.compile_groups(groups),
+            None if self.content_policies.get(kind) == ContentPolicy::DisallowAll => {
+                Cylon::compile_with(
+                    vec![Rule::Disallow(b"/")],
+                    self.semantics,
+                    self.case_folding,
+                    self.allow_robots_txt,
+                )
+            }
+            None => Cylon::allow_all(),
+        };
+        cylon.set_content_kind(kind);
+
+        if let Some(limit) = self.max_states {
+            let states = cylon.state_count();
+            if states > limit {
+                return Err(Error::StateLimitExceeded { limit, states });
             }
         }
+        Ok(cylon)
+    }
+
+    fn compile_groups(&self, groups: Groups) -> Cylon {
+        let rules = self.group_rules(groups.rules);
+
+        #[cfg(feature = "crawl-delay")]
+        let (delay, invalid_delay) = self.crawl_delay(&rules);
+        let (request_rates, visit_times) = Compiler::crawl_rates(&rules);
+
+        let (host, clean_params) = Compiler::yandex_globals(&groups.globals);
+        let unknown = Compiler::unknown_directives(&groups.unknown, &rules);
+        let rules = self.match_rules(&rules);
+        let mut cylon = Cylon::compile_with(
+            rules,
+            self.semantics,
+            self.case_folding,
+            self.allow_robots_txt,
+        );
+        cylon.set_yandex(host, clean_params);
+        cylon.set_sitemaps(Compiler::sitemaps(&groups.globals));
+        cylon.set_unknown_directives(unknown);
+        cylon.set_rates(request_rates, visit_times);
+        #[cfg(feature = "crawl-delay")]
+        cylon.set_delay(delay, invalid_delay);
+        cylon
+    }
 
-        let rules = Compiler::filter_dupes(&rules);
-        Ok(Cylon::compile(rules))
+    /// Convert the rules for the Compiler's user agent into regular
+    /// expressions ordered by precedence, for systems that can't use a
+    /// Cylon. The first pattern that matches a path decides whether it is
+    /// allowed, which gives the same result as `Cylon::allow`:
+    ///
+    /// ```
+    /// use cylon::Compiler;
+    ///
+    /// # tokio_test::block_on(async {
+    /// let file = "User-agent: *\nDisallow: /private\nAllow: /private/*.html$";
+    /// let regexes = Compiler::new("foobot").compile_regexes(file.as_bytes()).await.unwrap();
+    /// assert_eq!("^/robots\\.txt$", regexes[0].pattern);
+    /// assert_eq!("^/private/.*\\.html$", regexes[1].pattern);
+    /// assert_eq!(true, regexes[1].allow);
+    /// assert_eq!("^/private", regexes[2].pattern);
+    /// assert_eq!(false, regexes[2].allow);
+    /// # });
+    /// ```
+    pub async fn compile_regexes<R: AsyncRead + Unpin>(
+        &self,
+        file: R,
+    ) -> Result<Vec<RegexRule>, Error> {
+        self.validate()?;
+        let file = self.limit_size(file).await?;
+        let (kind, file) = sniff(file, None).await?;
+        let policy = self.content_policies.get(kind);
+        let parsed = match policy {
+            ContentPolicy::Parse => self.group_rules(self.read_groups(file).await?.rules),
+            _ => vec![],
+        };
+        let rules = match policy {
+            ContentPolicy::Parse => self.match_rules(&parsed),
+            ContentPolicy::AllowAll => vec![],
+            ContentPolicy::DisallowAll => vec![Rule::Disallow(b"/")],
+        };
+        Ok(regex_rule::from_rules(
+            &rules,
+            self.semantics,
+            self.case_folding,
+            self.allow_robots_txt,
+        ))
+    }
+
+    /// Reject options that contradict each other.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        if self.user_agent.is_empty() {
+            let reason = "user agent does not start with a product token such as `googlebot`";
+            return Err(Error::InvalidConfiguration(reason));
+        }
+        #[cfg(feature = "crawl-delay")]
+        if let (Some(min_delay), Some(max_delay)) = (self.min_delay, self.max_delay) {
+            if min_delay > max_delay {
+                let reason = "min delay is longer than max delay";
+                return Err(Error::InvalidConfiguration(reason));
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the whole file if it has a size limit, to check that it is
+    /// within the limit before anything is parsed.
+    async fn limit_size<R: AsyncRead + Unpin>(
+        &self,
+        file: R,
+    ) -> Result<Either<Cursor<Vec<u8>>, R>, Error> {
+        let limit = match self.max_size {
+            Some(limit) => limit,
+            None => return Ok(Either::Right(file)),
+        };
+        let mut buf = vec![];
+        file.take(limit as u64 + 1).read_to_end(&mut buf).await?;
+        if buf.len() > limit {
+            return Err(Error::SizeLimitExceeded { limit });
+        }
+        Ok(Either::Left(Cursor::new(buf)))
+    }
+
+    /// Read the whole file, collecting the rules of the groups for the
+    /// user agent and the file's globals.
+    async fn read_groups<R: AsyncRead + Unpin>(&self, mut file: R) -> io::Result<Groups> {
+        let mut parser = GroupParser::new(&self.user_agent);
+        let mut buf = vec![0; READ_LEN];
+        loop {
+            let n = file.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(parser.finish());
+            }
+            parser.feed(&buf[..n]);
+        }
+    }
+
+    /// The rules of the selected groups, along with the index rules that
+    /// RFC 9309 implies.
+    fn group_rules(&self, mut rules: Vec<ParsedRule>) -> Vec<ParsedRule> {
+        if self.semantics == MatchSemantics::Rfc9309 {
+            rules.extend(index_rules(&rules));
+        }
+        rules
+    }
+
+    pub(crate) fn user_agent(&self) -> &str {
+        &self.user_agent
+    }
+
+    pub(crate) fn max_size(&self) -> Option<usize> {
+        self.max_size
+    }
+
+    pub(crate) fn content_policy(&self, kind: ContentKind) -> ContentPolicy {
+        self.content_policies.get(kind)
+    }
+
+    fn unknown_directives(
+        globals: &[(String, String)],
+        rules: &[ParsedRule],
+    ) -> Vec<UnknownDirective> {
+        let globals = globals
+            .iter()
+            .map(|(k, v)| UnknownDirective::new(k.clone(), v.clone(), Scope::Global));
+        let group = rules.iter().filter_map(|rule| match rule {
+            ParsedRule::Unknown(k, v) => {
+                Some(UnknownDirective::new(k.clone(), v.clone(), Scope::Group))
+            }
+            _ => None,
+        });
+        globals.chain(group).collect()
+    }
+
+    fn sitemaps(globals: &[ParsedGlobal]) -> Vec<String> {
+        globals
+            .iter()
+            .filter_map(|global| match global {
+                ParsedGlobal::Sitemap(url) if !url.is_empty() => Some(url.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Yandex only honours the first `Host` directive in the file, while
+    /// every valid `Clean-param` directive applies.
+    fn yandex_globals(globals: &[ParsedGlobal]) -> (Option<String>, Vec<CleanParam>) {
+        let mut host = None;
+        let mut clean_params = vec![];
+        for global in globals {
+            match global {
+                ParsedGlobal::Host(value) if host.is_none() && !value.is_empty() => {
+                    host = Some(value.clone());
+                }
+                ParsedGlobal::Host(..) => (),
+                ParsedGlobal::CleanParam(value) => clean_params.extend(CleanParam::parse(value)),
+                ParsedGlobal::Sitemap(..) => (),
+            }
+        }
+        (host, clean_params)
+    }
+
+    /// Pick the smallest valid crawl delay in the group and clamp it to the
+    /// configured bounds. The first value that could not be parsed is kept
+    /// so callers can report it.
+    #[cfg(feature = "crawl-delay")]
+    fn crawl_delay(&self, rules: &[ParsedRule]) -> (Option<Duration>, Option<String>) {
+        let mut delay: Option<Duration> = None;
+        let mut invalid_delay = None;
+        for rule in rules {
+            if let ParsedRule::Delay(value) = rule {
+                match parse_delay_value(value) {
+                    Some(d) => delay = Some(delay.map_or(d, |delay| delay.min(d))),
+                    None if invalid_delay.is_none() => invalid_delay = Some(value.clone()),
+                    None => (),
+                }
+            }
+        }
+
+        let delay = delay.map(|mut d| {
+            if let Some(min_delay) = self.min_delay {
+                d = d.max(min_delay);
+            }
+            if let Some(max_delay) = self.max_delay {
+                d = d.min(max_delay);
+            }
+            d
+        });
+        (delay, invalid_delay)
+    }
+
+    /// Collect the valid request rates and visit times in the group. Values
+    /// that cannot be parsed are ignored.
+    fn crawl_rates(rules: &[ParsedRule]) -> (Vec<RequestRate>, Vec<VisitTime>) {
+        let mut request_rates = vec![];
+        let mut visit_times = vec![];
+        for rule in rules {
+            match rule {
+                ParsedRule::RequestRate(value) => request_rates.extend(RequestRate::parse(value)),
+                ParsedRule::VisitTime(value) => visit_times.extend(VisitTime::parse(value)),
+                _ => (),
+            }
+        }
+        (request_rates, visit_times)
+    }
+
+    /// The Allow and Disallow rules to match. Under RFC 9309 only the
+    /// Allow of duplicate patterns can win, so the others are dropped, and
+    /// otherwise the rules are kept in file order.
+    fn match_rules<'a>(&self, rules: &'a [ParsedRule]) -> Vec<Rule<'a>> {
+        if self.semantics == MatchSemantics::Rfc9309 {
+            return Compiler::filter_dupes(rules);
+        }
+        rules
+            .iter()
+            .filter_map(|rule| match rule {
+                ParsedRule::Allow(inner) => Some(Rule::Allow(inner.as_bytes())),
+                ParsedRule::Disallow(inner) => Some(Rule::Disallow(inner.as_bytes())),
+                _ => None,
+            })
+            .collect()
     }
 
     fn filter_dupes(rules: &[ParsedRule]) -> Vec<Rule<'_>> {
@@ -84,134 +550,289 @@ impl Compiler {
         for rule in rules {
             match rule {
                 ParsedRule::Allow(inner) => {
-                    dedupe.insert(inner.clone(), rule.into());
-                }
-                #[cfg(feature = "crawl-delay")]
-                ParsedRule::Delay(inner) => {
-                    dedupe.insert(inner.clone(), rule.into());
+                    dedupe.insert(inner.clone(), Rule::Allow(inner.as_bytes()));
                 }
                 ParsedRule::Disallow(inner) => {
                     if !dedupe.contains_key(inner) {
-                        dedupe.insert(inner.clone(), rule.into());
+                        dedupe.insert(inner.clone(), Rule::Disallow(inner.as_bytes()));
                     }
                 }
+                #[cfg(feature = "crawl-delay")]
+                ParsedRule::Delay(_) => (),
+                ParsedRule::RequestRate(_) | ParsedRule::VisitTime(_) => (),
+                ParsedRule::Unknown(..) => (),
             }
         }
         dedupe.into_values().collect()
     }
 }
 
-struct GroupReader<R: AsyncBufRead + Unpin> {
+/// The rules of the groups that apply to a user agent, and the parts of
+/// the file that apply to every user agent.
+pub(crate) struct Groups {
+    /// The rules of every group that names the user agent, or of every `*`
+    /// group if none do.
+    rules: Vec<ParsedRule>,
+    globals: Vec<ParsedGlobal>,
+    unknown: Vec<(String, String)>,
+}
+
+/// Whether a GroupParser is in a group's header, or in the rules of a group
+/// that applies to the user agent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Position {
+    Header,
+    Rules { specific: bool, global: bool },
+}
+
+/// Parses a file as it arrives in chunks, splitting it into lines and
+/// collecting the rules of the groups for a user agent. Lines may end with
+/// `\n`, `\r\n` or `\r`, and may be split across chunks.
+#[derive(Debug)]
+pub(crate) struct GroupParser {
+    user_agent: String,
+    line: Vec<u8>,
+    started: bool,
+    position: Position,
     parsing_agents: bool,
     agents: Vec<String>,
     rules: Vec<ParsedRule>,
-    reader: R,
+    specific: Option<Vec<ParsedRule>>,
+    global: Vec<ParsedRule>,
+    globals: Vec<ParsedGlobal>,
+    unknown: Vec<(String, String)>,
 }
 
-impl<R: AsyncBufRead + Unpin> GroupReader<R> {
-    fn new(reader: R) -> Self {
+impl GroupParser {
+    pub(crate) fn new(user_agent: &str) -> Self {
         Self {
+            user_agent: user_agent.into(),
+            line: vec![],
+            started: false,
+            position: Position::Header,
             parsing_agents: true,
             agents: vec![],
             rules: vec![],
-            reader,
+            specific: None,
+            global: vec![],
+            globals: vec![],
+            unknown: vec![],
         }
     }
 
-    /// Scan forward until the next group header defined by one or more
-    /// user agent lines. This lets us optimize the lines we need to copy
-    /// so we can skip over groups that don't match the desired user agent.
-    async fn next_header(&mut self) -> Result<Option<Vec<String>>> {
-        let mut buf = String::new();
-        while self.reader.read_line(&mut buf).await? != 0 {
-            let parsed_line = parse_line(buf.clone());
-
-            match parsed_line {
-                ParsedLine::UserAgent(ua) if self.parsing_agents => {
-                    self.agents.push(ua);
-                }
-                ParsedLine::UserAgent(ua) => {
-                    self.agents = vec![ua];
-                    self.rules = vec![];
-                    self.parsing_agents = true;
-                }
-                ParsedLine::Rule(rule) if self.parsing_agents => {
-                    // Preserve the rule in case we need it in next_rules().
-                    self.rules.push(rule);
-                    self.parsing_agents = false;
-                    break;
-                }
-                // Skip over lines until we get to the next user agent.
-                ParsedLine::Rule(..) => (),
-                ParsedLine::Nothing => (),
-            }
+    pub(crate) fn feed(&mut self, mut chunk: &[u8]) {
+        while let Some(end) = chunk.iter().position(|b| *b == b'\r' || *b == b'\n') {
+            self.push_bytes(&chunk[..end]);
+            self.end_line();
+            chunk = &chunk[end + 1..];
+        }
+        self.push_bytes(chunk);
+    }
+
+    /// Whether a whole group naming the user agent has been read.
+    pub(crate) fn found_group(&self) -> bool {
+        self.specific.is_some()
+    }
 
-            buf.clear();
+    /// Parse the last line, which has no line ending, and end the group
+    /// it is in.
+    pub(crate) fn finish(mut self) -> Groups {
+        self.end_line();
+        let rules = std::mem::take(&mut self.rules);
+        match self.position {
+            Position::Rules { specific, global } => self.end_group(specific, global, rules),
+            Position::Header if !self.agents.is_empty() => {
+                let (specific, global) = self.applies(&self.agents);
+                self.end_group(specific, global, rules);
+            }
+            Position::Header => {}
+        }
+        Groups {
+            rules: self.specific.unwrap_or(self.global),
+            globals: self.globals,
+            unknown: self.unknown,
         }
+    }
 
-        let agents = self.agents.clone();
-        self.agents = vec![];
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        // Anything after the first MAX_LINE_LEN bytes is ignored, leaving
+        // room for a byte order mark that is skipped later.
+        let room = (MAX_LINE_LEN + BOM.len()).saturating_sub(self.line.len());
+        self.line.extend_from_slice(&bytes[..bytes.len().min(room)]);
+    }
 
-        if agents.is_empty() {
-            return Ok(None);
+    fn end_line(&mut self) {
+        let mut bytes = &self.line[..];
+        if !self.started {
+            let bom = bytes.iter().zip(BOM).take_while(|(a, b)| a == b).count();
+            bytes = &bytes[bom..];
+            self.started = true;
         }
+        let line = decode_line(&bytes[..bytes.len().min(MAX_LINE_LEN)]);
+        self.line.clear();
 
-        Ok(Some(agents))
+        match self.position {
+            Position::Header => self.parse_header(parse_line(line)),
+            Position::Rules { specific, global } => {
+                self.parse_rules(parse_line(line), specific, global)
+            }
+        }
     }
 
-    async fn next_rules(&mut self) -> Result<Vec<ParsedRule>> {
-        let mut buf = String::new();
-        while self.reader.read_line(&mut buf).await? != 0 {
-            let parsed_line = parse_line(buf.clone());
+    /// Whether a group's user agents name the user agent, and whether they
+    /// include `*`.
+    fn applies(&self, agents: &[String]) -> (bool, bool) {
+        let specific = agents.iter().any(|a| {
+            let token = product_token(a);
+            !token.is_empty() && token.eq_ignore_ascii_case(&self.user_agent)
+        });
+        let global = agents.iter().any(|a| is_global_agent(a));
+        (specific, global)
+    }
 
-            match parsed_line {
-                ParsedLine::Rule(rule) => {
-                    self.rules.push(rule);
-                    self.parsing_agents = false;
-                }
-                ParsedLine::UserAgent(ua) if !self.parsing_agents => {
-                    // Preserve the agent in case we need it in next_agents().
-                    self.agents.push(ua);
-                    self.parsing_agents = true;
-                    break;
+    /// Scan forward until the next group header defined by one or more
+    /// user agent lines ends with its first rule. The rules of groups that
+    /// don't apply to the user agent are skipped.
+    fn parse_header(&mut self, parsed_line: ParsedLine) {
+        match parsed_line {
+            ParsedLine::UserAgent(ua) if self.parsing_agents => {
+                self.agents.push(ua);
+            }
+            ParsedLine::UserAgent(ua) => {
+                self.agents = vec![ua];
+                self.rules = vec![];
+                self.parsing_agents = true;
+            }
+            ParsedLine::Rule(rule) if self.parsing_agents && !self.agents.is_empty() => {
+                self.rules.push(rule);
+                self.parsing_agents = false;
+                let agents = std::mem::take(&mut self.agents);
+                let (specific, global) = self.applies(&agents);
+                if specific || global {
+                    self.position = Position::Rules { specific, global };
                 }
-                // Skip over lines until we get to the next rule.
-                ParsedLine::UserAgent(..) => (),
-                ParsedLine::Nothing => (),
             }
+            // Skip over lines until we get to the next user agent,
+            // including rules before the first group.
+            ParsedLine::Rule(..) => (),
+            ParsedLine::Global(global) => self.globals.push(global),
+            // Unknown lines before the first group apply to the whole
+            // file, and inside a header they belong to the upcoming
+            // group without ending the header.
+            ParsedLine::Unknown(k, v) if self.parsing_agents && self.agents.is_empty() => {
+                self.unknown.push((k, v));
+            }
+            ParsedLine::Unknown(k, v) if self.parsing_agents => {
+                self.rules.push(ParsedRule::Unknown(k, v));
+            }
+            ParsedLine::Unknown(..) => (),
+            ParsedLine::Nothing => (),
+        }
+    }
 
-            buf.clear();
+    /// Collect the rules of a group that applies to the user agent, until
+    /// the next group's header starts.
+    fn parse_rules(&mut self, parsed_line: ParsedLine, specific: bool, global: bool) {
+        match parsed_line {
+            ParsedLine::Rule(rule) => {
+                self.rules.push(rule);
+                self.parsing_agents = false;
+            }
+            ParsedLine::UserAgent(ua) if !self.parsing_agents => {
+                self.agents.push(ua);
+                self.parsing_agents = true;
+                let rules = std::mem::take(&mut self.rules);
+                self.end_group(specific, global, rules);
+                self.position = Position::Header;
+            }
+            // Skip over lines until we get to the next rule.
+            ParsedLine::UserAgent(..) => (),
+            ParsedLine::Global(global) => self.globals.push(global),
+            ParsedLine::Unknown(k, v) => self.rules.push(ParsedRule::Unknown(k, v)),
+            ParsedLine::Nothing => (),
         }
+    }
 
-        let rules = self.rules.clone();
-        self.rules = vec![];
-        Ok(rules)
+    fn end_group(&mut self, specific: bool, global: bool, rules: Vec<ParsedRule>) {
+        if global {
+            self.global.extend(rules.iter().cloned());
+        }
+        if specific {
+            self.specific.get_or_insert_with(Vec::new).extend(rules);
+        }
     }
 }
 
-fn parse_line(line: String) -> ParsedLine {
-    let line = strip_comments(&line[..]).trim();
+/// Read the start of a file to tell what kind of content it is, returning
+/// a reader for the whole file. Fails if it is in an encoding that can't be
+/// read as UTF-8.
+async fn sniff<R: AsyncRead + Unpin>(
+    mut file: R,
+    content_type: Option<&str>,
+) -> Result<(ContentKind, Chain<Cursor<Vec<u8>>, R>), Error> {
+    let mut prefix = Vec::with_capacity(SNIFF_LEN);
+    (&mut file)
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut prefix)
+        .await?;
+    if let Some(encoding) = sniff::unsupported_encoding(&prefix) {
+        return Err(Error::Encoding(encoding));
+    }
+    let complete = prefix.len() < SNIFF_LEN;
+    let kind = ContentKind::sniff_prefix(&prefix, content_type, complete);
+    Ok((kind, Cursor::new(prefix).chain(file)))
+}
 
-    // This tries to parse lines roughly in order of most frequent kind to
-    // least frequent kind in order to minimize CPU cycles on average.
+fn default_allow_robots_txt() -> bool {
+    true
+}
 
-    #[cfg(feature = "crawl-delay")]
-    return parse_disallow(line)
-        .map(|s| ParsedLine::Rule(ParsedRule::Disallow(s.into())))
-        .or_else(|| parse_user_agent(line).map(|s| ParsedLine::UserAgent(s.to_lowercase())))
-        .or_else(|| parse_allow(line).map(|s| ParsedLine::Rule(ParsedRule::Allow(s.into()))))
-        .or_else(|| parse_delay(line).map(|s| ParsedLine::Rule(ParsedRule::Delay(s.into()))))
-        .unwrap_or(ParsedLine::Nothing);
-
-    #[cfg(not(feature = "crawl-delay"))]
-    return parse_disallow(line)
-        .map(|s| ParsedLine::Rule(ParsedRule::Disallow(s.into())))
-        .or_else(|| parse_user_agent(line).map(|s| ParsedLine::UserAgent(s.to_lowercase())))
-        .or_else(|| parse_allow(line).map(|s| ParsedLine::Rule(ParsedRule::Allow(s.into()))))
-        .unwrap_or(ParsedLine::Nothing);
+fn parse_line(line: String) -> ParsedLine {
+    let (key, value) = match split_line(&line) {
+        Some(key_value) => key_value,
+        None => return ParsedLine::Nothing,
+    };
+
+    match Key::from_name(key) {
+        Key::UserAgent => ParsedLine::UserAgent(value.to_lowercase()),
+        Key::Allow => ParsedLine::Rule(ParsedRule::Allow(escape_pattern(value).into())),
+        Key::Disallow => ParsedLine::Rule(ParsedRule::Disallow(escape_pattern(value).into())),
+        #[cfg(feature = "crawl-delay")]
+        Key::CrawlDelay => ParsedLine::Rule(ParsedRule::Delay(value.into())),
+        Key::RequestRate => ParsedLine::Rule(ParsedRule::RequestRate(value.into())),
+        Key::VisitTime => ParsedLine::Rule(ParsedRule::VisitTime(value.into())),
+        Key::Sitemap => ParsedLine::Global(ParsedGlobal::Sitemap(value.into())),
+        Key::Host => ParsedLine::Global(ParsedGlobal::Host(value.into())),
+        Key::CleanParam => ParsedLine::Global(ParsedGlobal::CleanParam(value.into())),
+        _ if directive::is_directive_key(key) => {
+            ParsedLine::Unknown(key.to_ascii_lowercase(), value.into())
+        }
+        _ => ParsedLine::Nothing,
+    }
+}
+
+/// Split a line into its directive name and value, with comments and
+/// surrounding whitespace removed. Returns None for lines that are blank,
+/// only contain a comment, or have no separator. Like Google, a known
+/// directive may be followed by whitespace instead of `:` when its value
+/// is a single word, e.g. `Disallow /`.
+pub(crate) fn split_line(line: &str) -> Option<(&str, &str)> {
+    let line = strip_comments(line).trim();
+    let (key, value) = match line.split_once(':') {
+        Some(key_value) => key_value,
+        None => {
+            let (key, value) = line.split_once([' ', '\t'])?;
+            let value = value.trim_start_matches([' ', '\t']);
+            if value.contains([' ', '\t']) || Key::from_name(key) == Key::Unknown {
+                return None;
+            }
+            (key, value)
+        }
+    };
+    Some((key.trim(), value.trim()))
 }
 
-fn strip_comments(line: &str) -> &str {
+pub(crate) fn strip_comments(line: &str) -> &str {
     if let Some(before) = line.split('#').next() {
         before
     } else {
@@ -219,64 +840,157 @@ fn strip_comments(line: &str) -> &str {
     }
 }
 
-fn parse_user_agent(line: &str) -> Option<&str> {
-    if line.len() < UA_PREFIX.len() {
-        return None;
-    }
-    let prefix = &line[..UA_PREFIX.len()].to_ascii_lowercase();
-    let suffix = &line[UA_PREFIX.len()..];
+/// Split a file into lines and their endings the way the Compiler does,
+/// ending lines at `\n`, `\r\n` or a bare `\r`. The last line has no
+/// ending if the file doesn't end with one.
+pub(crate) fn lines(file: &str) -> impl Iterator<Item = (&str, &'static str)> {
+    let mut rest = file;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let (text, ending, next) = match rest.find(['\r', '\n']) {
+            Some(i) if rest[i..].starts_with("\r\n") => (&rest[..i], "\r\n", &rest[i + 2..]),
+            Some(i) if rest[i..].starts_with('\r') => (&rest[..i], "\r", &rest[i + 1..]),
+            Some(i) => (&rest[..i], "\n", &rest[i + 1..]),
+            None => (rest, "", ""),
+        };
+        rest = next;
+        Some((text, ending))
+    })
+}
 
-    if prefix == UA_PREFIX {
-        Some(suffix.trim())
-    } else {
-        None
+/// The lines of a file that can affect how it compiles, with comments and
+/// surrounding whitespace removed. Files with the same significant lines
+/// compile to the same Cylon.
+pub(crate) fn significant_lines(file: &str) -> impl Iterator<Item = &str> {
+    file.split(['\n', '\r'])
+        .map(|line| strip_comments(line).trim())
+        .filter(|line| !line.is_empty())
+}
+
+/// Decode a line as UTF-8, percent-encoding any invalid bytes so that they
+/// are matched the same way Google matches them.
+fn decode_line(mut bytes: &[u8]) -> String {
+    let mut line = String::new();
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                line.push_str(valid);
+                return line;
+            }
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                line.push_str(std::str::from_utf8(valid).unwrap());
+                let invalid = e.error_len().unwrap_or(rest.len());
+                for b in &rest[..invalid] {
+                    line.push_str(&format!("%{:02X}", b));
+                }
+                bytes = &rest[invalid..];
+            }
+        }
     }
 }
 
-#[cfg(feature = "crawl-delay")]
-fn parse_delay(line: &str) -> Option<&str> {
-    if line.len() < DELAY_PREFIX.len() {
-        return None;
+/// Normalize a rule's pattern the way Google does: bytes outside of ASCII
+/// are percent-encoded, and existing percent-encodings are uppercased.
+/// Paths are not normalized, since URLs are expected to be encoded already.
+pub(crate) fn escape_pattern(pattern: &str) -> Cow<'_, str> {
+    let bytes = pattern.as_bytes();
+    let is_escape = |i: usize| {
+        bytes[i] == b'%'
+            && bytes
+                .get(i + 1..i + 3)
+                .is_some_and(|h| h.iter().all(u8::is_ascii_hexdigit))
+    };
+    let needs_escape = (0..bytes.len()).any(|i| {
+        !bytes[i].is_ascii()
+            || (is_escape(i) && bytes[i + 1..i + 3].iter().any(u8::is_ascii_lowercase))
+    });
+    if !needs_escape {
+        return Cow::Borrowed(pattern);
     }
 
-    let prefix = &line[..DELAY_PREFIX.len()].to_ascii_lowercase();
-    let suffix = &line[DELAY_PREFIX.len()..];
-    if prefix == DELAY_PREFIX {
-        Some(suffix.trim())
-    } else {
-        None
+    let mut escaped = String::with_capacity(bytes.len() * 3);
+    let mut i = 0;
+    while i < bytes.len() {
+        if is_escape(i) {
+            escaped.push('%');
+            escaped.push_str(&pattern[i + 1..i + 3].to_ascii_uppercase());
+            i += 3;
+            continue;
+        }
+        match bytes[i] {
+            b if b.is_ascii() => escaped.push(b as char),
+            b => escaped.push_str(&format!("%{:02X}", b)),
+        }
+        i += 1;
     }
+    Cow::Owned(escaped)
 }
 
-fn parse_allow(line: &str) -> Option<&str> {
-    if line.len() < ALLOW_PREFIX.len() {
-        return None;
-    }
-    let prefix = &line[..ALLOW_PREFIX.len()].to_ascii_lowercase();
-    let suffix = &line[ALLOW_PREFIX.len()..];
+/// The product token at the start of a `User-agent` value, e.g. `foobot`
+/// for `FooBot/2.1`. Google only compares these tokens.
+fn product_token(user_agent: &str) -> &str {
+    let end = user_agent
+        .find(|c: char| !c.is_ascii_alphabetic() && c != '-' && c != '_')
+        .unwrap_or(user_agent.len());
+    &user_agent[..end]
+}
 
-    if prefix == ALLOW_PREFIX {
-        Some(suffix.trim())
-    } else {
-        None
-    }
+/// Whether a `User-agent` value names every crawler. Like Google, a `*`
+/// followed by other words still counts.
+fn is_global_agent(user_agent: &str) -> bool {
+    let mut chars = user_agent.chars();
+    chars.next() == Some('*') && chars.next().is_none_or(char::is_whitespace)
+}
+
+/// Google treats allowing a directory's `index.html` or `index.htm` as
+/// allowing the directory itself, so `Allow: /a/index.html` also adds
+/// `Allow: /a/$`.
+fn index_rules(rules: &[ParsedRule]) -> Vec<ParsedRule> {
+    rules
+        .iter()
+        .filter_map(|rule| match rule {
+            ParsedRule::Allow(pattern) => {
+                let slash = pattern.rfind('/')?;
+                pattern[slash..]
+                    .starts_with("/index.htm")
+                    .then(|| ParsedRule::Allow(format!("{}$", &pattern[..=slash])))
+            }
+            _ => None,
+        })
+        .collect()
 }
 
-fn parse_disallow(line: &str) -> Option<&str> {
-    if line.len() < DISALLOW_PREFIX.len() {
+/// Parse a crawl delay given as a (possibly fractional) number of seconds,
+/// e.g. `10`, `0.5` or `1.25`. Precision beyond nanoseconds is truncated.
+#[cfg(feature = "crawl-delay")]
+fn parse_delay_value(value: &str) -> Option<Duration> {
+    let (secs, frac) = match value.find('.') {
+        Some(i) => (&value[..i], &value[i + 1..]),
+        None => (value, ""),
+    };
+    let all_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if (secs.is_empty() && frac.is_empty()) || !all_digits(secs) || !all_digits(frac) {
         return None;
     }
-    let prefix = &line[..DISALLOW_PREFIX.len()].to_ascii_lowercase();
-    let suffix = &line[DISALLOW_PREFIX.len()..];
 
-    if prefix == DISALLOW_PREFIX {
-        Some(suffix.trim())
+    let secs = if secs.is_empty() {
+        0
     } else {
-        None
-    }
+        secs.parse::<u64>().ok()?
+    };
+    let nanos = frac
+        .bytes()
+        .chain(std::iter::repeat(b'0'))
+        .take(9)
+        .fold(0, |n, b| n * 10 + u32::from(b - b'0'));
+    Some(Duration::new(secs, nanos))
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
@@ -288,6 +1002,8 @@ mod tests {
             ("ALLOW: /abc/def  ", "/abc/def"),
             ("Allow:   /abc/def  ", "/abc/def"),
             ("  Allow: /*/foo", "/*/foo"),
+            ("Allow /abc", "/abc"),
+            ("Allow: /ä", "/%C3%A4"),
         ];
 
         for (i, o) in test_cases {
@@ -306,6 +1022,8 @@ mod tests {
             ("DISALLOW: /abc/def  ", "/abc/def"),
             ("Disallow:   /abc/def  ", "/abc/def"),
             ("  Disallow: /*/foo", "/*/foo"),
+            ("Disallow \t /abc # comment", "/abc"),
+            ("Disallow: /%aa", "/%AA"),
         ];
 
         for (i, o) in test_cases {
@@ -332,13 +1050,13 @@ mod tests {
     #[test]
     fn test_parse_nothing() {
         let test_cases = vec![
-            "Useragent: *",
             "# Comment",
             "",
             "    ",
             "\t",
-            "alow: /",
-            "disalow: /",
+            "no colon",
+            "two words: x",
+            "Disallow /a /b",
         ];
 
         for i in test_cases {
@@ -346,6 +1064,120 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_escape_pattern() {
+        let test_cases = vec![
+            ("http://www.example.com", "http://www.example.com"),
+            ("/a/b/c", "/a/b/c"),
+            ("á", "%C3%A1"),
+            ("%aa", "%AA"),
+            ("/%a/%zz%Ff", "/%a/%zz%FF"),
+        ];
+
+        for (i, o) in test_cases {
+            assert_eq!(o, escape_pattern(i), "{}", i);
+        }
+        assert!(matches!(escape_pattern("/a%20"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_decode_line() {
+        assert_eq!("Disallow: /%FF%FEa", decode_line(b"Disallow: /\xff\xfea"));
+        assert_eq!("/ä%C3", decode_line(b"/\xc3\xa4\xc3"));
+    }
+
+    #[test]
+    fn test_parse_unknown() {
+        let test_cases = vec![
+            ("Useragent: *", "useragent", "*"),
+            ("alow: /", "alow", "/"),
+            ("disalow: /", "disalow", "/"),
+            (
+                "Content-Signal: ai-train=no # comment",
+                "content-signal",
+                "ai-train=no",
+            ),
+            ("Noindex:", "noindex", ""),
+        ];
+
+        for (i, k, v) in test_cases {
+            assert_eq!(
+                parse_line(i.into()),
+                ParsedLine::Unknown(k.into(), v.into())
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_directives() {
+        use crate::Directive;
+
+        #[derive(Debug, PartialEq)]
+        struct NoAiTraining(bool);
+
+        impl Directive for NoAiTraining {
+            const NAME: &'static str = "DisallowAITraining";
+
+            fn parse(value: &str) -> Option<Self> {
+                Some(Self(value.starts_with('/')))
+            }
+        }
+
+        tokio_test::block_on(async {
+            let example_robots = r#"
+            Content-Usage: train-ai=n
+
+            User-agent: foobar
+            Noindex: /secret
+            User-agent: jones
+            Disallow: /private
+            DisallowAITraining: /
+
+            User-agent: *
+            DisallowAITraining: nothing
+            "#
+            .as_bytes();
+
+            let parser = Compiler::new("foobar");
+            let machine = parser.compile(example_robots).await.unwrap();
+            assert_eq!(false, machine.allow("/private"));
+            assert_eq!(true, machine.allow("/secret"));
+
+            let directives: Vec<_> = machine
+                .unknown_directives()
+                .iter()
+                .map(|d| (d.key(), d.value(), d.scope()))
+                .collect();
+            assert_eq!(
+                vec![
+                    ("content-usage", "train-ai=n", Scope::Global),
+                    ("noindex", "/secret", Scope::Group),
+                    ("disallowaitraining", "/", Scope::Group),
+                ],
+                directives
+            );
+            assert_eq!(
+                vec!["/secret"],
+                machine.unknown_values("NOINDEX").collect::<Vec<_>>()
+            );
+            assert_eq!(
+                vec![NoAiTraining(true)],
+                machine.directives::<NoAiTraining>()
+            );
+
+            let parser = Compiler::new("imabot");
+            let machine = parser.compile(example_robots).await.unwrap();
+            assert_eq!(
+                vec![NoAiTraining(false)],
+                machine.directives::<NoAiTraining>()
+            );
+            assert_eq!(
+                vec!["train-ai=n"],
+                machine.unknown_values("content-usage").collect::<Vec<_>>()
+            );
+        });
+    }
+
     #[test]
     #[cfg(feature = "crawl-delay")]
     fn test_crawl_delay() {
@@ -379,13 +1211,206 @@ mod tests {
             let parser = Compiler::new("jones-bot");
             let jonesbot_machine = parser.compile(example_robots).await.unwrap();
 
-            assert_eq!(Some(60), foobar_machine.delay());
-            assert_eq!(Some(20), barfoo_machine.delay());
-            assert_eq!(Some(30), jonesbot_machine.delay());
+            assert_eq!(Some(Duration::from_secs(60)), foobar_machine.delay());
+            assert_eq!(Some(Duration::from_secs(20)), barfoo_machine.delay());
+            assert_eq!(Some(Duration::from_secs(30)), jonesbot_machine.delay());
             assert_eq!(None, googlebot_machine.delay());
         });
     }
 
+    #[test]
+    fn test_crawl_rates() {
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+        tokio_test::block_on(async {
+            let example_robots = r#"
+            User-agent: foobar
+            Disallow: /private
+            Request-rate: 1/10s
+            Request-rate: 1/1m 0800-1700
+            Visit-time: 0100-0600
+            Visit-time: 2200-2300
+
+            User-agent: *
+            Request-rate: soon
+            Visit-time: never
+            "#
+            .as_bytes();
+
+            let midnight = UNIX_EPOCH + Duration::from_secs(86400 * 100);
+            let hour = |h: u64| midnight + Duration::from_secs(h * 3600);
+
+            let parser = Compiler::new("foobar");
+            let machine = parser.compile(example_robots).await.unwrap();
+            assert_eq!(false, machine.allow("/private"));
+            assert_eq!(2, machine.request_rates().len());
+            assert_eq!(2, machine.visit_times().len());
+            assert_eq!(
+                Some(Duration::from_secs(10)),
+                machine.request_rate_at(hour(2)).map(|r| r.interval())
+            );
+            assert_eq!(
+                Some(Duration::from_secs(60)),
+                machine.request_rate_at(hour(12)).map(|r| r.interval())
+            );
+            assert_eq!(true, machine.visit_allowed_at(hour(3)));
+            assert_eq!(true, machine.visit_allowed_at(hour(22)));
+            assert_eq!(false, machine.visit_allowed_at(hour(12)));
+
+            let parser = Compiler::new("imabot");
+            let machine = parser.compile(example_robots).await.unwrap();
+            assert!(machine.request_rates().is_empty());
+            assert_eq!(None, machine.request_rate_at(SystemTime::now()));
+            assert_eq!(true, machine.visit_allowed_at(SystemTime::now()));
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "crawl-delay")]
+    fn test_parse_delay_value() {
+        let test_cases = vec![
+            ("10", Some(Duration::from_secs(10))),
+            ("0.5", Some(Duration::from_millis(500))),
+            ("1.5", Some(Duration::from_millis(1500))),
+            (".25", Some(Duration::from_millis(250))),
+            ("2.", Some(Duration::from_secs(2))),
+            ("0.0000000019", Some(Duration::from_nanos(1))),
+            ("", None),
+            (".", None),
+            ("-1", None),
+            ("1e3", None),
+            ("1.2.3", None),
+            ("ten", None),
+        ];
+
+        for (i, o) in test_cases {
+            assert_eq!(parse_delay_value(i), o, "{}", i);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "crawl-delay")]
+    fn test_crawl_delay_fractional() {
+        tokio_test::block_on(async {
+            let example_robots = r#"
+            User-agent: foobar
+            Crawl-Delay: 1.5
+            Crawl-Delay: 0.5
+
+            User-agent: barfoo
+            Crawl-Delay: soon
+            Crawl-Delay: later
+            "#
+            .as_bytes();
+
+            let parser = Compiler::new("foobar");
+            let foobar_machine = parser.compile(example_robots).await.unwrap();
+            assert_eq!(Some(Duration::from_millis(500)), foobar_machine.delay());
+            assert_eq!(None, foobar_machine.invalid_delay());
+
+            let parser = Compiler::new("barfoo");
+            let barfoo_machine = parser.compile(example_robots).await.unwrap();
+            assert_eq!(None, barfoo_machine.delay());
+            assert_eq!(Some("soon"), barfoo_machine.invalid_delay());
+
+            let parser = Compiler::new("foobar").with_min_delay(Duration::from_secs(1));
+            let foobar_machine = parser.compile(example_robots).await.unwrap();
+            assert_eq!(Some(Duration::from_secs(1)), foobar_machine.delay());
+
+            let parser = Compiler::new("foobar").with_max_delay(Duration::from_millis(100));
+            let foobar_machine = parser.compile(example_robots).await.unwrap();
+            assert_eq!(Some(Duration::from_millis(100)), foobar_machine.delay());
+        });
+    }
+
+    #[test]
+    fn test_parse_spacing() {
+        let test_cases = vec![
+            ("Disallow : /a", ParsedRule::Disallow("/a".into())),
+            ("Disallow:/a:b", ParsedRule::Disallow("/a:b".into())),
+            ("\tAllow:\t/a\t", ParsedRule::Allow("/a".into())),
+        ];
+
+        for (i, o) in test_cases {
+            assert_eq!(parse_line(i.into()), ParsedLine::Rule(o));
+        }
+    }
+
+    #[test]
+    fn test_sitemaps() {
+        tokio_test::block_on(async {
+            let example_robots = r#"
+            Sitemap: https://example.com/sitemap.xml
+            User-agent: foobar
+            Sitemap: https://example.com/news.xml
+            Disallow: /
+            "#
+            .as_bytes();
+
+            let parser = Compiler::new("foobar");
+            let machine = parser.compile(example_robots).await.unwrap();
+            assert_eq!(false, machine.allow("/"));
+            assert_eq!(
+                &[
+                    "https://example.com/sitemap.xml",
+                    "https://example.com/news.xml"
+                ],
+                machine.sitemaps()
+            );
+            assert!(machine.unknown_directives().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_parse_global() {
+        assert_eq!(
+            parse_line("Host: www.example.com # mirror".into()),
+            ParsedLine::Global(ParsedGlobal::Host("www.example.com".into()))
+        );
+        assert_eq!(
+            parse_line("clean-param: ref&sid /forum/".into()),
+            ParsedLine::Global(ParsedGlobal::CleanParam("ref&sid /forum/".into()))
+        );
+    }
+
+    #[test]
+    fn test_yandex_directives() {
+        tokio_test::block_on(async {
+            let example_robots = r#"
+            User-agent: yandex
+            Disallow: /admin
+            Clean-param: sid&ref /forum/
+            Host: https://www.example.com
+            Disallow: /private
+
+            User-agent: *
+            Disallow: /
+            Host: example.com
+            Clean-param: utm_source
+            "#
+            .as_bytes();
+
+            let parser = Compiler::new("yandex");
+            let machine = parser.compile(example_robots).await.unwrap();
+
+            // Global directives don't end the group they appear in.
+            assert_eq!(false, machine.allow("/private"));
+            assert_eq!(true, machine.allow("/index.html"));
+            assert_eq!(Some("https://www.example.com"), machine.host());
+            assert_eq!(2, machine.clean_params().len());
+            assert_eq!(
+                "/forum/t.php?id=1",
+                machine.canonicalize("/forum/t.php?sid=2&id=1&utm_source=x")
+            );
+
+            let parser = Compiler::new("imabot");
+            let machine = parser.compile(example_robots).await.unwrap();
+            assert_eq!(false, machine.allow("/index.html"));
+            assert_eq!(Some("https://www.example.com"), machine.host());
+            assert_eq!(2, machine.clean_params().len());
+        });
+    }
+
     #[test]
     fn test_end_to_end() {
         tokio_test::block_on(async {
@@ -429,8 +1454,7 @@ mod tests {
     fn test_invalid_1() {
         tokio_test::block_on(async {
             let example_robots = r#"
-            # Instead of treating this as an error, we'll just consider
-            # this behavior undefined.
+            # Like Google, rules outside of a group are ignored.
             Allow: /
 
             User-agent: jones
@@ -445,8 +1469,7 @@ mod tests {
             let parser = Compiler::new("imabot");
             let imabot_machine = parser.compile(example_robots).await.unwrap();
 
-            // Everything is allowed because next_header() returns None
-            assert_eq!(true, foobar_machine.allow("/index.html"));
+            assert_eq!(false, foobar_machine.allow("/index.html"));
             assert_eq!(true, imabot_machine.allow("/index.html"));
         });
     }
@@ -476,6 +1499,310 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_match_semantics() {
+        tokio_test::block_on(async {
+            let example_robots = r#"
+            User-agent: foobot
+            Disallow: /a
+            Allow: /a/b
+            Allow: /c
+            Disallow: /c
+            Disallow: /*.php$
+            Allow: /index.html
+            "#
+            .as_bytes();
+
+            let test_cases = vec![
+                ("/a/b", true, false, true),
+                ("/a/c", false, false, false),
+                ("/c", true, true, false),
+                ("/x.php", false, true, false),
+                ("/*.php$", true, false, true),
+                ("/", true, true, true),
+            ];
+
+            let compile = |semantics| async move {
+                Compiler::new("foobot")
+                    .with_semantics(semantics)
+                    .compile(example_robots)
+                    .await
+                    .unwrap()
+            };
+            let rfc = compile(MatchSemantics::Rfc9309).await;
+            let original = compile(MatchSemantics::Original).await;
+            let bing = compile(MatchSemantics::Bing).await;
+            for (path, rfc_allow, original_allow, bing_allow) in test_cases {
+                assert_eq!(rfc_allow, rfc.allow(path), "{}", path);
+                assert_eq!(original_allow, original.allow(path), "{}", path);
+                assert_eq!(bing_allow, bing.allow(path), "{}", path);
+            }
+        });
+    }
+
+    #[test]
+    fn test_case_folding() {
+        tokio_test::block_on(async {
+            let example_robots = r#"
+            User-agent: foobot
+            Disallow: /Admin
+            Allow: /admin/public
+            Disallow: /*.PDF$
+            Disallow: /Ärger
+            Disallow: /%C3%96l
+            "#
+            .as_bytes();
+
+            let test_cases = vec![
+                ("/Admin", false, false, false),
+                ("/ADMIN/x", true, false, false),
+                ("/Admin/Public", false, true, true),
+                ("/a.pdf", true, false, false),
+                ("/%C3%84rger", false, false, false),
+                ("/%c3%84RGER", true, false, false),
+                ("/%C3%A4rger", true, true, false),
+                ("/%C3%B6l", true, true, false),
+                ("/%c3%b6L", true, true, false),
+                ("/index.html", true, true, true),
+            ];
+
+            let compile = |case_folding| async move {
+                Compiler::new("foobot")
+                    .with_case_folding(case_folding)
+                    .compile(example_robots)
+                    .await
+                    .unwrap()
+            };
+            let none = compile(CaseFolding::None).await;
+            let ascii = compile(CaseFolding::Ascii).await;
+            let unicode = compile(CaseFolding::Unicode).await;
+            for (path, none_allow, ascii_allow, unicode_allow) in test_cases {
+                assert_eq!(none_allow, none.allow(path), "{}", path);
+                assert_eq!(ascii_allow, ascii.allow(path), "{}", path);
+                assert_eq!(unicode_allow, unicode.allow(path), "{}", path);
+            }
+        });
+    }
+
+    #[test]
+    fn test_robots_txt_allowed() {
+        tokio_test::block_on(async {
+            let example_robots = r#"
+            User-agent: *
+            Disallow: /
+            Disallow: /robots.txt
+            "#
+            .as_bytes();
+
+            for semantics in [
+                MatchSemantics::Rfc9309,
+                MatchSemantics::Original,
+                MatchSemantics::Bing,
+            ] {
+                for case_folding in [CaseFolding::None, CaseFolding::Unicode] {
+                    let compiler = Compiler::new("foobot")
+                        .with_semantics(semantics)
+                        .with_case_folding(case_folding);
+                    let cylon = compiler.compile(example_robots).await.unwrap();
+                    assert!(cylon.allow("/robots.txt"), "{:?}", semantics);
+                    assert!(!cylon.allow("/robots.txt2"), "{:?}", semantics);
+                    assert!(!cylon.allow("/"), "{:?}", semantics);
+
+                    let cylon = compiler
+                        .with_robots_txt_allowed(false)
+                        .compile(example_robots)
+                        .await
+                        .unwrap();
+                    assert!(!cylon.allow("/robots.txt"), "{:?}", semantics);
+                }
+            }
+
+            // With nothing disallowed, there is nothing to add.
+            let allow_all = "User-agent: *\nAllow: /a\n".as_bytes();
+            let cylon = Compiler::new("foobot").compile(allow_all).await.unwrap();
+            let exact = Compiler::new("foobot")
+                .with_robots_txt_allowed(false)
+                .compile(allow_all)
+                .await
+                .unwrap();
+            assert_eq!(exact.state_count(), cylon.state_count());
+        });
+    }
+
+    #[test]
+    fn test_content_policy() {
+        tokio_test::block_on(async {
+            // Each file, how it is served, its kind and whether /a is allowed
+            // if it is parsed anyway.
+            let test_cases: Vec<(&[u8], Option<&str>, ContentKind, bool)> = vec![
+                (
+                    b"User-agent: *\nDisallow: /a",
+                    None,
+                    ContentKind::RobotsTxt,
+                    false,
+                ),
+                (
+                    b"<html><body>\nUser-agent: *\nDisallow: /a\n</body></html>",
+                    None,
+                    ContentKind::Html,
+                    false,
+                ),
+                (
+                    b"User-agent: *\nDisallow: /a",
+                    Some("image/gif"),
+                    ContentKind::Binary,
+                    false,
+                ),
+                (
+                    b"\x1f\x8b\x08\x00User-agent: *\nDisallow: /a",
+                    None,
+                    ContentKind::Binary,
+                    true,
+                ),
+                (b"\n\n", Some("text/plain"), ContentKind::Empty, true),
+            ];
+
+            for (file, content_type, kind, parsed_allow) in test_cases {
+                let compiler = Compiler::new("foobot");
+                let cylon = compiler
+                    .compile_with_content_type(file, content_type)
+                    .await
+                    .unwrap();
+                assert_eq!(kind, cylon.content_kind());
+                assert_eq!(parsed_allow, cylon.allow("/a"), "{:?}", kind);
+
+                for (policy, allow) in [
+                    (ContentPolicy::Parse, parsed_allow),
+                    (ContentPolicy::AllowAll, true),
+                    (ContentPolicy::DisallowAll, false),
+                ] {
+                    let compiler = compiler.clone().with_content_policy(kind, policy);
+                    let cylon = compiler
+                        .compile_with_content_type(file, content_type)
+                        .await
+                        .unwrap();
+                    assert_eq!(kind, cylon.content_kind(), "{:?}", policy);
+                    assert_eq!(allow, cylon.allow("/a"), "{:?} {:?}", kind, policy);
+                    assert!(cylon.allow("/robots.txt"), "{:?} {:?}", kind, policy);
+                }
+            }
+
+            // Exported regexes follow the same policy.
+            let page = b"<!DOCTYPE html>\nUser-agent: *\nDisallow: /a";
+            let compiler = Compiler::new("foobot");
+            let regexes = compiler.compile_regexes(&page[..]).await.unwrap();
+            assert_eq!("^/a", regexes[1].pattern);
+            let compiler = compiler.with_content_policy(ContentKind::Html, ContentPolicy::AllowAll);
+            assert!(compiler
+                .compile_regexes(&page[..])
+                .await
+                .unwrap()
+                .is_empty());
+
+            // A file longer than the sniffed prefix is read to the end.
+            let mut long = "User-agent: *\n".repeat(2 * SNIFF_LEN);
+            long.push_str("Disallow: /a\n");
+            let cylon = Compiler::new("foobot")
+                .compile(long.as_bytes())
+                .await
+                .unwrap();
+            assert!(!cylon.allow("/a"));
+        });
+    }
+
+    #[test]
+    fn test_unsupported_encoding() {
+        tokio_test::block_on(async {
+            let text = "\u{FEFF}User-agent: *\nDisallow: /\n";
+            let le: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+            let be: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+            let compiler = Compiler::new("foobot");
+
+            let result = compiler.compile(&le[..]).await;
+            assert!(matches!(result, Err(Error::Encoding("UTF-16LE"))));
+            let result = compiler.compile(&be[..]).await;
+            assert!(matches!(result, Err(Error::Encoding("UTF-16BE"))));
+
+            // A UTF-8 byte order mark is skipped as usual.
+            let cylon = compiler.compile(text.as_bytes()).await.unwrap();
+            assert!(!cylon.allow("/a"));
+        });
+    }
+
+    #[test]
+    fn test_limits() {
+        tokio_test::block_on(async {
+            let file = "User-agent: *\nDisallow: /a\nDisallow: /b\n".as_bytes();
+            let compiler = Compiler::new("foobot");
+            let states = compiler.compile(file).await.unwrap().state_count();
+
+            let limited = compiler.clone().with_max_size(file.len());
+            assert!(limited.compile(file).await.is_ok());
+            let result = compiler.clone().with_max_size(16).compile(file).await;
+            assert!(matches!(
+                result,
+                Err(Error::SizeLimitExceeded { limit: 16 })
+            ));
+            let result = compiler
+                .clone()
+                .with_max_size(16)
+                .compile_regexes(file)
+                .await;
+            assert!(matches!(
+                result,
+                Err(Error::SizeLimitExceeded { limit: 16 })
+            ));
+
+            let limited = compiler.clone().with_max_states(states);
+            assert!(limited.compile(file).await.is_ok());
+            let result = compiler.clone().with_max_states(4).compile(file).await;
+            assert!(matches!(
+                result,
+                Err(Error::StateLimitExceeded { limit: 4, states: s }) if s == states
+            ));
+        });
+    }
+
+    #[test]
+    fn test_invalid_user_agent() {
+        tokio_test::block_on(async {
+            let example_robots = "User-agent: googlebot\nDisallow: /\n".as_bytes();
+            for user_agent in ["", " ", "/2.1", "(googlebot)"] {
+                let result = Compiler::new(user_agent).compile(example_robots).await;
+                assert!(
+                    matches!(result, Err(Error::InvalidConfiguration(_))),
+                    "{:?}",
+                    user_agent
+                );
+            }
+
+            // Only the product token at the start is matched.
+            let test_cases = vec![
+                ("Googlebot", false),
+                ("Googlebot/2.1 (+http://www.google.com/bot.html)", false),
+                (" googlebot ", false),
+                ("Mozilla/5.0 (compatible; Googlebot/2.1)", true),
+            ];
+            for (user_agent, allow) in test_cases {
+                let compiler = Compiler::new(user_agent);
+                let cylon = compiler.compile(example_robots).await.unwrap();
+                assert_eq!(allow, cylon.allow("/"), "{:?}", user_agent);
+            }
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "crawl-delay")]
+    fn test_invalid_configuration() {
+        tokio_test::block_on(async {
+            let compiler = Compiler::new("foobot")
+                .with_min_delay(Duration::from_secs(10))
+                .with_max_delay(Duration::from_secs(1));
+            let result = compiler.compile("".as_bytes()).await;
+            assert!(matches!(result, Err(Error::InvalidConfiguration(_))));
+        });
+    }
+
     #[test]
     fn test_unicode_support() {
         tokio_test::block_on(async {
@@ -501,9 +1828,14 @@ mod tests {
             let machine = parser.compile(example_robots).await.unwrap();
 
             assert_eq!(true, machine.allow("/index.html"));
+            // Patterns are percent-encoded, but paths must already be.
+            assert_eq!(
+                false,
+                machine.allow("/wiki/%D7%95%D7%99%D7%A7%D7%99%D7%A4%D7%93%D7%99%D7%94:%D7%A2%D7%A8%D7%9B%D7%99%D7%9D_%D7%9C%D7%90_%D7%A7%D7%99%D7%99%D7%9E%D7%99%D7%9D_%D7%95%D7%9E%D7%95%D7%92%D7%A0%D7%99%D7%9D")
+            );
             assert_eq!(
                 false,
-                machine.allow("/wiki/ויקיפדיה:ערכים_לא_קיימים_ומוגנים")
+                machine.allow("/wiki/%D7%95%D7%99%D7%A7%D7%99%D7%A4%D7%93%D7%99%D7%94%3A%D7%93%D7%A4%D7%99%D7%9D_%D7%9C%D7%90_%D7%A7%D7%99%D7%99%D7%9E%D7%99%D7%9D_%D7%95%D7%9E%D7%95%D7%92%D7%A0%D7%99%D7%9D")
             );
         });
     }
@@ -0,0 +1,227 @@
+use crate::error::Error;
+use crate::nfa::Cylon;
+use crate::parse::{Compiler, GroupParser};
+use crate::sniff::{self, ContentKind, ContentPolicy, SNIFF_LEN};
+
+/// Compiles a robots.txt file that arrives in chunks, e.g. from the body
+/// stream of an HTTP client, without wrapping it in an `AsyncRead`. Lines
+/// may be split anywhere across chunks, and the result is the same Cylon
+/// that [`Compiler::compile`] gives for the whole file.
+///
+/// ```
+/// use cylon::{Compiler, PushParser};
+///
+/// let mut parser = PushParser::new(&Compiler::new("foobot"));
+/// parser.feed(b"User-agent: foobot\nDisall").unwrap();
+/// parser.feed(b"ow: /private\n").unwrap();
+/// let cylon = parser.finish().unwrap();
+/// assert!(!cylon.allow("/private"));
+/// ```
+#[derive(Debug)]
+pub struct PushParser {
+    compiler: Compiler,
+    content_type: Option<String>,
+    /// The start of the file, kept until there is enough of it to tell
+    /// what kind of content it is.
+    prefix: Vec<u8>,
+    kind: Option<ContentKind>,
+    /// The groups of the file, if its kind is parsed.
+    groups: Option<GroupParser>,
+    size: usize,
+}
+
+impl PushParser {
+    pub fn new(compiler: &Compiler) -> Self {
+        Self {
+            compiler: compiler.clone(),
+            content_type: None,
+            prefix: vec![],
+            kind: None,
+            groups: None,
+            size: 0,
+        }
+    }
+
+    /// Also use the `Content-Type` the file is served with to tell what
+    /// kind of content it is, like
+    /// [`Compiler::compile_with_content_type`].
+    pub fn with_content_type(mut self, content_type: &str) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    /// Parse the next chunk of the file. Fails if the file grows beyond
+    /// the Compiler's size limit.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), Error> {
+        self.size += chunk.len();
+        self.check_size()?;
+        match (self.kind, &mut self.groups) {
+            (Some(_), Some(groups)) => groups.feed(chunk),
+            (Some(_), None) => {}
+            (None, _) => {
+                self.prefix.extend_from_slice(chunk);
+                if self.prefix.len() >= SNIFF_LEN {
+                    self.sniff(false)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a whole group naming the user agent has been read. Rules for
+    /// `*` no longer apply then, so a caller that only needs to match paths
+    /// can stop reading and call [`PushParser::finish`] early. The result
+    /// then misses any later group naming the user agent, which RFC 9309
+    /// merges with the first, and any later `Sitemap` lines.
+    pub fn found_group(&self) -> bool {
+        self.groups.as_ref().is_some_and(GroupParser::found_group)
+    }
+
+    /// Compile what has been fed so far, treating it as the whole file.
+    pub fn finish(mut self) -> Result<Cylon, Error> {
+        self.compiler.validate()?;
+        self.check_size()?;
+        let kind = match self.kind {
+            Some(kind) => kind,
+            None => self.sniff(true)?,
+        };
+        let groups = self.groups.map(GroupParser::finish);
+        self.compiler.build(kind, groups)
+    }
+
+    fn check_size(&self) -> Result<(), Error> {
+        match self.compiler.max_size() {
+            Some(limit) if self.size > limit => Err(Error::SizeLimitExceeded { limit }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Decide what kind of content the file is from its prefix, and start
+    /// parsing it if the Compiler's policy is to parse that kind.
+    fn sniff(&mut self, complete: bool) -> Result<ContentKind, Error> {
+        let prefix = &self.prefix[..self.prefix.len().min(SNIFF_LEN)];
+        if let Some(encoding) = sniff::unsupported_encoding(prefix) {
+            return Err(Error::Encoding(encoding));
+        }
+        let kind = ContentKind::sniff_prefix(prefix, self.content_type.as_deref(), complete);
+        if self.compiler.content_policy(kind) == ContentPolicy::Parse {
+            let mut groups = GroupParser::new(self.compiler.user_agent());
+            groups.feed(&self.prefix);
+            self.groups = Some(groups);
+        }
+        self.kind = Some(kind);
+        self.prefix = vec![];
+        Ok(kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile_chunked(compiler: &Compiler, file: &[u8], chunk_len: usize) -> Cylon {
+        let mut parser = PushParser::new(compiler);
+        for chunk in file.chunks(chunk_len) {
+            parser.feed(chunk).unwrap();
+        }
+        parser.finish().unwrap()
+    }
+
+    #[test]
+    fn test_same_as_compile() {
+        let long_line = format!("Disallow: /{}\n", "x".repeat(20_000));
+        let test_cases: Vec<Vec<u8>> = vec![
+            b"".to_vec(),
+            b"User-agent: foobot\nDisallow: /a\nAllow: /a/b".to_vec(),
+            b"\xEF\xBB\xBFUser-agent: *\r\nDisallow: /a\r\n\r\nSitemap: /s.xml\r".to_vec(),
+            b"\xEF\xBBUser-agent: *\rDisallow: /a\rUser-agent: foobot\rDisallow: /b".to_vec(),
+            b"Disallow: /x\nFoo: bar\nUser-agent: foobot\nBar: baz\n".to_vec(),
+            b"User-agent: barbot\nDisallow: /\nUser-agent: foobot\nDisallow: /\xff\n".to_vec(),
+            b"User-agent: foobot\nDisallow: /a\nUser-agent: *\nUser-agent: foobot\nAllow: /"
+                .to_vec(),
+            format!("User-agent: *\n{}Disallow: /b\n", long_line).into_bytes(),
+            b"<!DOCTYPE html>\nUser-agent: *\nDisallow: /\n".to_vec(),
+            format!("{}User-agent: *\nDisallow: /", "\n".repeat(2 * SNIFF_LEN)).into_bytes(),
+        ];
+        let compilers = [
+            Compiler::new("foobot"),
+            Compiler::new("foobot").with_content_policy(ContentKind::Html, ContentPolicy::AllowAll),
+        ];
+
+        for (compiler, file) in compilers
+            .iter()
+            .flat_map(|c| test_cases.iter().map(move |f| (c, f)))
+        {
+            let expected = tokio_test::block_on(compiler.compile(&file[..])).unwrap();
+            for chunk_len in [1, 2, 3, 7, 1000, file.len().max(1)] {
+                let actual = compile_chunked(compiler, file, chunk_len);
+                assert_eq!(
+                    expected.to_bytes(),
+                    actual.to_bytes(),
+                    "{:?} in chunks of {}",
+                    String::from_utf8_lossy(&file[..file.len().min(100)]),
+                    chunk_len
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_content_type() {
+        let compiler = Compiler::new("foobot");
+        let mut parser = PushParser::new(&compiler).with_content_type("image/png");
+        parser.feed(b"User-agent: *\nDisallow: /").unwrap();
+        let cylon = parser.finish().unwrap();
+        assert_eq!(ContentKind::Binary, cylon.content_kind());
+        assert!(!cylon.allow("/a"));
+
+        let compiler = compiler.with_content_policy(ContentKind::Binary, ContentPolicy::AllowAll);
+        let mut parser = PushParser::new(&compiler).with_content_type("image/png");
+        parser.feed(b"User-agent: *\nDisallow: /").unwrap();
+        assert!(parser.finish().unwrap().allow("/a"));
+    }
+
+    #[test]
+    fn test_found_group() {
+        let mut parser = PushParser::new(&Compiler::new("foobot"));
+        parser.feed(b"User-agent: *\nDisallow: /\n").unwrap();
+        parser.feed(b"User-agent: foobot\nDisallow: /a\n").unwrap();
+        // The prefix hasn't been parsed until there's enough to sniff.
+        assert!(!parser.found_group());
+        parser.feed(&[b'\n'; SNIFF_LEN]).unwrap();
+        assert!(!parser.found_group());
+        parser.feed(b"User-agent: barbot\n").unwrap();
+        assert!(parser.found_group());
+
+        let cylon = parser.finish().unwrap();
+        assert!(cylon.allow("/"));
+        assert!(!cylon.allow("/a"));
+    }
+
+    #[test]
+    fn test_size_limit() {
+        let compiler = Compiler::new("foobot").with_max_size(8);
+        let mut parser = PushParser::new(&compiler);
+        parser.feed(b"Disallow").unwrap();
+        let result = parser.feed(b": /");
+        assert!(matches!(result, Err(Error::SizeLimitExceeded { limit: 8 })));
+        assert!(parser.finish().is_err());
+    }
+
+    #[test]
+    fn test_unsupported_encoding() {
+        let file: Vec<u8> = "\u{FEFF}User-agent: *\nDisallow: /\n"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let compiler = Compiler::new("foobot");
+        for chunk_len in [1, 7, file.len()] {
+            let mut parser = PushParser::new(&compiler);
+            let fed = file
+                .chunks(chunk_len)
+                .try_for_each(|chunk| parser.feed(chunk));
+            let result = fed.and_then(|_| parser.finish());
+            assert!(matches!(result, Err(Error::Encoding("UTF-16LE"))));
+        }
+    }
+}
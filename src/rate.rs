@@ -0,0 +1,286 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_derive::{Deserialize, Serialize};
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// A daily window of time, in UTC, during which a crawler may visit the
+/// site. This is parsed from the `Visit-time` directive of the extended
+/// robots exclusion standard, e.g. `Visit-time: 0100-0600`.
+///
+/// Windows may wrap around midnight, e.g. `2300-0200`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VisitTime {
+    start: u16,
+    end: u16,
+}
+
+impl VisitTime {
+    /// Create a window from the given `(hour, minute)` pairs in UTC. Returns
+    /// None if either time is out of range.
+    pub fn new(start: (u8, u8), end: (u8, u8)) -> Option<Self> {
+        Some(Self {
+            start: minute_of_day(start)?,
+            end: minute_of_day(end)?,
+        })
+    }
+
+    /// The start of the window as an `(hour, minute)` pair in UTC.
+    pub fn start(&self) -> (u8, u8) {
+        ((self.start / 60) as u8, (self.start % 60) as u8)
+    }
+
+    /// The end of the window as an `(hour, minute)` pair in UTC.
+    pub fn end(&self) -> (u8, u8) {
+        ((self.end / 60) as u8, (self.end % 60) as u8)
+    }
+
+    /// Whether the given timestamp falls within this window. The end of
+    /// the window is inclusive to the minute.
+    pub fn contains(&self, time: SystemTime) -> bool {
+        let minute = utc_minute_of_day(time);
+        if self.start <= self.end {
+            self.start <= minute && minute <= self.end
+        } else {
+            self.start <= minute || minute <= self.end
+        }
+    }
+
+    /// Parse a window like `0100-0600` or `01:00-06:00`.
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.splitn(2, '-');
+        let start = parse_clock(parts.next()?.trim())?;
+        let end = parse_clock(parts.next()?.trim())?;
+        Self::new(start, end)
+    }
+}
+
+impl fmt::Display for VisitTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (sh, sm) = self.start();
+        let (eh, em) = self.end();
+        write!(f, "{:02}{:02}-{:02}{:02}", sh, sm, eh, em)
+    }
+}
+
+/// The maximum rate at which a crawler should request pages, parsed from
+/// the `Request-rate` directive of the extended robots exclusion standard,
+/// e.g. `Request-rate: 1/10s`. A rate may optionally apply only during a
+/// daily window, e.g. `Request-rate: 1/10s 0100-0600`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RequestRateFields")]
+pub struct RequestRate {
+    requests: u32,
+    period: Duration,
+    window: Option<VisitTime>,
+}
+
+/// The serialized fields of a RequestRate, which are checked before it
+/// is deserialized.
+#[derive(Deserialize)]
+struct RequestRateFields {
+    requests: u32,
+    period: Duration,
+    window: Option<VisitTime>,
+}
+
+impl TryFrom<RequestRateFields> for RequestRate {
+    type Error = &'static str;
+
+    fn try_from(fields: RequestRateFields) -> Result<Self, Self::Error> {
+        RequestRate::new(fields.requests, fields.period, fields.window)
+            .ok_or("request rate must have requests and a period")
+    }
+}
+
+impl RequestRate {
+    /// Returns None unless there is at least one request and the period
+    /// is not zero.
+    pub(crate) fn new(requests: u32, period: Duration, window: Option<VisitTime>) -> Option<Self> {
+        if requests == 0 || period.is_zero() {
+            return None;
+        }
+        Some(Self {
+            requests,
+            period,
+            window,
+        })
+    }
+
+    /// The number of requests allowed per period.
+    pub fn requests(&self) -> u32 {
+        self.requests
+    }
+
+    /// The period over which the requests are counted.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// The window of the day this rate applies to, if it is restricted.
+    pub fn window(&self) -> Option<VisitTime> {
+        self.window
+    }
+
+    /// The minimum interval between two requests that respects this rate.
+    pub fn interval(&self) -> Duration {
+        // A rate of no requests can't be constructed, but would never
+        // allow one.
+        self.period
+            .checked_div(self.requests)
+            .unwrap_or(Duration::MAX)
+    }
+
+    /// Whether this rate applies at the given timestamp.
+    pub fn applies_at(&self, time: SystemTime) -> bool {
+        self.window.is_none_or(|w| w.contains(time))
+    }
+
+    /// Parse a rate like `1/10s`, `3/1m` or `1/5 0100-0600`. The period
+    /// unit may be one of `s`, `m`, `h` or `d` and defaults to seconds.
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.split_whitespace();
+        let rate = parts.next()?;
+        let window = match parts.next() {
+            Some(window) => Some(VisitTime::parse(window)?),
+            None => None,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+
+        let mut rate = rate.splitn(2, '/');
+        let requests = rate.next()?.parse::<u32>().ok()?;
+        let period = rate.next()?;
+        let (count, unit) = match period.find(|c: char| !c.is_ascii_digit()) {
+            Some(i) => period.split_at(i),
+            None => (period, "s"),
+        };
+        let scale = match &unit.to_ascii_lowercase()[..] {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => return None,
+        };
+        let period = count.parse::<u64>().ok()?.checked_mul(scale)?;
+        Self::new(requests, Duration::from_secs(period), window)
+    }
+}
+
+fn minute_of_day((hour, minute): (u8, u8)) -> Option<u16> {
+    if hour < 24 && minute < 60 {
+        Some(u16::from(hour) * 60 + u16::from(minute))
+    } else {
+        None
+    }
+}
+
+/// Parse a time of day written as `HHMM` or `HH:MM`.
+fn parse_clock(value: &str) -> Option<(u8, u8)> {
+    let (hour, minute) = match value.split_once(':') {
+        Some(split) => split,
+        None => value.split_at_checked(2)?,
+    };
+    let two_digits = |s: &str| s.len() == 2 && s.bytes().all(|b| b.is_ascii_digit());
+    if !two_digits(hour) || !two_digits(minute) {
+        return None;
+    }
+    Some((hour.parse().ok()?, minute.parse().ok()?))
+}
+
+fn utc_minute_of_day(time: SystemTime) -> u16 {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs(),
+        Err(before) => {
+            // Times before the epoch count backwards from midnight.
+            let secs = before.duration().as_secs() % 86400;
+            (86400 - secs) % 86400
+        }
+    };
+    ((secs / 60) % u64::from(MINUTES_PER_DAY)) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u64, minute: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(3 * 86400 + hour * 3600 + minute * 60)
+    }
+
+    #[test]
+    fn test_parse_visit_time() {
+        let test_cases = vec![
+            ("0100-0600", Some(((1, 0), (6, 0)))),
+            ("01:00-06:30", Some(((1, 0), (6, 30)))),
+            ("2300 - 0200", Some(((23, 0), (2, 0)))),
+            ("2400-0100", None),
+            ("0160-0200", None),
+            ("100-600", None),
+            ("0:100-0600", None),
+            ("01:0-06:00", None),
+            ("01:00:00-0600", None),
+            ("0100", None),
+        ];
+
+        for (i, o) in test_cases {
+            let actual = VisitTime::parse(i).map(|v| (v.start(), v.end()));
+            assert_eq!(actual, o, "{}", i);
+        }
+    }
+
+    #[test]
+    fn test_visit_time_contains() {
+        let window = VisitTime::parse("0100-0600").unwrap();
+        assert!(window.contains(at(1, 0)));
+        assert!(window.contains(at(6, 0)));
+        assert!(!window.contains(at(6, 1)));
+        assert!(!window.contains(at(0, 59)));
+
+        let window = VisitTime::parse("2300-0200").unwrap();
+        assert!(window.contains(at(23, 30)));
+        assert!(window.contains(at(1, 30)));
+        assert!(!window.contains(at(12, 0)));
+    }
+
+    #[test]
+    fn test_parse_request_rate() {
+        let test_cases = vec![
+            ("1/10s", Some((1, 10, None))),
+            ("1/10", Some((1, 10, None))),
+            ("3/1m", Some((3, 60, None))),
+            ("100/1H", Some((100, 3600, None))),
+            ("1/1d", Some((1, 86400, None))),
+            ("1/10s 0100-0600", Some((1, 10, Some("0100-0600")))),
+            ("0/10s", None),
+            ("1/0s", None),
+            ("1/10y", None),
+            ("1/10s 0100", None),
+            ("10s", None),
+        ];
+
+        for (i, o) in test_cases {
+            let actual = RequestRate::parse(i).map(|r| {
+                let window = r.window().map(|w| w.to_string());
+                (r.requests(), r.period().as_secs(), window)
+            });
+            let o = o.map(|(r, p, w)| (r, p, w.map(String::from)));
+            assert_eq!(actual, o, "{}", i);
+        }
+    }
+
+    #[test]
+    fn test_request_rate_interval() {
+        let rate = RequestRate::parse("4/1m").unwrap();
+        assert_eq!(Duration::from_secs(15), rate.interval());
+
+        let rate = RequestRate::parse("1/10s 0100-0600").unwrap();
+        assert!(rate.applies_at(at(2, 0)));
+        assert_eq!(None, RequestRate::new(0, Duration::from_secs(1), None));
+        assert_eq!(None, RequestRate::new(1, Duration::ZERO, None));
+        assert!(!rate.applies_at(at(12, 0)));
+    }
+}
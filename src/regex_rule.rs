@@ -0,0 +1,260 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::fold::CaseFolding;
+use crate::nfa::{Rule, ROBOTS_TXT};
+use crate::semantics::MatchSemantics;
+
+/// Characters with a special meaning in common regular expression
+/// dialects, which must be escaped to match literally.
+const META: &[u8] = b"\\.+*?()|[]{}^$";
+/// A `*` matches any sequence of bytes.
+const WILDCARD_BYTE: u8 = b'*';
+
+/// A single robots.txt rule as a regular expression, for systems that
+/// can't use a Cylon directly.
+///
+/// A list of rules from
+/// [`Compiler::compile_regexes`](crate::Compiler::compile_regexes) is
+/// ordered by precedence. The first rule whose pattern matches a path
+/// decides whether it is allowed, and a path that matches no pattern is
+/// allowed. Like [`Cylon::allow`](crate::Cylon::allow), an empty path
+/// should be matched as `/`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegexRule {
+    /// A regular expression anchored at the start of the path. `.*` stands
+    /// for a `*` wildcard and it ends with `$` if the rule does, unless the
+    /// rules were compiled with [`MatchSemantics::Original`]. Only syntax
+    /// shared by POSIX extended, PCRE and Rust regexes is used.
+    pub pattern: String,
+    /// Whether paths matching the pattern are allowed.
+    pub allow: bool,
+    /// The precedence of the rule. Under RFC 9309 it is the length of the
+    /// original pattern, in which a trailing run of wildcards counts as one
+    /// character. When several patterns match a path, the one with the
+    /// highest priority wins, and Allow wins a tie. Other semantics give
+    /// every rule a distinct priority.
+    pub priority: usize,
+}
+
+impl RegexRule {
+    fn new(
+        rule: &[u8],
+        allow: bool,
+        priority: usize,
+        semantics: MatchSemantics,
+        folding: CaseFolding,
+    ) -> Self {
+        let special_characters = semantics.special_characters();
+        let (body, anchored) = match rule.strip_suffix(b"$") {
+            Some(body) if special_characters => (body, true),
+            _ => (rule, false),
+        };
+
+        let mut pattern = b"^".to_vec();
+        let mut last_wildcard = false;
+        for variants in folding.units(body) {
+            let is_wildcard = special_characters && variants == [[WILDCARD_BYTE]];
+            match is_wildcard {
+                // Repeated wildcards are equivalent to a single one.
+                true if last_wildcard => (),
+                true => pattern.extend_from_slice(b".*"),
+                false if variants.len() == 1 => push_escaped(&mut pattern, &variants[0], folding),
+                false => {
+                    pattern.push(b'(');
+                    for (i, variant) in variants.iter().enumerate() {
+                        if i > 0 {
+                            pattern.push(b'|');
+                        }
+                        push_escaped(&mut pattern, variant, folding);
+                    }
+                    pattern.push(b')');
+                }
+            }
+            last_wildcard = is_wildcard;
+        }
+        if anchored {
+            pattern.push(b'$');
+        }
+
+        Self {
+            pattern: String::from_utf8_lossy(&pattern).into_owned(),
+            allow,
+            priority,
+        }
+    }
+}
+
+/// Append bytes to a pattern so that they match literally, and match
+/// either case if case is folded.
+fn push_escaped(pattern: &mut Vec<u8>, bytes: &[u8], folding: CaseFolding) {
+    for b in bytes {
+        if folding != CaseFolding::None && b.is_ascii_alphabetic() {
+            pattern.extend_from_slice(&[
+                b'[',
+                b.to_ascii_lowercase(),
+                b.to_ascii_uppercase(),
+                b']',
+            ]);
+            continue;
+        }
+        if META.contains(b) {
+            pattern.push(b'\\');
+        }
+        pattern.push(*b);
+    }
+}
+
+/// Convert a group's rules into regular expressions ordered by precedence,
+/// with the same priorities the NFA gives them.
+pub(crate) fn from_rules(
+    rules: &[Rule],
+    semantics: MatchSemantics,
+    folding: CaseFolding,
+    allow_robots_txt: bool,
+) -> Vec<RegexRule> {
+    let mut regexes: Vec<RegexRule> = rules
+        .iter()
+        .enumerate()
+        .filter_map(|(i, rule)| {
+            let (inner, allow) = match rule {
+                Rule::Allow(inner) => (inner, true),
+                Rule::Disallow(inner) => (inner, false),
+            };
+            if inner.is_empty() {
+                return None;
+            }
+            let priority = semantics.weight(inner, allow, i, rules.len());
+            Some(RegexRule::new(inner, allow, priority, semantics, folding))
+        })
+        .collect();
+    if allow_robots_txt && regexes.iter().any(|r| !r.allow) {
+        let priority = regexes.iter().map(|r| r.priority + 1).max().unwrap_or(0);
+        let mut pattern = ROBOTS_TXT.to_vec();
+        pattern.push(b'$');
+        let rule = RegexRule::new(&pattern, true, priority, MatchSemantics::Rfc9309, folding);
+        regexes.push(rule);
+    }
+    regexes.sort_by_key(|r| std::cmp::Reverse((r.priority, r.allow)));
+    regexes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patterns() {
+        let test_cases = vec![
+            ("/", "^/", 1),
+            ("/fish", "^/fish", 5),
+            ("/fish*", "^/fish.*", 6),
+            ("/fish**", "^/fish.*", 6),
+            ("/*.php$", "^/.*\\.php$", 7),
+            ("/a**b", "^/a.*b", 5),
+            ("/$", "^/$", 2),
+            ("/a$b", "^/a\\$b", 4),
+            ("/(x)?[y]|{z}+^", "^/\\(x\\)\\?\\[y\\]\\|\\{z\\}\\+\\^", 14),
+            ("/ä", "^/ä", 3),
+        ];
+
+        for (rule, pattern, priority) in test_cases {
+            let regex = from_rules(
+                &[Rule::Allow(rule.as_bytes())],
+                MatchSemantics::Rfc9309,
+                CaseFolding::None,
+                false,
+            )
+            .remove(0);
+            assert_eq!(pattern, regex.pattern, "{}", rule);
+            assert_eq!(priority, regex.priority, "{}", rule);
+        }
+    }
+
+    #[test]
+    fn test_order() {
+        let rules = vec![
+            Rule::Allow(b"/a"),
+            Rule::Disallow(b""),
+            Rule::Disallow(b"/ab"),
+            Rule::Allow(b"/a*"),
+            Rule::Disallow(b"/"),
+        ];
+        let regexes = from_rules(&rules, MatchSemantics::Rfc9309, CaseFolding::None, false);
+        let order: Vec<(&str, bool)> = regexes.iter().map(|r| (&r.pattern[..], r.allow)).collect();
+        assert_eq!(
+            vec![
+                ("^/a.*", true),
+                ("^/ab", false),
+                ("^/a", true),
+                ("^/", false)
+            ],
+            order
+        );
+    }
+
+    #[test]
+    fn test_semantics() {
+        let rules = vec![
+            Rule::Disallow(b"/a"),
+            Rule::Allow(b"/a*"),
+            Rule::Allow(b"/ab$"),
+            Rule::Disallow(b"/ab$"),
+        ];
+        let test_cases = vec![
+            (
+                MatchSemantics::Original,
+                vec![
+                    ("^/a", false, 4),
+                    ("^/a\\*", true, 3),
+                    ("^/ab\\$", true, 2),
+                    ("^/ab\\$", false, 1),
+                ],
+            ),
+            (
+                MatchSemantics::Bing,
+                vec![
+                    ("^/ab$", false, 9),
+                    ("^/ab$", true, 8),
+                    ("^/a.*", true, 6),
+                    ("^/a", false, 5),
+                ],
+            ),
+        ];
+
+        for (semantics, expected) in test_cases {
+            let regexes = from_rules(&rules, semantics, CaseFolding::None, false);
+            let order: Vec<(&str, bool, usize)> = regexes
+                .iter()
+                .map(|r| (&r.pattern[..], r.allow, r.priority))
+                .collect();
+            assert_eq!(expected, order, "{:?}", semantics);
+        }
+    }
+
+    #[test]
+    fn test_robots_txt() {
+        let rules = vec![Rule::Disallow(b"/"), Rule::Allow(b"/a")];
+        let test_cases = vec![
+            (MatchSemantics::Rfc9309, 3),
+            (MatchSemantics::Original, 3),
+            (MatchSemantics::Bing, 5),
+        ];
+
+        for (semantics, priority) in test_cases {
+            let regexes = from_rules(&rules, semantics, CaseFolding::None, true);
+            assert_eq!(3, regexes.len(), "{:?}", semantics);
+            assert_eq!("^/robots\\.txt$", regexes[0].pattern, "{:?}", semantics);
+            assert!(regexes[0].allow, "{:?}", semantics);
+            assert_eq!(priority, regexes[0].priority, "{:?}", semantics);
+        }
+
+        // Without a Disallow there is nothing to exempt it from.
+        let regexes = from_rules(
+            &rules[1..],
+            MatchSemantics::Rfc9309,
+            CaseFolding::None,
+            true,
+        );
+        assert_eq!(1, regexes.len());
+    }
+}
@@ -0,0 +1,122 @@
+use std::io;
+use std::pin::Pin;
+
+use futures_util::io::AsyncRead;
+use futures_util::TryStreamExt;
+use reqwest::header::{HeaderName, CACHE_CONTROL, CONTENT_TYPE, LOCATION};
+use reqwest::redirect::Policy;
+
+use crate::error::Error;
+use crate::fetch::{HttpClient, HttpResponse};
+
+/// An HttpClient backed by [reqwest](https://docs.rs/reqwest), enabled
+/// with the `reqwest` feature. Enable one of reqwest's TLS features in your
+/// own crate to fetch `https` origins.
+#[derive(Debug, Clone)]
+pub struct ReqwestClient {
+    client: reqwest::Client,
+}
+
+impl ReqwestClient {
+    /// A client that identifies itself with the given user agent. Fails
+    /// with [`Error::Io`] if reqwest can't build the client, e.g. because
+    /// the user agent is not a valid header value.
+    pub fn new(user_agent: &str) -> Result<Self, Error> {
+        let client = reqwest::Client::builder()
+            .user_agent(user_agent)
+            .redirect(Policy::none())
+            .build()
+            .map_err(io::Error::other)?;
+        Ok(Self { client })
+    }
+
+    /// Wrap an existing client. It should be built with
+    /// `redirect(Policy::none())` so that redirects are limited by the
+    /// RobotsFetcher.
+    pub fn from_client(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+}
+
+impl HttpClient for ReqwestClient {
+    type Body = Pin<Box<dyn AsyncRead + Send>>;
+
+    async fn get(&self, url: &str) -> io::Result<HttpResponse<Self::Body>> {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(io::Error::other)?;
+        let header = |name: HeaderName| {
+            let value = response.headers().get(name)?;
+            value.to_str().ok().map(String::from)
+        };
+        let location = header(LOCATION);
+        let cache_control = header(CACHE_CONTROL);
+        let content_type = header(CONTENT_TYPE);
+        let status = response.status().as_u16();
+        let body = Box::pin(response.bytes_stream())
+            .map_err(io::Error::other)
+            .into_async_read();
+
+        Ok(HttpResponse {
+            status,
+            location,
+            cache_control,
+            content_type,
+            body: Box::pin(body),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::RobotsFetcher;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serve canned HTTP responses by path on a local port.
+    fn serve(responses: Vec<(&'static str, &'static str)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let origin = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = String::new();
+                BufReader::new(&stream).read_line(&mut request).unwrap();
+                let path = request.split_whitespace().nth(1).unwrap_or("");
+                let response = responses.iter().find(|(p, _)| *p == path).map_or(
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n",
+                    |(_, r)| r,
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        origin
+    }
+
+    #[test]
+    fn test_reqwest_client() {
+        let origin = serve(vec![
+            (
+                "/robots.txt",
+                "HTTP/1.1 301 Moved\r\nLocation: /real.txt\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            ),
+            (
+                "/real.txt",
+                "HTTP/1.1 200 OK\r\nCache-Control: max-age=60\r\nContent-Length: 32\r\nConnection: close\r\n\r\nUser-agent: *\nDisallow: /private",
+            ),
+        ]);
+
+        tokio_test::block_on(async {
+            let fetcher = RobotsFetcher::new(ReqwestClient::new("foobot").unwrap());
+            let response = fetcher.fetch(&origin).await.unwrap();
+            assert_eq!(200, response.status);
+            assert_eq!(Some("max-age=60"), response.cache_control.as_deref());
+            assert_eq!(b"User-agent: *\nDisallow: /private", &response.body[..]);
+        });
+    }
+}
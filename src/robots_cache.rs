@@ -0,0 +1,550 @@
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::cache::CompileCache;
+use crate::error::Error;
+use crate::nfa::Cylon;
+use crate::parse::Compiler;
+
+/// RFC 9309 says a cached robots.txt file should not be used for more than
+/// 24 hours, unless it is unreachable.
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long to wait before fetching an unreachable robots.txt file again.
+const RETRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_CAPACITY: usize = 10_000;
+
+/// A response to a request for a robots.txt file.
+#[derive(Debug, Clone, Default)]
+pub struct FetchResponse {
+    /// The HTTP status code, after following redirects.
+    pub status: u16,
+    /// The response body.
+    pub body: Vec<u8>,
+    /// The value of the `Cache-Control` header, if any.
+    pub cache_control: Option<String>,
+    /// The value of the `Content-Type` header, if any.
+    pub content_type: Option<String>,
+}
+
+impl FetchResponse {
+    /// Whether the file was served and should be parsed.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Whether the server failed to serve the file, with a 5xx or 429
+    /// status. RFC 9309 says everything should be disallowed until it can
+    /// be fetched. Any other status that isn't a success means the file is
+    /// unavailable and nothing is disallowed.
+    pub fn is_unreachable(&self) -> bool {
+        self.status == 429 || self.status >= 500
+    }
+}
+
+/// Fetches robots.txt files for a RobotsCache.
+pub trait Fetcher {
+    /// Fetch `/robots.txt` from the origin, e.g. `https://example.com`.
+    /// Return an error if the server could not be reached at all. I/O
+    /// errors convert into [`Error::Io`] with `?`.
+    fn fetch(&self, origin: &str) -> impl Future<Output = Result<FetchResponse, Error>> + Send;
+}
+
+/// A cached robots.txt file, as exported by [`RobotsCache::snapshot`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedRobots {
+    pub origin: String,
+    pub cylon: Cylon,
+    /// When the file must be fetched again.
+    pub expires_at: SystemTime,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    cylon: Arc<Cylon>,
+    expires_at: SystemTime,
+}
+
+/// A map from origin to compiled robots.txt file following the caching
+/// rules of RFC 9309:
+///
+/// * A successful response is cached for 24 hours, or less if its
+///   `Cache-Control` header says so.
+/// * A 4xx response means there are no restrictions.
+/// * A file that is larger than the compiler's size limit, compiles to too
+///   many states or is not UTF-8, such as a UTF-16 file, disallows
+///   everything.
+/// * If the server can't be reached, or responds with a 5xx or 429 status,
+///   the previous file keeps being used. If there is none, everything is
+///   disallowed. Either way the file is fetched again after an hour.
+///
+/// The least recently used origins are evicted once the cache is full.
+/// Identical files from different origins share one compiled Cylon.
+pub struct RobotsCache<F> {
+    compiler: Compiler,
+    fetcher: F,
+    capacity: usize,
+    max_age: Duration,
+    compiled: CompileCache,
+    allow_all: Arc<Cylon>,
+    disallow_all: Arc<Cylon>,
+    entries: Mutex<Lru>,
+}
+
+impl<F: Fetcher> RobotsCache<F> {
+    /// A cache that compiles files with the compiler. Fails with
+    /// [`Error::InvalidConfiguration`] if the compiler can't compile any
+    /// file, rather than failing on every fetch.
+    pub fn new(compiler: Compiler, fetcher: F) -> Result<Self, Error> {
+        compiler.validate()?;
+        Ok(Self {
+            compiler,
+            fetcher,
+            capacity: DEFAULT_CAPACITY,
+            max_age: DEFAULT_MAX_AGE,
+            compiled: CompileCache::new(),
+            allow_all: Arc::new(Cylon::allow_all()),
+            disallow_all: Arc::new(Cylon::disallow_all()),
+            entries: Mutex::new(Lru::default()),
+        })
+    }
+
+    /// Keep at most this many origins, evicting the least recently used.
+    /// Defaults to 10,000.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Use fetched files for at most this long. Defaults to 24 hours.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// The rules for the origin, fetching them if they are not cached or
+    /// have expired.
+    pub async fn get(&self, origin: &str) -> Arc<Cylon> {
+        self.get_at(origin, SystemTime::now()).await
+    }
+
+    /// Like [`RobotsCache::get`], at the given time.
+    pub async fn get_at(&self, origin: &str, now: SystemTime) -> Arc<Cylon> {
+        let stale = match self.lock().get(origin) {
+            Some(entry) if now < entry.expires_at => return entry.cylon.clone(),
+            Some(entry) => Some(entry.cylon.clone()),
+            None => None,
+        };
+
+        let (cylon, ttl) = match self.fetcher.fetch(origin).await {
+            Ok(response) if response.is_success() => {
+                let ttl = match response.cache_control.as_deref().and_then(max_age) {
+                    Some(max_age) => max_age.min(self.max_age),
+                    None => self.max_age,
+                };
+                let content_type = response.content_type.as_deref();
+                (self.compile(&response.body, content_type).await, ttl)
+            }
+            Ok(response) if !response.is_unreachable() => (self.allow_all.clone(), self.max_age),
+            _ => (
+                stale.unwrap_or_else(|| self.disallow_all.clone()),
+                RETRY_INTERVAL.min(self.max_age),
+            ),
+        };
+
+        let entry = Entry {
+            cylon: cylon.clone(),
+            expires_at: now + ttl,
+        };
+        self.lock().insert(origin.into(), entry, self.capacity);
+        cylon
+    }
+
+    /// Forget the rules for the origin.
+    pub fn remove(&self, origin: &str) {
+        self.lock().remove(origin);
+    }
+
+    /// The number of cached origins.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Export every cached file, e.g. to persist the cache across restarts.
+    pub fn snapshot(&self) -> Vec<CachedRobots> {
+        let lru = self.lock();
+        lru.order
+            .values()
+            .filter_map(|origin| {
+                let (_, entry) = lru.entries.get(origin)?;
+                Some(CachedRobots {
+                    origin: origin.clone(),
+                    cylon: Cylon::clone(&entry.cylon),
+                    expires_at: entry.expires_at,
+                })
+            })
+            .collect()
+    }
+
+    /// Import files exported by [`RobotsCache::snapshot`].
+    pub fn restore(&self, cached: Vec<CachedRobots>) {
+        let mut lru = self.lock();
+        for cached in cached {
+            let entry = Entry {
+                cylon: Arc::new(cached.cylon),
+                expires_at: cached.expires_at,
+            };
+            lru.insert(cached.origin, entry, self.capacity);
+        }
+    }
+
+    async fn compile(&self, body: &[u8], content_type: Option<&str>) -> Arc<Cylon> {
+        // The compiler was validated in `new`, so the file itself is
+        // unparseable: too large, too complex or not UTF-8. Disallow
+        // everything rather than crawl what it may disallow.
+        self.compiled
+            .compile_with_content_type(&self.compiler, body, content_type)
+            .await
+            .unwrap_or_else(|_| self.disallow_all.clone())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Lru> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Parse the lifetime allowed by a `Cache-Control` header value.
+fn max_age(cache_control: &str) -> Option<Duration> {
+    let mut max_age = None;
+    for directive in cache_control.split(',') {
+        let directive = directive.trim().to_ascii_lowercase();
+        match directive.split_once('=') {
+            _ if directive == "no-store" || directive == "no-cache" => {
+                return Some(Duration::from_secs(0))
+            }
+            Some(("max-age", value)) => {
+                max_age = value
+                    .trim_matches('"')
+                    .parse()
+                    .ok()
+                    .map(Duration::from_secs)
+            }
+            _ => {}
+        }
+    }
+    max_age
+}
+
+/// Cache entries ordered by when they were last used.
+#[derive(Debug, Default)]
+struct Lru {
+    entries: HashMap<String, (u64, Entry)>,
+    order: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl Lru {
+    fn get(&mut self, origin: &str) -> Option<&Entry> {
+        let (tick, entry) = self.entries.get_mut(origin)?;
+        self.tick += 1;
+        let origin = self.order.remove(tick)?;
+        self.order.insert(self.tick, origin);
+        *tick = self.tick;
+        Some(entry)
+    }
+
+    fn insert(&mut self, origin: String, entry: Entry, capacity: usize) {
+        self.remove(&origin);
+        self.tick += 1;
+        self.order.insert(self.tick, origin.clone());
+        self.entries.insert(origin, (self.tick, entry));
+
+        while self.entries.len() > capacity {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+
+    fn remove(&mut self, origin: &str) {
+        if let Some((tick, _)) = self.entries.remove(origin) {
+            self.order.remove(&tick);
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::io;
+
+    /// Serves queued responses per origin and counts requests.
+    #[derive(Default)]
+    struct FakeFetcher {
+        responses: Mutex<HashMap<String, VecDeque<Result<FetchResponse, Error>>>>,
+        requests: Mutex<usize>,
+    }
+
+    impl FakeFetcher {
+        fn respond(&self, origin: &str, status: u16, body: &str, cache_control: Option<&str>) {
+            let response = FetchResponse {
+                status,
+                body: body.into(),
+                cache_control: cache_control.map(String::from),
+                content_type: None,
+            };
+            self.queue(origin, Ok(response));
+        }
+
+        fn fail(&self, origin: &str) {
+            let error = io::Error::new(io::ErrorKind::ConnectionRefused, "refused");
+            self.queue(origin, Err(error.into()));
+        }
+
+        fn queue(&self, origin: &str, response: Result<FetchResponse, Error>) {
+            let mut responses = self.responses.lock().unwrap();
+            responses
+                .entry(origin.into())
+                .or_default()
+                .push_back(response);
+        }
+
+        fn requests(&self) -> usize {
+            *self.requests.lock().unwrap()
+        }
+    }
+
+    impl Fetcher for &FakeFetcher {
+        async fn fetch(&self, origin: &str) -> Result<FetchResponse, Error> {
+            *self.requests.lock().unwrap() += 1;
+            let mut responses = self.responses.lock().unwrap();
+            let queue = responses.get_mut(origin);
+            queue.and_then(|q| q.pop_front()).unwrap_or_else(|| {
+                Err(io::Error::new(io::ErrorKind::NotFound, "no response").into())
+            })
+        }
+    }
+
+    const ROBOTS: &str = "User-agent: *\nDisallow: /private";
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    #[test]
+    fn test_max_age() {
+        tokio_test::block_on(async {
+            let fetcher = FakeFetcher::default();
+            let cache = RobotsCache::new(Compiler::new("bot"), &fetcher).unwrap();
+            let now = SystemTime::UNIX_EPOCH;
+            fetcher.respond("https://a.com", 200, ROBOTS, None);
+            fetcher.respond("https://a.com", 200, "", None);
+
+            let cylon = cache.get_at("https://a.com", now).await;
+            assert_eq!(false, cylon.allow("/private"));
+            let cylon = cache.get_at("https://a.com", now + 23 * HOUR).await;
+            assert_eq!(false, cylon.allow("/private"));
+            assert_eq!(1, fetcher.requests());
+
+            let cylon = cache.get_at("https://a.com", now + 24 * HOUR).await;
+            assert_eq!(true, cylon.allow("/private"));
+            assert_eq!(2, fetcher.requests());
+        });
+    }
+
+    #[test]
+    fn test_cache_control() {
+        let test_cases = vec![
+            (Some("max-age=60"), Duration::from_secs(60)),
+            (Some("public, max-age=\"120\""), Duration::from_secs(120)),
+            (Some("max-age=604800"), 24 * HOUR),
+            (Some("no-store"), Duration::from_secs(0)),
+            (Some("max-age=60, No-Cache"), Duration::from_secs(0)),
+            (Some("max-age=soon"), 24 * HOUR),
+            (None, 24 * HOUR),
+        ];
+
+        for (cache_control, ttl) in test_cases {
+            tokio_test::block_on(async {
+                let fetcher = FakeFetcher::default();
+                let cache = RobotsCache::new(Compiler::new("bot"), &fetcher).unwrap();
+                let now = SystemTime::UNIX_EPOCH;
+                fetcher.respond("https://a.com", 200, ROBOTS, cache_control);
+                fetcher.respond("https://a.com", 200, ROBOTS, cache_control);
+
+                cache.get_at("https://a.com", now).await;
+                if ttl > Duration::from_secs(0) {
+                    cache
+                        .get_at("https://a.com", now + ttl - Duration::from_secs(1))
+                        .await;
+                    assert_eq!(1, fetcher.requests(), "{:?}", cache_control);
+                }
+                cache.get_at("https://a.com", now + ttl).await;
+                assert_eq!(2, fetcher.requests(), "{:?}", cache_control);
+            });
+        }
+    }
+
+    #[test]
+    fn test_status() {
+        tokio_test::block_on(async {
+            let fetcher = FakeFetcher::default();
+            let cache = RobotsCache::new(Compiler::new("bot"), &fetcher).unwrap();
+            let now = SystemTime::UNIX_EPOCH;
+            fetcher.respond("https://gone.com", 404, "Not found", None);
+            fetcher.respond("https://down.com", 503, "", None);
+            fetcher.respond("https://busy.com", 429, "", None);
+            fetcher.fail("https://offline.com");
+
+            assert_eq!(true, cache.get_at("https://gone.com", now).await.allow("/"));
+            assert_eq!(
+                false,
+                cache.get_at("https://down.com", now).await.allow("/")
+            );
+            assert_eq!(
+                false,
+                cache.get_at("https://busy.com", now).await.allow("/a")
+            );
+            assert_eq!(
+                false,
+                cache.get_at("https://offline.com", now).await.allow("")
+            );
+
+            // Unreachable files are retried sooner than the max age.
+            fetcher.respond("https://down.com", 200, ROBOTS, None);
+            let cylon = cache.get_at("https://down.com", now + HOUR).await;
+            assert_eq!(true, cylon.allow("/"));
+            assert_eq!(false, cylon.allow("/private"));
+        });
+    }
+
+    #[test]
+    fn test_unparseable() {
+        let fetcher = FakeFetcher::default();
+        assert!(matches!(
+            RobotsCache::new(Compiler::new(""), &fetcher),
+            Err(Error::InvalidConfiguration(_))
+        ));
+
+        tokio_test::block_on(async {
+            let compiler = Compiler::new("bot").with_max_size(64);
+            let cache = RobotsCache::new(compiler, &fetcher).unwrap();
+            let now = SystemTime::UNIX_EPOCH;
+            let large = format!("{}\n{}", ROBOTS, "#".repeat(64));
+            fetcher.respond("https://large.com", 200, &large, None);
+            let respond = |origin, body: &[u8]| {
+                let response = FetchResponse {
+                    status: 200,
+                    body: body.into(),
+                    ..FetchResponse::default()
+                };
+                fetcher.queue(origin, Ok(response));
+            };
+            respond("https://utf16.com", b"\xff\xfeU\0s\0e\0r\0");
+            respond(
+                "https://invalid.com",
+                b"User-agent: *\nDisallow: /\xff\nDisallow: /private",
+            );
+
+            // Files that can't be parsed disallow everything.
+            let cylon = cache.get_at("https://large.com", now).await;
+            assert_eq!(false, cylon.allow("/"));
+            let cylon = cache.get_at("https://utf16.com", now).await;
+            assert_eq!(false, cylon.allow("/"));
+
+            // An invalid byte doesn't stop the rest of the file from being
+            // parsed.
+            let cylon = cache.get_at("https://invalid.com", now).await;
+            assert_eq!(true, cylon.allow("/"));
+            assert_eq!(false, cylon.allow("/%FF"));
+            assert_eq!(false, cylon.allow("/private"));
+        });
+    }
+
+    #[test]
+    fn test_stale_on_error() {
+        tokio_test::block_on(async {
+            let fetcher = FakeFetcher::default();
+            let cache = RobotsCache::new(Compiler::new("bot"), &fetcher).unwrap();
+            let now = SystemTime::UNIX_EPOCH;
+            fetcher.respond("https://a.com", 200, ROBOTS, None);
+            fetcher.respond("https://a.com", 500, "", None);
+            fetcher.fail("https://a.com");
+            fetcher.respond("https://a.com", 200, "", None);
+
+            cache.get_at("https://a.com", now).await;
+            let cylon = cache.get_at("https://a.com", now + 24 * HOUR).await;
+            assert_eq!(true, cylon.allow("/"));
+            assert_eq!(false, cylon.allow("/private"));
+            let cylon = cache.get_at("https://a.com", now + 25 * HOUR).await;
+            assert_eq!(false, cylon.allow("/private"));
+            let cylon = cache.get_at("https://a.com", now + 26 * HOUR).await;
+            assert_eq!(true, cylon.allow("/private"));
+            assert_eq!(4, fetcher.requests());
+        });
+    }
+
+    #[test]
+    fn test_lru() {
+        tokio_test::block_on(async {
+            let fetcher = FakeFetcher::default();
+            let cache = RobotsCache::new(Compiler::new("bot"), &fetcher)
+                .unwrap()
+                .with_capacity(2);
+            let now = SystemTime::UNIX_EPOCH;
+            for origin in &[
+                "https://a.com",
+                "https://b.com",
+                "https://c.com",
+                "https://a.com",
+            ] {
+                fetcher.respond(origin, 200, ROBOTS, None);
+            }
+
+            cache.get_at("https://a.com", now).await;
+            cache.get_at("https://b.com", now).await;
+            cache.get_at("https://a.com", now).await;
+            cache.get_at("https://c.com", now).await;
+            assert_eq!(2, cache.len());
+            assert_eq!(3, fetcher.requests());
+
+            // b was evicted, a was used more recently
+            cache.get_at("https://a.com", now).await;
+            assert_eq!(3, fetcher.requests());
+            cache.get_at("https://b.com", now).await;
+            assert_eq!(4, fetcher.requests());
+        });
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        tokio_test::block_on(async {
+            let fetcher = FakeFetcher::default();
+            let cache = RobotsCache::new(Compiler::new("bot"), &fetcher).unwrap();
+            let now = SystemTime::UNIX_EPOCH;
+            fetcher.respond("https://a.com", 200, ROBOTS, None);
+            cache.get_at("https://a.com", now).await;
+
+            let snapshot = cache.snapshot();
+            assert_eq!(1, snapshot.len());
+            assert_eq!("https://a.com", snapshot[0].origin);
+            assert_eq!(now + 24 * HOUR, snapshot[0].expires_at);
+
+            let restored = RobotsCache::new(Compiler::new("bot"), &fetcher).unwrap();
+            restored.restore(snapshot);
+            let cylon = restored.get_at("https://a.com", now + HOUR).await;
+            assert_eq!(false, cylon.allow("/private"));
+            assert_eq!(1, fetcher.requests());
+
+            restored.remove("https://a.com");
+            assert!(restored.is_empty());
+        });
+    }
+}
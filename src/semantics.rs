@@ -0,0 +1,46 @@
+use serde_derive::{Deserialize, Serialize};
+
+/// How a Compiler decides which rule applies to a path. Crawlers have
+/// interpreted robots.txt files differently over the years, so this can
+/// be used to reproduce how a particular crawler will read a file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MatchSemantics {
+    /// RFC 9309, as implemented by Google. The longest matching pattern
+    /// wins and Allow wins a tie. `*` matches any sequence of characters
+    /// and a trailing `$` matches the end of the path.
+    #[default]
+    Rfc9309,
+    /// The original 1994 convention, which many older crawlers follow. The
+    /// first matching rule in the group wins, and patterns are plain
+    /// prefixes in which `*` and `$` only match themselves.
+    Original,
+    /// Bing. Like RFC 9309, except that Disallow wins a tie between
+    /// patterns of the same length.
+    Bing,
+}
+
+impl MatchSemantics {
+    /// Whether `*` and `$` have a special meaning in patterns.
+    pub(crate) fn special_characters(self) -> bool {
+        self != MatchSemantics::Original
+    }
+
+    /// The weight of the rule at `index` of a group's `count` rules. When
+    /// several rules match a path, the heaviest wins, and Allow wins a tie.
+    pub(crate) fn weight(self, pattern: &[u8], allow: bool, index: usize, count: usize) -> usize {
+        match self {
+            MatchSemantics::Rfc9309 => length(pattern),
+            // Doubling the length leaves room for Disallow to outweigh an
+            // Allow of the same length.
+            MatchSemantics::Bing => 2 * length(pattern) + usize::from(!allow),
+            MatchSemantics::Original => count - index,
+        }
+    }
+}
+
+/// The length of a pattern for its precedence. Like the NFA, a trailing
+/// run of wildcards counts as one character.
+pub(crate) fn length(pattern: &[u8]) -> usize {
+    let wildcards = pattern.iter().rev().take_while(|b| **b == b'*').count();
+    pattern.len() - wildcards.saturating_sub(1)
+}
@@ -0,0 +1,255 @@
+use serde_derive::{Deserialize, Serialize};
+
+/// How many bytes at the start of a file are looked at to classify it.
+pub(crate) const SNIFF_LEN: usize = 1024;
+
+/// Tags that only start an HTML document, never a robots.txt file.
+const HTML_MARKERS: &[&[u8]] = &[
+    b"<!doctype html",
+    b"<html",
+    b"<head",
+    b"<body",
+    b"<meta",
+    b"<title",
+    b"<script",
+    b"<!--",
+];
+
+/// Signatures of common binary formats.
+const BINARY_MAGIC: &[&[u8]] = &[
+    b"\x1f\x8b",
+    b"PK\x03\x04",
+    b"%PDF-",
+    b"\x89PNG",
+    b"GIF8",
+    b"\xff\xd8\xff",
+];
+
+/// Byte order marks of text encodings that can't be read as UTF-8.
+const UNSUPPORTED_BOMS: &[(&[u8], &str)] = &[(b"\xff\xfe", "UTF-16LE"), (b"\xfe\xff", "UTF-16BE")];
+
+/// The name of the text encoding if the file starts with the byte order
+/// mark of one that can't be read as UTF-8.
+pub(crate) fn unsupported_encoding(prefix: &[u8]) -> Option<&'static str> {
+    UNSUPPORTED_BOMS
+        .iter()
+        .find(|(bom, _)| prefix.starts_with(bom))
+        .map(|(_, encoding)| *encoding)
+}
+
+/// What a file served as robots.txt looks like. Misconfigured servers
+/// often answer a request for `/robots.txt` with an error page, the shell
+/// of a single-page app or some other file, with a success status.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ContentKind {
+    /// Text that may be a robots.txt file.
+    #[default]
+    RobotsTxt,
+    /// An HTML page.
+    Html,
+    /// Binary data, such as an image or a compressed file.
+    Binary,
+    /// Nothing but whitespace.
+    Empty,
+}
+
+impl ContentKind {
+    /// Classify a file from its first bytes and, if known, the media type
+    /// of its `Content-Type` header. The header only decides the kind for
+    /// images, audio, video and fonts: servers that generate robots.txt
+    /// files often label them `text/html`, so HTML is recognized by its
+    /// markup.
+    ///
+    /// ```
+    /// use cylon::ContentKind;
+    ///
+    /// let html = b"<!DOCTYPE html><title>Not found</title>";
+    /// assert_eq!(ContentKind::Html, ContentKind::sniff(html, None));
+    /// let robots = b"User-agent: *\nDisallow: /";
+    /// assert_eq!(ContentKind::RobotsTxt, ContentKind::sniff(robots, Some("text/html")));
+    /// ```
+    pub fn sniff(file: &[u8], content_type: Option<&str>) -> Self {
+        Self::sniff_prefix(&file[..file.len().min(SNIFF_LEN)], content_type, true)
+    }
+
+    /// Classify a file from its first bytes. Unless `complete`, more of the
+    /// file follows, so it isn't empty even if they are all whitespace.
+    pub(crate) fn sniff_prefix(prefix: &[u8], content_type: Option<&str>, complete: bool) -> Self {
+        let media_type = content_type
+            .and_then(|t| t.split(';').next())
+            .map(|t| t.trim().to_ascii_lowercase());
+        let binary_type = media_type.as_deref().is_some_and(|t| {
+            let binary = ["image/", "audio/", "video/", "font/"];
+            binary.iter().any(|prefix| t.starts_with(prefix))
+        });
+
+        let text = prefix.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(prefix);
+        let start = text.iter().position(|b| !b.is_ascii_whitespace());
+        let text = match start {
+            Some(start) => &text[start..],
+            None if complete => return ContentKind::Empty,
+            None => return ContentKind::RobotsTxt,
+        };
+
+        if binary_type || is_binary(text) {
+            ContentKind::Binary
+        } else if is_html(text) {
+            ContentKind::Html
+        } else {
+            ContentKind::RobotsTxt
+        }
+    }
+
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            ContentKind::RobotsTxt => 0,
+            ContentKind::Html => 1,
+            ContentKind::Binary => 2,
+            ContentKind::Empty => 3,
+        }
+    }
+
+    pub(crate) fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(ContentKind::RobotsTxt),
+            1 => Some(ContentKind::Html),
+            2 => Some(ContentKind::Binary),
+            3 => Some(ContentKind::Empty),
+            _ => None,
+        }
+    }
+}
+
+/// What a Compiler does with a file of a given [`ContentKind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ContentPolicy {
+    /// Parse the file as robots.txt anyway.
+    Parse,
+    /// Allow every path, as if there were no robots.txt file.
+    AllowAll,
+    /// Disallow every path, as if the server couldn't be reached.
+    DisallowAll,
+}
+
+/// The policy for each kind of content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct ContentPolicies {
+    robots_txt: ContentPolicy,
+    html: ContentPolicy,
+    binary: ContentPolicy,
+    empty: ContentPolicy,
+}
+
+impl Default for ContentPolicies {
+    fn default() -> Self {
+        Self {
+            robots_txt: ContentPolicy::Parse,
+            html: ContentPolicy::Parse,
+            binary: ContentPolicy::Parse,
+            empty: ContentPolicy::Parse,
+        }
+    }
+}
+
+impl ContentPolicies {
+    pub(crate) fn get(&self, kind: ContentKind) -> ContentPolicy {
+        match kind {
+            ContentKind::RobotsTxt => self.robots_txt,
+            ContentKind::Html => self.html,
+            ContentKind::Binary => self.binary,
+            ContentKind::Empty => self.empty,
+        }
+    }
+
+    pub(crate) fn set(&mut self, kind: ContentKind, policy: ContentPolicy) {
+        let slot = match kind {
+            ContentKind::RobotsTxt => &mut self.robots_txt,
+            ContentKind::Html => &mut self.html,
+            ContentKind::Binary => &mut self.binary,
+            ContentKind::Empty => &mut self.empty,
+        };
+        *slot = policy;
+    }
+}
+
+/// Whether the text starts with markup that only HTML would.
+fn is_html(text: &[u8]) -> bool {
+    text.first() == Some(&b'<')
+        && HTML_MARKERS.iter().any(|marker| {
+            text.windows(marker.len())
+                .any(|w| w.eq_ignore_ascii_case(marker))
+        })
+}
+
+/// Whether the bytes look like a binary format rather than text: they
+/// start with a known signature, contain a NUL, or are largely control
+/// characters and invalid UTF-8.
+fn is_binary(text: &[u8]) -> bool {
+    if BINARY_MAGIC.iter().any(|magic| text.starts_with(magic)) || text.contains(&0) {
+        return true;
+    }
+    let decoded = String::from_utf8_lossy(text);
+    let suspicious = decoded
+        .chars()
+        .filter(|c| *c == '\u{FFFD}' || (c.is_control() && !c.is_ascii_whitespace()))
+        .count();
+    suspicious * 10 > decoded.chars().count() * 3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff() {
+        let test_cases: Vec<(&[u8], Option<&str>, ContentKind)> = vec![
+            (b"User-agent: *\nDisallow: /", None, ContentKind::RobotsTxt),
+            (b"\xEF\xBB\xBFUser-agent: *", None, ContentKind::RobotsTxt),
+            (b"# <html>\nDisallow: /", None, ContentKind::RobotsTxt),
+            (
+                b"Disallow: /",
+                Some("text/html; charset=utf-8"),
+                ContentKind::RobotsTxt,
+            ),
+            (b"Disallow: /\xFF\xFE", None, ContentKind::RobotsTxt),
+            (b"<!DOCTYPE html>\n<html>", None, ContentKind::Html),
+            (b"\n  <HTML><BODY>Not found", None, ContentKind::Html),
+            (b"<!-- app shell -->\n<div id=app>", None, ContentKind::Html),
+            (
+                b"<?xml version=\"1.0\"?><urlset>",
+                None,
+                ContentKind::RobotsTxt,
+            ),
+            (b"\x1f\x8b\x08\x00", None, ContentKind::Binary),
+            (b"\x89PNG\r\n\x1a\n", None, ContentKind::Binary),
+            (b"User-agent: *\x00", None, ContentKind::Binary),
+            (b"\xFF\xFE\x01\x02\x03", None, ContentKind::Binary),
+            (b"Disallow: /", Some("image/png"), ContentKind::Binary),
+            (b"", None, ContentKind::Empty),
+            (b" \r\n\t", Some("text/html"), ContentKind::Empty),
+            (b"\xEF\xBB\xBF\n", None, ContentKind::Empty),
+        ];
+
+        for (file, content_type, kind) in test_cases {
+            assert_eq!(
+                kind,
+                ContentKind::sniff(file, content_type),
+                "{:?}",
+                String::from_utf8_lossy(file)
+            );
+        }
+        assert_eq!(
+            ContentKind::RobotsTxt,
+            ContentKind::sniff_prefix(b"\n\n", None, false)
+        );
+    }
+
+    #[test]
+    fn test_policies() {
+        let mut policies = ContentPolicies::default();
+        assert_eq!(ContentPolicy::Parse, policies.get(ContentKind::Html));
+        policies.set(ContentKind::Html, ContentPolicy::DisallowAll);
+        assert_eq!(ContentPolicy::DisallowAll, policies.get(ContentKind::Html));
+        assert_eq!(ContentPolicy::Parse, policies.get(ContentKind::Binary));
+    }
+}
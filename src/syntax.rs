@@ -0,0 +1,445 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::directive;
+use crate::model::{Group, GroupRule, RobotsTxt};
+use crate::parse::{self, Key};
+
+const BOM: char = '\u{feff}';
+
+/// How a line of a robots.txt file was classified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    /// An empty or whitespace-only line.
+    Blank,
+    /// A line that only contains a comment.
+    Comment,
+    UserAgent,
+    /// A directive that belongs to a group, such as `Allow` or `Crawl-delay`.
+    Rule,
+    /// A directive that applies to the whole file, such as `Sitemap`.
+    Global,
+    /// A line that is not a directive and is ignored by parsers.
+    Invalid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Line {
+    text: String,
+    ending: &'static str,
+    kind: LineKind,
+}
+
+impl Line {
+    fn new(text: String, ending: &'static str) -> Self {
+        let kind = classify(text.trim_start_matches(BOM));
+        Self { text, ending, kind }
+    }
+
+    /// The directive name and value, as slices of the line's text.
+    fn key_value(&self) -> Option<(&str, &str)> {
+        parse::split_line(self.text.trim_start_matches(BOM))
+    }
+
+    fn indent(&self) -> &str {
+        let text = self.text.trim_start_matches(BOM);
+        &text[..text.len() - text.trim_start().len()]
+    }
+}
+
+#[derive(Debug, Clone)]
+struct GroupSpan {
+    agents: Vec<usize>,
+    directives: Vec<usize>,
+}
+
+/// A lossless syntax tree of a robots.txt file. Every line, comment, blank
+/// line and spelling is kept, so the text it writes out is identical to the
+/// input except where it was edited. This is meant for tools that let
+/// people edit their own robots.txt files.
+///
+/// ```
+/// use cylon::{GroupRule, SyntaxTree};
+///
+/// let mut tree = SyntaxTree::parse("user-agent: *   # everyone\ndisallow: /tmp\n");
+/// let group = tree.find_group("*").unwrap();
+/// tree.add_rule(group, GroupRule::Allow("/tmp/public".into()));
+///
+/// assert_eq!(
+///     "user-agent: *   # everyone\ndisallow: /tmp\nAllow: /tmp/public\n",
+///     tree.to_string()
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyntaxTree {
+    lines: Vec<Line>,
+}
+
+impl SyntaxTree {
+    /// Parse a robots.txt file, keeping `\n`, `\r\n` and `\r` line endings.
+    pub fn parse(input: &str) -> Self {
+        let lines = parse::lines(input)
+            .map(|(text, ending)| Line::new(text.into(), ending))
+            .collect();
+        Self { lines }
+    }
+
+    /// The kind of every line, in order.
+    pub fn line_kinds(&self) -> Vec<LineKind> {
+        self.lines.iter().map(|l| l.kind).collect()
+    }
+
+    /// The number of groups in the file.
+    pub fn group_count(&self) -> usize {
+        self.groups().len()
+    }
+
+    /// The user agents of a group, as written.
+    pub fn user_agents(&self, group: usize) -> Vec<&str> {
+        match self.groups().get(group) {
+            Some(span) => span.agents.iter().map(|&i| self.value(i)).collect(),
+            None => vec![],
+        }
+    }
+
+    /// The index of the first group that lists the user agent, ignoring case.
+    pub fn find_group(&self, user_agent: &str) -> Option<usize> {
+        self.groups().iter().position(|span| {
+            span.agents
+                .iter()
+                .any(|&i| self.value(i).eq_ignore_ascii_case(user_agent))
+        })
+    }
+
+    /// Append a rule after the last directive of a group, using the same
+    /// indentation. Returns false if the group does not exist.
+    pub fn add_rule(&mut self, group: usize, rule: GroupRule) -> bool {
+        let span = match self.groups().into_iter().nth(group) {
+            Some(span) => span,
+            None => return false,
+        };
+        let last = *span
+            .directives
+            .last()
+            .or_else(|| span.agents.last())
+            .unwrap();
+        let indent = self.lines[last].indent().to_string();
+        self.ensure_ending(last);
+        let ending = self.line_ending();
+        let line = Line::new([indent, rule_text(&rule)].concat(), ending);
+        self.lines.insert(last + 1, line);
+        true
+    }
+
+    /// Remove the first rule of a group equal to the given one. Returns
+    /// false if there is no such rule.
+    pub fn remove_rule(&mut self, group: usize, rule: &GroupRule) -> bool {
+        let span = match self.groups().into_iter().nth(group) {
+            Some(span) => span,
+            None => return false,
+        };
+        let found = span
+            .directives
+            .into_iter()
+            .find(|&i| self.rule_matches(i, rule));
+        match found {
+            Some(i) => {
+                self.lines.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Append a group to the end of the file, separated by a blank line.
+    pub fn add_group(&mut self, group: &Group) {
+        let ending = self.line_ending();
+        if let Some(last) = self.lines.len().checked_sub(1) {
+            self.ensure_ending(last);
+            if self.lines[last].kind != LineKind::Blank {
+                self.lines.push(Line::new(String::new(), ending));
+            }
+        }
+
+        let text = RobotsTxt::new().group(group.clone()).to_string();
+        for text in text.lines() {
+            self.lines.push(Line::new(text.into(), ending));
+        }
+    }
+
+    /// Change every `User-agent` line naming `from` (ignoring case) to name
+    /// `to` instead, keeping the rest of the line intact. Returns the number
+    /// of lines changed.
+    pub fn rename_agent(&mut self, from: &str, to: &str) -> usize {
+        let mut changed = 0;
+        for line in self.lines.iter_mut() {
+            if line.kind != LineKind::UserAgent {
+                continue;
+            }
+            let range = match line.key_value() {
+                Some((_, value)) if value.eq_ignore_ascii_case(from) => span_of(&line.text, value),
+                _ => continue,
+            };
+            line.text.replace_range(range, to);
+            changed += 1;
+        }
+        changed
+    }
+
+    /// Convert the tree into the parsed model, dropping its layout.
+    pub fn to_model(&self) -> RobotsTxt {
+        RobotsTxt::parse(&self.to_string())
+    }
+
+    /// Split the file into groups the same way the Compiler does.
+    fn groups(&self) -> Vec<GroupSpan> {
+        let mut groups: Vec<GroupSpan> = vec![];
+        let mut parsing_agents = false;
+        for (i, line) in self.lines.iter().enumerate() {
+            match line.kind {
+                LineKind::UserAgent => {
+                    match groups.last_mut() {
+                        Some(span) if parsing_agents => span.agents.push(i),
+                        _ => groups.push(GroupSpan {
+                            agents: vec![i],
+                            directives: vec![],
+                        }),
+                    }
+                    parsing_agents = true;
+                }
+                LineKind::Rule => {
+                    if groups.is_empty() {
+                        if self.is_unknown(i) {
+                            // Unknown lines before the first group are global.
+                            continue;
+                        }
+                        groups.push(GroupSpan {
+                            agents: vec![],
+                            directives: vec![],
+                        });
+                    }
+                    parsing_agents &= self.is_unknown(i);
+                    groups.last_mut().unwrap().directives.push(i);
+                }
+                _ => (),
+            }
+        }
+        groups
+    }
+
+    fn is_unknown(&self, line: usize) -> bool {
+        match self.lines[line].key_value() {
+            Some((key, _)) => Key::from_name(key) == Key::Unknown,
+            None => false,
+        }
+    }
+
+    fn value(&self, line: usize) -> &str {
+        self.lines[line].key_value().map_or("", |(_, value)| value)
+    }
+
+    fn rule_matches(&self, line: usize, rule: &GroupRule) -> bool {
+        let (key, value) = match self.lines[line].key_value() {
+            Some(key_value) => key_value,
+            None => return false,
+        };
+        match (GroupRule::from_key_value(key, value), rule) {
+            (GroupRule::Other(a), GroupRule::Other(b)) => {
+                a.key.eq_ignore_ascii_case(&b.key) && a.value == b.value
+            }
+            (a, b) => &a == b,
+        }
+    }
+
+    /// The line ending used by the file, defaulting to `\n`.
+    fn line_ending(&self) -> &'static str {
+        self.lines
+            .iter()
+            .map(|l| l.ending)
+            .find(|e| !e.is_empty())
+            .unwrap_or("\n")
+    }
+
+    fn ensure_ending(&mut self, line: usize) {
+        if self.lines[line].ending.is_empty() {
+            self.lines[line].ending = self.line_ending();
+        }
+    }
+}
+
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            f.write_str(&line.text)?;
+            f.write_str(line.ending)?;
+        }
+        Ok(())
+    }
+}
+
+/// Classify a line using the same rules the Compiler uses to parse it.
+fn classify(text: &str) -> LineKind {
+    match parse::split_line(text) {
+        Some((key, _)) => match Key::from_name(key) {
+            Key::UserAgent => LineKind::UserAgent,
+            kind if kind.is_global() => LineKind::Global,
+            Key::Unknown if !directive::is_directive_key(key) => LineKind::Invalid,
+            _ => LineKind::Rule,
+        },
+        None if text.trim().is_empty() => LineKind::Blank,
+        None if parse::strip_comments(text).trim().is_empty() => LineKind::Comment,
+        None => LineKind::Invalid,
+    }
+}
+
+fn rule_text(rule: &GroupRule) -> String {
+    let text = RobotsTxt::new()
+        .group(Group {
+            rules: vec![rule.clone()],
+            ..Group::default()
+        })
+        .to_string();
+    text.trim_end().into()
+}
+
+/// The byte range of a subslice within its parent string.
+fn span_of(parent: &str, child: &str) -> Range<usize> {
+    let start = child.as_ptr() as usize - parent.as_ptr() as usize;
+    start..start + child.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "\u{feff}# Our robots file\r\n\
+        \r\n\
+        user-agent: BadBot   # the bad one\r\n\
+        user-agent: EvilBot\r\n\
+        \tdisallow:/\r\n\
+        \r\n\
+        # Everybody else\r\n\
+        User-Agent: *\r\n\
+        Allow : /public\r\n\
+        Sitemap: https://example.com/sitemap.xml\r\n\
+        Disallow: /private\r\n\
+        nonsense line";
+
+    #[test]
+    fn test_lossless() {
+        let test_cases = vec![EXAMPLE, "", "\n\n", "a\rb\n", "User-agent: *\nDisallow: /"];
+        for i in test_cases {
+            assert_eq!(i, SyntaxTree::parse(i).to_string());
+        }
+    }
+
+    #[test]
+    fn test_bare_cr() {
+        // The Compiler also ends a line at a bare `\r`.
+        let input = "User-agent: a\rDisallow: /a\r\nUser-agent: b\rDisallow: /b";
+        let tree = SyntaxTree::parse(input);
+        assert_eq!(
+            vec![
+                LineKind::UserAgent,
+                LineKind::Rule,
+                LineKind::UserAgent,
+                LineKind::Rule,
+            ],
+            tree.line_kinds()
+        );
+        assert_eq!(2, tree.group_count());
+        assert_eq!(Some(1), tree.find_group("b"));
+        assert_eq!(input, tree.to_string());
+    }
+
+    #[test]
+    fn test_line_kinds() {
+        let tree = SyntaxTree::parse(EXAMPLE);
+        assert_eq!(
+            vec![
+                LineKind::Comment,
+                LineKind::Blank,
+                LineKind::UserAgent,
+                LineKind::UserAgent,
+                LineKind::Rule,
+                LineKind::Blank,
+                LineKind::Comment,
+                LineKind::UserAgent,
+                LineKind::Rule,
+                LineKind::Global,
+                LineKind::Rule,
+                LineKind::Invalid,
+            ],
+            tree.line_kinds()
+        );
+        assert_eq!(2, tree.group_count());
+        assert_eq!(vec!["BadBot", "EvilBot"], tree.user_agents(0));
+        assert_eq!(Some(1), tree.find_group("*"));
+        assert_eq!(Some(0), tree.find_group("evilbot"));
+        assert_eq!(None, tree.find_group("goodbot"));
+    }
+
+    #[test]
+    fn test_add_rule() {
+        let mut tree = SyntaxTree::parse(EXAMPLE);
+        assert!(tree.add_rule(0, GroupRule::Allow("/robots.txt".into())));
+        assert!(tree.add_rule(1, GroupRule::Disallow("/tmp".into())));
+        assert!(!tree.add_rule(2, GroupRule::Disallow("/tmp".into())));
+
+        let expected = EXAMPLE
+            .replace(
+                "\tdisallow:/\r\n",
+                "\tdisallow:/\r\n\tAllow: /robots.txt\r\n",
+            )
+            .replace(
+                "Disallow: /private\r\n",
+                "Disallow: /private\r\nDisallow: /tmp\r\n",
+            );
+        assert_eq!(expected, tree.to_string());
+    }
+
+    #[test]
+    fn test_remove_rule() {
+        let mut tree = SyntaxTree::parse(EXAMPLE);
+        assert!(tree.remove_rule(1, &GroupRule::Allow("/public".into())));
+        assert!(!tree.remove_rule(1, &GroupRule::Allow("/public".into())));
+        assert!(!tree.remove_rule(0, &GroupRule::Disallow("/private".into())));
+
+        let expected = EXAMPLE.replace("Allow : /public\r\n", "");
+        assert_eq!(expected, tree.to_string());
+    }
+
+    #[test]
+    fn test_add_group() {
+        let mut tree = SyntaxTree::parse(EXAMPLE);
+        tree.add_group(&Group::new(vec!["GoodBot"]).allow("/"));
+
+        let expected = [EXAMPLE, "\r\n\r\nUser-agent: GoodBot\r\nAllow: /\r\n"].concat();
+        assert_eq!(expected, tree.to_string());
+        assert_eq!(Some(2), tree.find_group("goodbot"));
+
+        let mut tree = SyntaxTree::parse("");
+        tree.add_group(&Group::new(vec!["*"]).disallow("/"));
+        assert_eq!("User-agent: *\nDisallow: /\n", tree.to_string());
+    }
+
+    #[test]
+    fn test_rename_agent() {
+        let mut tree = SyntaxTree::parse(EXAMPLE);
+        assert_eq!(1, tree.rename_agent("badbot", "WorseBot"));
+        assert_eq!(0, tree.rename_agent("badbot", "WorseBot"));
+
+        let expected = EXAMPLE.replace("BadBot   #", "WorseBot   #");
+        assert_eq!(expected, tree.to_string());
+    }
+
+    #[test]
+    fn test_to_model() {
+        let tree = SyntaxTree::parse(EXAMPLE);
+        assert_eq!(RobotsTxt::parse(EXAMPLE), tree.to_model());
+        assert_eq!(
+            Some(&"BadBot".to_string()),
+            tree.to_model().groups[0].user_agents.first()
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use std::borrow::Cow;
+
+/// Characters that end the authority of a URL and start its path, params
+/// or query.
+const PATH_START: &[char] = &['/', '?', ';'];
+
+/// Extract the part of a URL that robots.txt rules are matched against:
+/// its path, params and query, without the fragment. The URL may omit its
+/// scheme or host, and the result always starts with `/`. This follows
+/// Google's parser, including its handling of malformed URLs.
+pub(crate) fn path_params_query(url: &str) -> Cow<'_, str> {
+    // Two initial slashes are a scheme-relative URL's host marker.
+    let search_start = if url.starts_with("//") { 2 } else { 0 };
+    let early_path = url[search_start..].find(PATH_START);
+    let protocol_end = match url[search_start..].find("://") {
+        // A `://` after the path has started doesn't end a scheme.
+        Some(i) if early_path.is_none_or(|p| i < p) => search_start + i + 3,
+        _ => search_start,
+    };
+
+    let path_start = match url[protocol_end..].find(PATH_START) {
+        Some(i) => protocol_end + i,
+        None => return Cow::Borrowed("/"),
+    };
+    let hash = url[search_start..].find('#').map(|i| search_start + i);
+    if hash.is_some_and(|h| h < path_start) {
+        return Cow::Borrowed("/");
+    }
+    let path = &url[path_start..hash.unwrap_or(url.len())];
+    if path.starts_with('/') {
+        Cow::Borrowed(path)
+    } else {
+        Cow::Owned(format!("/{}", path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_params_query() {
+        let test_cases = vec![
+            ("", "/"),
+            ("http://www.example.com", "/"),
+            ("http://www.example.com/", "/"),
+            ("http://www.example.com/a", "/a"),
+            ("http://www.example.com/a/", "/a/"),
+            (
+                "http://www.example.com/a/b?c=http://d.e/",
+                "/a/b?c=http://d.e/",
+            ),
+            (
+                "http://www.example.com/a/b?c=d&e=f#fragment",
+                "/a/b?c=d&e=f",
+            ),
+            ("example.com", "/"),
+            ("example.com/", "/"),
+            ("example.com/a", "/a"),
+            ("example.com/a/", "/a/"),
+            ("example.com/a/b?c=d&e=f#fragment", "/a/b?c=d&e=f"),
+            ("a", "/"),
+            ("a/", "/"),
+            ("/a", "/a"),
+            ("a/b", "/b"),
+            ("example.com?a", "/?a"),
+            ("example.com/a;b#c", "/a;b"),
+            ("//a/b/c", "/b/c"),
+            ("a#b/c", "/"),
+        ];
+
+        for (url, path) in test_cases {
+            assert_eq!(path, path_params_query(url), "{}", url);
+        }
+    }
+}
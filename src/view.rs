@@ -0,0 +1,228 @@
+use std::collections::BTreeSet;
+
+use crate::binary::{DecodeError, Reader};
+use crate::error::Error;
+use crate::nfa::{self, Accept, Automaton, Instruction};
+
+const NODE_LEN: usize = 13;
+const EDGE_LEN: usize = 5;
+const WILDCARD_LEN: usize = 4;
+const INSTRUCTION_LEN: usize = 5;
+
+/// A compiled Cylon matched directly from the bytes written by
+/// [`Cylon::to_bytes`](crate::Cylon::to_bytes), without decoding the NFA
+/// into memory. This is useful for sharing many precompiled files between
+/// processes, e.g. by memory-mapping a file of them.
+///
+/// The bytes are fully validated when the view is created, so corrupt or
+/// malicious data is rejected up front and matching never panics. Only
+/// matching is available from a view; use
+/// [`Cylon::from_bytes`](crate::Cylon::from_bytes) to read the metadata.
+///
+/// ```
+/// use cylon::{CylonBuilder, CylonView};
+///
+/// let bytes = CylonBuilder::new().disallow("/private").build().unwrap().to_bytes();
+/// let view = CylonView::new(&bytes).unwrap();
+/// assert!(view.allow("/index.html"));
+/// assert!(!view.allow("/private/index.html"));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct CylonView<'a> {
+    nodes: &'a [u8],
+    edges: &'a [u8],
+    wildcards: &'a [u8],
+    program: &'a [u8],
+}
+
+impl<'a> CylonView<'a> {
+    /// Validate the bytes and create a view over them.
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        let (mut r, _) = Reader::open(data)?;
+
+        let len = r.len(NODE_LEN)?;
+        let nodes = r.bytes(len * NODE_LEN)?;
+        let (mut edge_count, mut wildcard_count) = (0, 0);
+        for node in nodes.chunks(NODE_LEN) {
+            if Accept::from_byte(node[0]).is_none() {
+                return Err(DecodeError::Invalid("bad accept state").into());
+            }
+            let (edges_end, wildcards_end) = (read_u32(node, 5), read_u32(node, 9));
+            if edges_end < edge_count {
+                return Err(DecodeError::Invalid("bad edge table").into());
+            }
+            if wildcards_end < wildcard_count {
+                return Err(DecodeError::Invalid("bad wildcard table").into());
+            }
+            edge_count = edges_end;
+            wildcard_count = wildcards_end;
+        }
+
+        let edges = r.bytes(edge_count.saturating_mul(EDGE_LEN))?;
+        let wildcards = r.bytes(wildcard_count.saturating_mul(WILDCARD_LEN))?;
+        let in_range = edges
+            .chunks(EDGE_LEN)
+            .map(|edge| read_u32(edge, 1))
+            .chain(wildcards.chunks(WILDCARD_LEN).map(|w| read_u32(w, 0)))
+            .all(|state| state < len);
+        if !in_range {
+            return Err(DecodeError::Invalid("state out of range").into());
+        }
+
+        let program_len = r.len(INSTRUCTION_LEN)?;
+        let program = r.bytes(program_len * INSTRUCTION_LEN)?;
+        let mut instructions = Reader::new(program);
+        let instructions = (0..program_len)
+            .map(|_| Instruction::read(&mut instructions))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !nfa::valid_program(&instructions, len) {
+            return Err(DecodeError::Invalid("bad program").into());
+        }
+
+        Ok(Self {
+            nodes,
+            edges,
+            wildcards,
+            program,
+        })
+    }
+
+    /// Match whether the rules allow or disallow the target path.
+    pub fn allow<T: AsRef<[u8]>>(&self, path: T) -> bool {
+        nfa::matches(self, path.as_ref())
+    }
+
+    fn node_range(&self, state: usize, offset: usize) -> (usize, usize) {
+        let start = match state {
+            0 => 0,
+            _ => read_u32(&self.nodes[(state - 1) * NODE_LEN..], offset),
+        };
+        let end = read_u32(&self.nodes[state * NODE_LEN..], offset);
+        (start, end)
+    }
+}
+
+impl Automaton for CylonView<'_> {
+    fn node(&self, state: usize) -> Option<(Accept, usize)> {
+        let node = self.nodes.get(state * NODE_LEN..(state + 1) * NODE_LEN)?;
+        Some((Accept::from_byte(node[0])?, read_u32(node, 1)))
+    }
+
+    fn follow_edges(&self, state: usize, edge: u8, next: &mut BTreeSet<usize>) {
+        if state * NODE_LEN >= self.nodes.len() {
+            return;
+        }
+
+        let (start, end) = self.node_range(state, 5);
+        for e in self.edges[start * EDGE_LEN..end * EDGE_LEN].chunks(EDGE_LEN) {
+            if e[0] == edge {
+                next.insert(read_u32(e, 1));
+            }
+        }
+
+        let (start, end) = self.node_range(state, 9);
+        if start == end {
+            next.insert(state);
+        }
+        for w in self.wildcards[start * WILDCARD_LEN..end * WILDCARD_LEN].chunks(WILDCARD_LEN) {
+            next.insert(read_u32(w, 0));
+        }
+    }
+
+    fn instruction(&self, i: usize) -> Option<Instruction> {
+        let bytes = self
+            .program
+            .get(i * INSTRUCTION_LEN..(i + 1) * INSTRUCTION_LEN)?;
+        Instruction::read(&mut Reader::new(bytes)).ok()
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> usize {
+    let mut b = [0; 4];
+    b.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(b) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cylon, CylonBuilder};
+
+    #[test]
+    fn test_view_matches_cylon() {
+        let robots = CylonBuilder::new()
+            .disallow("/")
+            .allow("/fish*.php")
+            .disallow("/fish$")
+            .allow("/*.html")
+            .build()
+            .unwrap();
+        let policy = CylonBuilder::new().disallow("/logout").build().unwrap();
+        let cylons = vec![
+            robots.clone(),
+            robots.intersect(&policy),
+            policy.override_with(&robots),
+        ];
+
+        let paths = vec![
+            "",
+            "/",
+            "/fish",
+            "/fish.php",
+            "/fishheads/catfish.php?parameters",
+            "/logout",
+            "/index.html",
+            "/a/b/c.html?x=1",
+        ];
+
+        for cylon in cylons {
+            let bytes = cylon.to_bytes();
+            let view = CylonView::new(&bytes).unwrap();
+            for path in &paths {
+                assert_eq!(cylon.allow(path), view.allow(path), "{}", path);
+            }
+        }
+    }
+
+    #[test]
+    fn test_view_rejects_corrupt() {
+        let bytes = Cylon::compile(vec![]).to_bytes();
+        assert!(CylonView::new(&bytes).is_ok());
+        assert!(matches!(
+            CylonView::new(&bytes[..bytes.len() - 1]),
+            Err(Error::Decode(DecodeError::Truncated))
+        ));
+
+        // Flip every byte of the payload in turn, fixing up the checksum,
+        // to make sure validation never panics and matching never panics
+        // on whatever it accepts.
+        let bytes = CylonBuilder::new()
+            .disallow("/a*b$")
+            .allow("/a")
+            .build()
+            .unwrap()
+            .to_bytes();
+        for i in 16..bytes.len() {
+            for flip in [0x01, 0x80, 0xff] {
+                let mut corrupt = bytes.clone();
+                corrupt[i] ^= flip;
+                let corrupt = resign(&corrupt);
+                if let Ok(view) = CylonView::new(&corrupt) {
+                    view.allow("/aab");
+                    view.allow("/");
+                }
+            }
+        }
+    }
+
+    /// Recompute the checksum of encoded bytes.
+    fn resign(data: &[u8]) -> Vec<u8> {
+        let mut w = crate::binary::Writer::default();
+        for b in &data[16..] {
+            w.u8(*b);
+        }
+        let mut out = w.finish(0);
+        out[6..8].copy_from_slice(&data[6..8]);
+        out
+    }
+}
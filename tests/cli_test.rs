@@ -0,0 +1,49 @@
+#![cfg(feature = "cli")]
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const ROBOTS: &str = "User-agent: *\nDisallow: /private\n\nUser-agent: foobot\nDisallow: /\n";
+
+fn cylon(args: &[&str]) -> (Option<i32>, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cylon"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    // The process may exit without reading its input.
+    let _ = child.stdin.take().unwrap().write_all(ROBOTS.as_bytes());
+    let output = child.wait_with_output().unwrap();
+    (
+        output.status.code(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn test_dot() {
+    let (code, stdout) = cylon(&["dot", "-"]);
+    assert_eq!(Some(0), code);
+    assert!(stdout.starts_with("digraph cylon {"));
+    assert!(stdout.contains("[label=\"p\"]"));
+}
+
+#[test]
+fn test_check() {
+    let (code, stdout) = cylon(&["check", "-", "googlebot", "/index.html"]);
+    assert_eq!(Some(0), code);
+    assert_eq!("allow\t/index.html\n", stdout);
+
+    let (code, stdout) = cylon(&["check", "-", "foobot", "/index.html", "/private"]);
+    assert_eq!(Some(1), code);
+    assert_eq!("disallow\t/index.html\ndisallow\t/private\n", stdout);
+}
+
+#[test]
+fn test_usage() {
+    let (code, stdout) = cylon(&["check", "-"]);
+    assert_eq!(Some(2), code);
+    assert!(stdout.is_empty());
+}
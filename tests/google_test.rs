@@ -0,0 +1,713 @@
+//! A port of the tests for Google's robots.txt parser, from
+//! https://github.com/google/robotstxt/blob/master/robots_test.cc.
+//! Tests of Google's line-number reporting API have no equivalent here, so
+//! the line ending and byte order mark cases check behaviour instead.
+
+use cylon::Compiler;
+
+fn is_user_agent_allowed(robots: &str, user_agent: &str, url: &str) -> bool {
+    is_user_agent_allowed_bytes(robots.as_bytes(), user_agent, url)
+}
+
+fn is_user_agent_allowed_bytes(robots: &[u8], user_agent: &str, url: &str) -> bool {
+    let compiler = Compiler::new(user_agent);
+    let cylon = tokio_test::block_on(compiler.compile(robots)).unwrap();
+    cylon.allow_url(url)
+}
+
+/// Google matches a user agent without a product token against the `*`
+/// groups only. Cylon rejects it instead, since it could never match a
+/// group of its own.
+fn is_user_agent_rejected(user_agent: &str) -> bool {
+    let compiler = Compiler::new(user_agent);
+    tokio_test::block_on(compiler.compile(&b""[..])).is_err()
+}
+
+#[test]
+fn test_system() {
+    let robots = "user-agent: FooBot\n\
+                  disallow: /\n";
+    // Empty robots.txt: everything allowed.
+    assert!(is_user_agent_allowed("", "FooBot", ""));
+    // Empty user agent to be matched: rejected rather than allowing
+    // everything.
+    assert!(is_user_agent_rejected(""));
+    // Empty URL: the path is taken to be "/".
+    assert!(!is_user_agent_allowed(robots, "FooBot", ""));
+}
+
+#[test]
+fn test_line_syntax_line() {
+    let correct = "user-agent: FooBot\n\
+                   disallow: /\n";
+    let incorrect = "foo: FooBot\n\
+                     bar: /\n";
+    let incorrect_accepted = "user-agent FooBot\n\
+                              disallow /\n";
+    let url = "http://foo.bar/x/y";
+
+    assert!(!is_user_agent_allowed(correct, "FooBot", url));
+    assert!(is_user_agent_allowed(incorrect, "FooBot", url));
+    assert!(!is_user_agent_allowed(incorrect_accepted, "FooBot", url));
+}
+
+#[test]
+fn test_line_syntax_groups() {
+    let robots = "allow: /foo/bar/\n\
+                  \n\
+                  user-agent: FooBot\n\
+                  disallow: /\n\
+                  allow: /x/\n\
+                  user-agent: BarBot\n\
+                  disallow: /\n\
+                  allow: /y/\n\
+                  \n\
+                  \n\
+                  allow: /w/\n\
+                  user-agent: BazBot\n\
+                  \n\
+                  user-agent: FooBot\n\
+                  allow: /z/\n\
+                  disallow: /\n";
+    let url_w = "http://foo.bar/w/a";
+    let url_x = "http://foo.bar/x/b";
+    let url_y = "http://foo.bar/y/c";
+    let url_z = "http://foo.bar/z/d";
+    let url_foo = "http://foo.bar/foo/bar/";
+
+    assert!(is_user_agent_allowed(robots, "FooBot", url_x));
+    assert!(is_user_agent_allowed(robots, "FooBot", url_z));
+    assert!(!is_user_agent_allowed(robots, "FooBot", url_y));
+    assert!(is_user_agent_allowed(robots, "BarBot", url_y));
+    assert!(is_user_agent_allowed(robots, "BarBot", url_w));
+    assert!(!is_user_agent_allowed(robots, "BarBot", url_z));
+    assert!(is_user_agent_allowed(robots, "BazBot", url_z));
+
+    // Rules outside of groups are ignored.
+    assert!(!is_user_agent_allowed(robots, "FooBot", url_foo));
+    assert!(!is_user_agent_allowed(robots, "BarBot", url_foo));
+    assert!(!is_user_agent_allowed(robots, "BazBot", url_foo));
+}
+
+#[test]
+fn test_line_syntax_groups_other_rules() {
+    let url = "http://foo.bar/";
+
+    // Other lines don't end the user agents that start a group.
+    let robots = "User-agent: BarBot\n\
+                  Sitemap: https://foo.bar/sitemap\n\
+                  User-agent: *\n\
+                  Disallow: /\n";
+    assert!(!is_user_agent_allowed(robots, "FooBot", url));
+    assert!(!is_user_agent_allowed(robots, "BarBot", url));
+
+    let robots = "User-agent: FooBot\n\
+                  Invalid-Unknown-Line: unknown\n\
+                  User-agent: *\n\
+                  Disallow: /\n";
+    assert!(!is_user_agent_allowed(robots, "FooBot", url));
+    assert!(!is_user_agent_allowed(robots, "BarBot", url));
+}
+
+#[test]
+fn test_line_names_case_insensitive() {
+    let upper = "USER-AGENT: FooBot\n\
+                 ALLOW: /x/\n\
+                 DISALLOW: /\n";
+    let lower = "user-agent: FooBot\n\
+                 allow: /x/\n\
+                 disallow: /\n";
+    let camel = "uSeR-aGeNt: FooBot\n\
+                 AlLoW: /x/\n\
+                 dIsAlLoW: /\n";
+    let url_allowed = "http://foo.bar/x/y";
+    let url_disallowed = "http://foo.bar/a/b";
+
+    for robots in [upper, lower, camel] {
+        assert!(is_user_agent_allowed(robots, "FooBot", url_allowed));
+        assert!(!is_user_agent_allowed(robots, "FooBot", url_disallowed));
+    }
+}
+
+#[test]
+fn test_valid_user_agents_to_obey() {
+    assert!(Compiler::is_valid_user_agent("Foobot"));
+    assert!(Compiler::is_valid_user_agent("Foobot-Bar"));
+    assert!(Compiler::is_valid_user_agent("Foo_Bar"));
+
+    assert!(!Compiler::is_valid_user_agent(""));
+    assert!(!Compiler::is_valid_user_agent("ツ"));
+
+    assert!(!Compiler::is_valid_user_agent("Foobot*"));
+    assert!(!Compiler::is_valid_user_agent(" Foobot "));
+    assert!(!Compiler::is_valid_user_agent("Foobot/2.1"));
+
+    assert!(!Compiler::is_valid_user_agent("Foobot Bar"));
+}
+
+#[test]
+fn test_user_agent_value_case_insensitive() {
+    let upper = "User-Agent: FOO BAR\n\
+                 Allow: /x/\n\
+                 Disallow: /\n";
+    let lower = "User-Agent: foo bar\n\
+                 Allow: /x/\n\
+                 Disallow: /\n";
+    let camel = "User-Agent: FoO bAr\n\
+                 Allow: /x/\n\
+                 Disallow: /\n";
+    let url_allowed = "http://foo.bar/x/y";
+    let url_disallowed = "http://foo.bar/a/b";
+
+    for robots in [upper, lower, camel] {
+        for user_agent in ["Foo", "foo"] {
+            assert!(is_user_agent_allowed(robots, user_agent, url_allowed));
+            assert!(!is_user_agent_allowed(robots, user_agent, url_disallowed));
+        }
+    }
+}
+
+#[test]
+fn test_accept_user_agent_up_to_first_space() {
+    assert!(!Compiler::is_valid_user_agent("Foobot Bar"));
+    let robots = "User-Agent: *\n\
+                  Disallow: /\n\
+                  User-Agent: Foo Bar\n\
+                  Allow: /x/\n\
+                  Disallow: /\n";
+    let url = "http://foo.bar/x/y";
+
+    assert!(is_user_agent_allowed(robots, "Foo", url));
+    // Google matches "Foo Bar" against the `*` group. Cylon only takes the
+    // product token at the start of the user agent, like the `User-agent`
+    // lines, so it follows the `Foo` group instead.
+    assert!(is_user_agent_allowed(robots, "Foo Bar", url));
+}
+
+#[test]
+fn test_global_groups_secondary() {
+    let empty = "";
+    let global = "user-agent: *\n\
+                  allow: /\n\
+                  user-agent: FooBot\n\
+                  disallow: /\n";
+    let only_specific = "user-agent: FooBot\n\
+                         allow: /\n\
+                         user-agent: BarBot\n\
+                         disallow: /\n\
+                         user-agent: BazBot\n\
+                         disallow: /\n";
+    let url = "http://foo.bar/x/y";
+
+    assert!(is_user_agent_allowed(empty, "FooBot", url));
+    assert!(!is_user_agent_allowed(global, "FooBot", url));
+    assert!(is_user_agent_allowed(global, "BarBot", url));
+    assert!(is_user_agent_allowed(only_specific, "QuxBot", url));
+}
+
+#[test]
+fn test_allow_disallow_value_case_sensitive() {
+    let lowercase_url = "user-agent: FooBot\n\
+                         disallow: /x/\n";
+    let uppercase_url = "user-agent: FooBot\n\
+                         disallow: /X/\n";
+    let url = "http://foo.bar/x/y";
+
+    assert!(!is_user_agent_allowed(lowercase_url, "FooBot", url));
+    assert!(is_user_agent_allowed(uppercase_url, "FooBot", url));
+}
+
+#[test]
+fn test_longest_match() {
+    let url = "http://foo.bar/x/page.html";
+
+    let robots = "user-agent: FooBot\n\
+                  disallow: /x/page.html\n\
+                  allow: /x/\n";
+    assert!(!is_user_agent_allowed(robots, "FooBot", url));
+
+    let robots = "user-agent: FooBot\n\
+                  allow: /x/page.html\n\
+                  disallow: /x/\n";
+    assert!(is_user_agent_allowed(robots, "FooBot", url));
+    assert!(!is_user_agent_allowed(
+        robots,
+        "FooBot",
+        "http://foo.bar/x/"
+    ));
+
+    // Empty patterns match nothing.
+    let robots = "user-agent: FooBot\n\
+                  disallow: \n\
+                  allow: \n";
+    assert!(is_user_agent_allowed(robots, "FooBot", url));
+
+    let robots = "user-agent: FooBot\n\
+                  disallow: /\n\
+                  allow: /\n";
+    assert!(is_user_agent_allowed(robots, "FooBot", url));
+
+    let robots = "user-agent: FooBot\n\
+                  disallow: /x\n\
+                  allow: /x/\n";
+    assert!(!is_user_agent_allowed(robots, "FooBot", "http://foo.bar/x"));
+    assert!(is_user_agent_allowed(robots, "FooBot", "http://foo.bar/x/"));
+
+    // Allow wins a tie.
+    let robots = "user-agent: FooBot\n\
+                  disallow: /x/page.html\n\
+                  allow: /x/page.html\n";
+    assert!(is_user_agent_allowed(robots, "FooBot", url));
+
+    let robots = "user-agent: FooBot\n\
+                  allow: /page\n\
+                  disallow: /*.html\n";
+    assert!(!is_user_agent_allowed(
+        robots,
+        "FooBot",
+        "http://foo.bar/page.html"
+    ));
+    assert!(is_user_agent_allowed(
+        robots,
+        "FooBot",
+        "http://foo.bar/page"
+    ));
+
+    let robots = "user-agent: FooBot\n\
+                  allow: /x/page.\n\
+                  disallow: /*.html\n";
+    assert!(is_user_agent_allowed(robots, "FooBot", url));
+    assert!(!is_user_agent_allowed(
+        robots,
+        "FooBot",
+        "http://foo.bar/x/y.html"
+    ));
+
+    // Only the most specific group applies.
+    let robots = "User-agent: *\n\
+                  Disallow: /x/\n\
+                  User-agent: FooBot\n\
+                  Disallow: /y/\n";
+    assert!(is_user_agent_allowed(
+        robots,
+        "FooBot",
+        "http://foo.bar/x/page"
+    ));
+    assert!(!is_user_agent_allowed(
+        robots,
+        "FooBot",
+        "http://foo.bar/y/page"
+    ));
+}
+
+#[test]
+fn test_encoding() {
+    // Query strings are matched as they are.
+    let robots = "User-agent: FooBot\n\
+                  Disallow: /\n\
+                  Allow: /foo/bar?qux=taz&baz=http://foo.bar?tar&par\n";
+    assert!(is_user_agent_allowed(
+        robots,
+        "FooBot",
+        "http://foo.bar/foo/bar?qux=taz&baz=http://foo.bar?tar&par"
+    ));
+
+    // A 3 byte character in a pattern is percent-encoded, but URLs must
+    // already be.
+    let robots = "User-agent: FooBot\n\
+                  Disallow: /\n\
+                  Allow: /foo/bar/ツ\n";
+    assert!(is_user_agent_allowed(
+        robots,
+        "FooBot",
+        "http://foo.bar/foo/bar/%E3%83%84"
+    ));
+    assert!(!is_user_agent_allowed(
+        robots,
+        "FooBot",
+        "http://foo.bar/foo/bar/ツ"
+    ));
+
+    // An already encoded 3 byte character is left alone.
+    let robots = "User-agent: FooBot\n\
+                  Disallow: /\n\
+                  Allow: /foo/bar/%E3%83%84\n";
+    assert!(is_user_agent_allowed(
+        robots,
+        "FooBot",
+        "http://foo.bar/foo/bar/%E3%83%84"
+    ));
+    assert!(!is_user_agent_allowed(
+        robots,
+        "FooBot",
+        "http://foo.bar/foo/bar/ツ"
+    ));
+
+    // Encoded unreserved ASCII characters aren't decoded. RFC 3986 forbids
+    // them, so they should not be relied on.
+    let robots = "User-agent: FooBot\n\
+                  Disallow: /\n\
+                  Allow: /foo/bar/%62%61%7A\n";
+    assert!(!is_user_agent_allowed(
+        robots,
+        "FooBot",
+        "http://foo.bar/foo/bar/baz"
+    ));
+    assert!(is_user_agent_allowed(
+        robots,
+        "FooBot",
+        "http://foo.bar/foo/bar/%62%61%7A"
+    ));
+}
+
+#[test]
+fn test_special_characters() {
+    let robots = "User-agent: FooBot\n\
+                  Disallow: /foo/bar/quz\n\
+                  Allow: /foo/*/qux\n";
+    let test_cases = vec![
+        ("http://foo.bar/foo/bar/quz", false),
+        ("http://foo.bar/foo/quz", true),
+        ("http://foo.bar/foo//quz", true),
+        ("http://foo.bar/foo/bax/quz", true),
+    ];
+    for (url, expected) in test_cases {
+        assert_eq!(
+            expected,
+            is_user_agent_allowed(robots, "FooBot", url),
+            "{}",
+            url
+        );
+    }
+
+    let robots = "User-agent: FooBot\n\
+                  Disallow: /foo/bar$\n\
+                  Allow: /foo/bar/qux\n";
+    let test_cases = vec![
+        ("http://foo.bar/foo/bar", false),
+        ("http://foo.bar/foo/bar/qux", true),
+        ("http://foo.bar/foo/bar/", true),
+        ("http://foo.bar/foo/bar/baz", true),
+    ];
+    for (url, expected) in test_cases {
+        assert_eq!(
+            expected,
+            is_user_agent_allowed(robots, "FooBot", url),
+            "{}",
+            url
+        );
+    }
+
+    let robots = "User-agent: FooBot\n\
+                  # Disallow: /\n\
+                  Disallow: /foo/quz#qux\n\
+                  Allow: /\n";
+    assert!(is_user_agent_allowed(
+        robots,
+        "FooBot",
+        "http://foo.bar/foo/bar"
+    ));
+    assert!(!is_user_agent_allowed(
+        robots,
+        "FooBot",
+        "http://foo.bar/foo/quz"
+    ));
+}
+
+#[test]
+fn test_index_html_is_directory() {
+    let robots = "User-Agent: *\n\
+                  Allow: /allowed-slash/index.html\n\
+                  Disallow: /\n";
+    // Allowing index.html allows its directory too.
+    assert!(is_user_agent_allowed(
+        robots,
+        "foobot",
+        "http://foo.com/allowed-slash/"
+    ));
+    // Doesn't match exactly.
+    assert!(!is_user_agent_allowed(
+        robots,
+        "foobot",
+        "http://foo.com/allowed-slash/index.htm"
+    ));
+    // Exact match.
+    assert!(is_user_agent_allowed(
+        robots,
+        "foobot",
+        "http://foo.com/allowed-slash/index.html"
+    ));
+    assert!(!is_user_agent_allowed(
+        robots,
+        "foobot",
+        "http://foo.com/anyother-url"
+    ));
+}
+
+#[test]
+fn test_line_too_long() {
+    let eol_len = "\n".len();
+    let max_line_len = 2083 * 8;
+    let allow = "allow: ";
+    let disallow = "disallow: ";
+
+    // A disallow pattern matches the URL after being cut off.
+    let mut robots = String::from("user-agent: FooBot\n");
+    let mut longline = String::from("/x/");
+    let max_length = max_line_len - longline.len() - disallow.len() + eol_len;
+    while longline.len() < max_length {
+        longline.push('a');
+    }
+    robots.push_str(&format!("{}{}/qux\n", disallow, longline));
+
+    // Matches nothing, so the URL is allowed.
+    assert!(is_user_agent_allowed(
+        &robots,
+        "FooBot",
+        "http://foo.bar/fux"
+    ));
+    // Matches the cut off disallow rule.
+    assert!(!is_user_agent_allowed(
+        &robots,
+        "FooBot",
+        &format!("http://foo.bar{}/fux", longline)
+    ));
+
+    let mut robots = String::from("user-agent: FooBot\ndisallow: /\n");
+    let mut longline_a = String::from("/x/");
+    let mut longline_b = String::from("/x/");
+    let max_length = max_line_len - longline_a.len() - allow.len() + eol_len;
+    while longline_a.len() < max_length {
+        longline_a.push('a');
+        longline_b.push('b');
+    }
+    robots.push_str(&format!("{}{}/qux\n", allow, longline_a));
+    robots.push_str(&format!("{}{}/qux\n", allow, longline_b));
+
+    // The URL matches the disallow rule.
+    assert!(!is_user_agent_allowed(&robots, "FooBot", "http://foo.bar/"));
+    // Matches the allow rule exactly.
+    assert!(is_user_agent_allowed(
+        &robots,
+        "FooBot",
+        &format!("http://foo.bar{}/qux", longline_a)
+    ));
+    // Matches the cut off allow rule.
+    assert!(is_user_agent_allowed(
+        &robots,
+        "FooBot",
+        &format!("http://foo.bar{}/fux", longline_b)
+    ));
+}
+
+/// The examples from Google's documentation of URL matching and rule
+/// precedence.
+#[test]
+fn test_documentation_checks() {
+    let fish = vec![
+        ("http://foo.bar/bar", false),
+        ("http://foo.bar/fish", true),
+        ("http://foo.bar/fish.html", true),
+        ("http://foo.bar/fish/salmon.html", true),
+        ("http://foo.bar/fishheads", true),
+        ("http://foo.bar/fishheads/yummy.html", true),
+        ("http://foo.bar/fish.html?id=anything", true),
+        ("http://foo.bar/Fish.asp", false),
+        ("http://foo.bar/catfish", false),
+        ("http://foo.bar/?id=fish", false),
+    ];
+    let fish_slash = vec![
+        ("http://foo.bar/bar", false),
+        ("http://foo.bar/fish/", true),
+        ("http://foo.bar/fish/salmon", true),
+        ("http://foo.bar/fish/?salmon", true),
+        ("http://foo.bar/fish/salmon.html", true),
+        ("http://foo.bar/fish/?id=anything", true),
+        ("http://foo.bar/fish", false),
+        ("http://foo.bar/fish.html", false),
+        ("http://foo.bar/Fish/Salmon.html", false),
+    ];
+    let php = vec![
+        ("http://foo.bar/bar", false),
+        ("http://foo.bar/filename.php", true),
+        ("http://foo.bar/folder/filename.php", true),
+        ("http://foo.bar/folder/filename.php?parameters", true),
+        ("http://foo.bar//folder/any.php.file.html", true),
+        ("http://foo.bar/filename.php/", true),
+        ("http://foo.bar/index?f=filename.php/", true),
+        ("http://foo.bar/php/", false),
+        ("http://foo.bar/index?php", false),
+        ("http://foo.bar/windows.PHP", false),
+    ];
+    let php_end = vec![
+        ("http://foo.bar/bar", false),
+        ("http://foo.bar/filename.php", true),
+        ("http://foo.bar/folder/filename.php", true),
+        ("http://foo.bar/filename.php?parameters", false),
+        ("http://foo.bar/filename.php/", false),
+        ("http://foo.bar/filename.php5", false),
+        ("http://foo.bar/php/", false),
+        ("http://foo.bar/filename?php", false),
+        ("http://foo.bar/aaaphpaaa", false),
+        ("http://foo.bar//windows.PHP", false),
+    ];
+    let fish_php = vec![
+        ("http://foo.bar/bar", false),
+        ("http://foo.bar/fish.php", true),
+        ("http://foo.bar/fishheads/catfish.php?parameters", true),
+        ("http://foo.bar/Fish.PHP", false),
+    ];
+    let test_cases = vec![
+        ("/fish", fish.clone()),
+        // "/fish*" is equivalent to "/fish".
+        ("/fish*", fish),
+        // "/fish/" is not.
+        ("/fish/", fish_slash),
+        ("/*.php", php),
+        ("/*.php$", php_end),
+        ("/fish*.php", fish_php),
+    ];
+
+    for (pattern, urls) in test_cases {
+        let robots = format!("user-agent: FooBot\ndisallow: /\nallow: {}\n", pattern);
+        for (url, expected) in urls {
+            assert_eq!(
+                expected,
+                is_user_agent_allowed(&robots, "FooBot", url),
+                "{} {}",
+                pattern,
+                url
+            );
+        }
+    }
+
+    // Order of precedence for group member rules.
+    let test_cases = vec![
+        ("allow: /p\ndisallow: /", "http://example.com/page", true),
+        (
+            "allow: /folder\ndisallow: /folder",
+            "http://example.com/folder/page",
+            true,
+        ),
+        (
+            "allow: /page\ndisallow: /*.htm",
+            "http://example.com/page.htm",
+            false,
+        ),
+        ("allow: /$\ndisallow: /", "http://example.com/", true),
+        (
+            "allow: /$\ndisallow: /",
+            "http://example.com/page.html",
+            false,
+        ),
+    ];
+    for (rules, url, expected) in test_cases {
+        let robots = format!("user-agent: FooBot\n{}\n", rules);
+        assert_eq!(
+            expected,
+            is_user_agent_allowed(&robots, "FooBot", url),
+            "{} {}",
+            rules,
+            url
+        );
+    }
+}
+
+#[test]
+fn test_line_endings() {
+    let test_cases = vec![
+        "User-Agent: foo\nAllow: /some/path\nUser-Agent: bar\n\n\nDisallow: /\n",
+        "User-Agent: foo\r\nAllow: /some/path\r\nUser-Agent: bar\r\n\r\n\r\nDisallow: /\r\n",
+        "User-Agent: foo\rAllow: /some/path\rUser-Agent: bar\r\r\rDisallow: /\r",
+        "User-Agent: foo\nAllow: /some/path\nUser-Agent: bar\n\n\nDisallow: /",
+        "User-Agent: foo\nAllow: /some/path\r\nUser-Agent: bar\n\r\n\nDisallow: /",
+    ];
+
+    for robots in test_cases {
+        assert!(
+            is_user_agent_allowed(robots, "foo", "http://foo.bar/some/path"),
+            "{:?}",
+            robots
+        );
+        assert!(
+            is_user_agent_allowed(robots, "foo", "http://foo.bar/other"),
+            "{:?}",
+            robots
+        );
+        assert!(
+            !is_user_agent_allowed(robots, "bar", "http://foo.bar/some/path"),
+            "{:?}",
+            robots
+        );
+    }
+}
+
+#[test]
+fn test_utf8_byte_order_mark_is_skipped() {
+    let url = "http://foo.bar/AnyValue";
+    let test_cases: Vec<(&[u8], bool)> = vec![
+        // A full, or partial, BOM is skipped.
+        (b"\xEF\xBB\xBFUser-Agent: foo\nDisallow: /AnyValue\n", false),
+        (b"\xEF\xBBUser-Agent: foo\nDisallow: /AnyValue\n", false),
+        (b"\xEFUser-Agent: foo\nDisallow: /AnyValue\n", false),
+        // A broken BOM makes the first line invalid, leaving the rule
+        // outside of any group.
+        (b"\xEF\x11\xBFUser-Agent: foo\nDisallow: /AnyValue\n", true),
+        // A BOM elsewhere in the file makes its line invalid.
+        (b"User-Agent: foo\n\xEF\xBB\xBFDisallow: /AnyValue\n", true),
+    ];
+
+    for (robots, expected) in test_cases {
+        assert_eq!(
+            expected,
+            is_user_agent_allowed_bytes(robots, "foo", url),
+            "{:?}",
+            robots
+        );
+    }
+}
+
+#[test]
+fn test_sitemap() {
+    let sitemap = "http://foo.bar/sitemap.xml";
+    let test_cases = vec![
+        format!(
+            "User-Agent: foo\nAllow: /some/path\nUser-Agent: bar\n\n\nSitemap: {}\n",
+            sitemap
+        ),
+        // A sitemap may appear anywhere in the file.
+        format!(
+            "Sitemap: {}\nUser-Agent: foo\nAllow: /some/path\nUser-Agent: bar\n\n\n",
+            sitemap
+        ),
+    ];
+
+    for robots in test_cases {
+        let compiler = Compiler::new("foo");
+        let cylon = tokio_test::block_on(compiler.compile(robots.as_bytes())).unwrap();
+        assert_eq!(&[sitemap.to_string()][..], cylon.sitemaps());
+    }
+}
+
+// Not in Google's tests, but RFC 9309 implicitly allows /robots.txt, so a
+// crawler that is disallowed everything can still fetch the file again.
+#[test]
+fn test_robots_txt_implicitly_allowed() {
+    let robots = "user-agent: FooBot\n\
+                  disallow: /\n\
+                  disallow: /robots.txt$\n";
+
+    assert!(is_user_agent_allowed(
+        robots,
+        "FooBot",
+        "http://foo.bar/robots.txt"
+    ));
+    assert!(is_user_agent_allowed(robots, "FooBot", "/robots.txt"));
+    assert!(!is_user_agent_allowed(robots, "FooBot", "/robots.txt?x"));
+    assert!(!is_user_agent_allowed(robots, "FooBot", "/robots.txt/"));
+    assert!(!is_user_agent_allowed(robots, "FooBot", "/x/robots.txt"));
+
+    let compiler = Compiler::new("FooBot").with_robots_txt_allowed(false);
+    let cylon = tokio_test::block_on(compiler.compile(robots.as_bytes())).unwrap();
+    assert!(!cylon.allow_url("http://foo.bar/robots.txt"));
+}
@@ -0,0 +1,51 @@
+use cylon::CylonBuilder;
+use proptest::prelude::*;
+
+/// Patterns that a CylonBuilder accepts, over a small alphabet so that
+/// random rules and paths overlap often.
+fn pattern() -> impl Strategy<Value = String> {
+    "[/*][/ab.*]{0,6}[$]?"
+}
+
+fn path() -> impl Strategy<Value = String> {
+    "(/[/ab.$]{0,8})?"
+}
+
+fn rules() -> impl Strategy<Value = Vec<(bool, String)>> {
+    prop::collection::vec((any::<bool>(), pattern()), 0..6)
+}
+
+fn builder(rules: &[(bool, String)]) -> CylonBuilder {
+    rules.iter().fold(
+        CylonBuilder::new(),
+        |builder, (allow, pattern)| match allow {
+            true => builder.allow(pattern),
+            false => builder.disallow(pattern),
+        },
+    )
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(2000))]
+
+    /// The NFA makes the same decision as checking every rule in turn.
+    #[test]
+    fn test_naive_matches_cylon(rules in rules(), paths in prop::collection::vec(path(), 1..10)) {
+        let builder = builder(&rules);
+        let cylon = builder.build().unwrap();
+        let naive = builder.build_naive().unwrap();
+        for path in paths {
+            prop_assert_eq!(naive.allow(&path), cylon.allow(&path), "{:?} {:?}", rules, path);
+        }
+    }
+
+    /// The order of the rules never matters.
+    #[test]
+    fn test_rule_order(rules in rules(), path in path()) {
+        let mut reversed = rules.clone();
+        reversed.reverse();
+        let cylon = builder(&rules).build().unwrap();
+        let reversed = builder(&reversed).build().unwrap();
+        prop_assert_eq!(cylon.allow(&path), reversed.allow(&path));
+    }
+}
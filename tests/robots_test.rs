@@ -1,4 +1,5 @@
-use cylon::{self, Compiler};
+use cylon::{self, CaseFolding, Compiler};
+use regex::Regex;
 
 struct TestCases {
     name: &'static str,
@@ -165,9 +166,10 @@ static TEST_CASES: &[TestCases] = &[
         User-agent: fake bot
         Disallow: /
         "#,
+        // Only the product token before the space is matched.
         ua_token: "fake",
         input_path: "/foo",
-        expected_result: true,
+        expected_result: false,
     },
     TestCases {
         name: "SpaceInUA2",
@@ -185,6 +187,7 @@ static TEST_CASES: &[TestCases] = &[
         User-agent: fake bot
         Disallow: /
         "#,
+        // Both sides are cut down to the product token "fake".
         ua_token: "fake bot",
         input_path: "/foo",
         expected_result: false,
@@ -392,21 +395,22 @@ static TEST_CASES: &[TestCases] = &[
         User-agent: fakebot
         Disallow: /foo/bar/ツ
         "#,
+        // Non-ASCII characters in patterns are percent-encoded.
         ua_token: "fakeBot",
         input_path: "/foo/bar/%E3%83%84",
-        expected_result: true,
+        expected_result: false,
     },
     // https://datatracker.ietf.org/doc/html/draft-koster-rep#section-2.2.2
-    // TestCases {
-    //     name: "PersentEncode2",
-    //     robots: r#"
-    //     User-agent: fakebot
-    //     Disallow: /foo/bar/ツ
-    //     "#,
-    //     ua_token: "fakeBot",
-    //     input_path: "/foo/bar/ツ",
-    //     expected_result: true,
-    // },
+    TestCases {
+        name: "PercentEncode2",
+        robots: r#"
+        User-agent: fakebot
+        Disallow: /foo/bar/ツ
+        "#,
+        ua_token: "fakeBot",
+        input_path: "/foo/bar/ツ",
+        expected_result: true,
+    },
     TestCases {
         name: "PercentEncode3",
         robots: r#"
@@ -489,6 +493,37 @@ static TEST_CASES: &[TestCases] = &[
         input_path: "/foo/test",
         expected_result: true,
     },
+    TestCases {
+        name: "RobotsTxtAllowed",
+        robots: r#"
+        User-agent: *
+        Disallow: /
+        "#,
+        ua_token: "fakeBot",
+        input_path: "/robots.txt",
+        expected_result: true,
+    },
+    TestCases {
+        name: "RobotsTxtAllowed2",
+        robots: r#"
+        User-agent: *
+        Disallow: /robots.txt
+        Disallow: /*.txt$
+        "#,
+        ua_token: "fakeBot",
+        input_path: "/robots.txt",
+        expected_result: true,
+    },
+    TestCases {
+        name: "RobotsTxtAllowed3",
+        robots: r#"
+        User-agent: *
+        Disallow: /robots
+        "#,
+        ua_token: "fakeBot",
+        input_path: "/robots.txt.bak",
+        expected_result: false,
+    },
 ];
 
 /// Test result from allow function.
@@ -522,3 +557,56 @@ fn test_robots() {
         nb_tests_failed
     );
 }
+
+/// The exported regular expressions must make the same decision as the
+/// Cylon for every test case.
+#[test]
+fn test_regexes() {
+    for test in TEST_CASES.iter() {
+        let compiler = Compiler::new(test.ua_token);
+        let cylon = tokio_test::block_on(compiler.compile(test.robots.as_bytes())).unwrap();
+        let regexes =
+            tokio_test::block_on(compiler.compile_regexes(test.robots.as_bytes())).unwrap();
+
+        let path = if test.input_path.is_empty() {
+            "/"
+        } else {
+            test.input_path
+        };
+        let allow = regexes
+            .iter()
+            .find(|rule| Regex::new(&rule.pattern).unwrap().is_match(path))
+            .is_none_or(|rule| rule.allow);
+        assert_eq!(cylon.allow(test.input_path), allow, "{}", test.name);
+    }
+}
+
+/// Exported regular expressions must also agree with the Cylon when case
+/// is folded.
+#[test]
+fn test_regexes_case_folding() {
+    let robots = "User-agent: *\nDisallow: /Admin\nAllow: /admin/*.HTML$\nDisallow: /%C3%84rger\nAllow: /K\n";
+    let paths = [
+        "/admin",
+        "/ADMIN/x.html",
+        "/admin/x.htm",
+        "/%c3%a4RGER",
+        "/%C3%84rger",
+        "/%E2%84%AA",
+        "/k",
+        "/",
+    ];
+
+    for case_folding in [CaseFolding::None, CaseFolding::Ascii, CaseFolding::Unicode] {
+        let compiler = Compiler::new("foobot").with_case_folding(case_folding);
+        let cylon = tokio_test::block_on(compiler.compile(robots.as_bytes())).unwrap();
+        let regexes = tokio_test::block_on(compiler.compile_regexes(robots.as_bytes())).unwrap();
+        for path in paths {
+            let allow = regexes
+                .iter()
+                .find(|rule| Regex::new(&rule.pattern).unwrap().is_match(path))
+                .is_none_or(|rule| rule.allow);
+            assert_eq!(cylon.allow(path), allow, "{:?} {}", case_folding, path);
+        }
+    }
+}